@@ -0,0 +1,170 @@
+//! addresses coins are paid to: the hash160 of a compressed public key,
+//! written in Base58Check after a version byte so that a mistyped address
+//! fails its checksum instead of paying a stranger
+use crate::secp256k1::PubKey;
+use crate::Error;
+use bitcoin_hashes::{hash160, Hash};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// version byte of the addresses of bloc, which start with a `B`
+pub const ADDRESS_VERSION: u8 = 0x19;
+
+/// the hash160 of the public key owning the coins paid to it
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct Address(#[serde(with = "serde_address")] [u8; 20]);
+
+impl Address {
+    pub fn new(hash: &[u8; 20]) -> Self {
+        Self(*hash)
+    }
+
+    /// the all-zero address, which no key hashes to
+    pub fn null() -> Self {
+        Self([0; 20])
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.0 == [0; 20]
+    }
+
+    /// whether `key` hashes to this address
+    pub fn is_owned_by(&self, key: &PubKey) -> bool {
+        *self == Self::from(key)
+    }
+}
+
+impl From<&PubKey> for Address {
+    fn from(key: &PubKey) -> Self {
+        Self(hash160::Hash::hash(key.as_ref()).into_inner())
+    }
+}
+
+impl From<PubKey> for Address {
+    fn from(key: PubKey) -> Self {
+        Self::from(&key)
+    }
+}
+
+impl AsRef<[u8]> for Address {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut data = [0u8; 21];
+        data[0] = ADDRESS_VERSION;
+        data[1..].copy_from_slice(&self.0);
+        f.write_str(&bs58::encode(data).with_check().into_string())
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+    /// parse the Base58Check form, rejecting a wrong checksum, length or
+    /// version
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let data = bs58::decode(s)
+            .with_check(None)
+            .into_vec()
+            .map_err(|e| Error::Address(e.to_string()))?;
+        match data.split_first() {
+            Some((&ADDRESS_VERSION, hash)) if hash.len() == 20 => {
+                let mut bytes = [0u8; 20];
+                bytes.copy_from_slice(hash);
+                Ok(Self(bytes))
+            }
+            Some((&ADDRESS_VERSION, hash)) => Err(Error::Address(format!(
+                "{} bytes of hash, expected 20",
+                hash.len()
+            ))),
+            Some((version, _)) => Err(Error::Address(format!("unknown version {}", version))),
+            None => Err(Error::Address("empty address".to_owned())),
+        }
+    }
+}
+
+// human-readable formats carry addresses in Base58Check, binary ones as
+// the raw hash
+mod serde_address {
+    use super::*;
+    pub fn serialize<S>(hash: &[u8; 20], s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if s.is_human_readable() {
+            return s.serialize_str(&Address(*hash).to_string());
+        }
+        hash.serialize(s)
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<[u8; 20], D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if d.is_human_readable() {
+            let s = String::deserialize(d)?;
+            return Address::from_str(&s)
+                .map(|address| address.0)
+                .map_err(de::Error::custom);
+        }
+        <[u8; 20]>::deserialize(d)
+    }
+}
+
+#[cfg(test)]
+mod test_address {
+    use super::*;
+    use crate::secp256k1::Secp256K1;
+
+    #[test]
+    fn test_round_trip() {
+        let (_, key) = Secp256K1::new().gen_keypair();
+        let address = Address::from(&key);
+        assert!(address.is_owned_by(&key));
+        let text = address.to_string();
+        assert!(text.starts_with('B'), "{}", text);
+        assert_eq!(text.parse::<Address>().unwrap(), address);
+
+        let json = serde_json::to_string(&address).unwrap();
+        assert_eq!(json, format!("{:?}", text));
+        assert_eq!(serde_json::from_str::<Address>(&json).unwrap(), address);
+        let bytes = bincode::serialize(&address).unwrap();
+        assert_eq!(bytes.len(), 20);
+        assert_eq!(bincode::deserialize::<Address>(&bytes).unwrap(), address);
+    }
+
+    #[test]
+    fn test_strict_parse() {
+        let (_, key) = Secp256K1::new().gen_keypair();
+        let text = Address::from(&key).to_string();
+
+        // any single mistyped character fails the checksum
+        let alphabet = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+        for (index, original) in text.char_indices() {
+            let typo = alphabet.chars().find(|c| *c != original).unwrap();
+            let mut mistyped = text.clone();
+            mistyped.replace_range(index..index + 1, &typo.to_string());
+            assert!(
+                matches!(mistyped.parse::<Address>(), Err(Error::Address(_))),
+                "{}",
+                mistyped
+            );
+        }
+
+        // a public key in hex or a checksummed payload of another version
+        let hex = crate::secp256k1::ToHex::to_hex(&key);
+        assert!(hex.parse::<Address>().is_err());
+        let other = bs58::encode([0u8; 21]).with_check().into_string();
+        assert!(other.parse::<Address>().is_err());
+        let short = bs58::encode([ADDRESS_VERSION; 20])
+            .with_check()
+            .into_string();
+        assert!(short.parse::<Address>().is_err());
+        assert!("".parse::<Address>().is_err());
+        assert!("B0OIl".parse::<Address>().is_err());
+    }
+}
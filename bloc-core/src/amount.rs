@@ -0,0 +1,235 @@
+use crate::Error;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// fixed-point quantity of coins, counted in indivisible base units
+///
+/// one coin is `10^DECIMALS` base units, so arithmetic is exact and
+/// every operation that could wrap is checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u64);
+
+impl Amount {
+    /// number of decimals used by `Display` and `FromStr`
+    pub const DECIMALS: u32 = 8;
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    pub const fn from_base_units(units: u64) -> Self {
+        Self(units)
+    }
+
+    pub const fn base_units(&self) -> u64 {
+        self.0
+    }
+
+    /// whole coins with the default number of decimals
+    pub fn from_coins(coins: u64) -> Result<Self, Error> {
+        Self::from_coins_with(coins, Self::DECIMALS)
+    }
+
+    pub fn from_coins_with(coins: u64, decimals: u32) -> Result<Self, Error> {
+        coins
+            .checked_mul(Self::unit(decimals)?)
+            .map(Self)
+            .ok_or(Error::AmountOverflow)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, rhs: Amount) -> Result<Self, Error> {
+        self.0
+            .checked_add(rhs.0)
+            .map(Self)
+            .ok_or(Error::AmountOverflow)
+    }
+
+    pub fn checked_sub(self, rhs: Amount) -> Result<Self, Error> {
+        self.0
+            .checked_sub(rhs.0)
+            .map(Self)
+            .ok_or(Error::AmountOverflow)
+    }
+
+    pub fn checked_mul(self, rhs: u64) -> Result<Self, Error> {
+        self.0
+            .checked_mul(rhs)
+            .map(Self)
+            .ok_or(Error::AmountOverflow)
+    }
+
+    /// sum of amounts, failing on overflow
+    pub fn checked_sum<'a, I: IntoIterator<Item = &'a Amount>>(iter: I) -> Result<Self, Error> {
+        iter.into_iter()
+            .try_fold(Amount::ZERO, |acc, ele| acc.checked_add(*ele))
+    }
+
+    pub fn saturating_sub(self, rhs: Amount) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+
+    /// parse a decimal string like `"12.345"` counting `decimals` digits
+    /// after the point as base units
+    pub fn parse_with(s: &str, decimals: u32) -> Result<Self, Error> {
+        let invalid = || Error::InvalidAmount(s.to_owned());
+        let unit = Self::unit(decimals)?;
+        let (int, frac) = match s.split_once('.') {
+            Some((int, frac)) => (int, frac),
+            None => (s, ""),
+        };
+        if int.is_empty() && frac.is_empty()
+            || !int.bytes().all(|ch| ch.is_ascii_digit())
+            || !frac.bytes().all(|ch| ch.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        if frac.len() > decimals as usize {
+            return Err(invalid());
+        }
+        let int = match int {
+            "" => 0,
+            int => int.parse::<u64>().map_err(|_| Error::AmountOverflow)?,
+        };
+        let frac = match frac {
+            "" => 0,
+            frac => {
+                frac.parse::<u64>().map_err(|_| invalid())?
+                    * 10u64.pow(decimals - frac.len() as u32)
+            }
+        };
+        int.checked_mul(unit)
+            .and_then(|units| units.checked_add(frac))
+            .map(Self)
+            .ok_or(Error::AmountOverflow)
+    }
+
+    /// format with `decimals` digits after the point, trailing zeros trimmed
+    pub fn format_with(&self, decimals: u32) -> String {
+        let unit = match Self::unit(decimals) {
+            Ok(unit) => unit,
+            Err(_) => return self.0.to_string(),
+        };
+        let int = self.0 / unit;
+        let frac = self.0 % unit;
+        if frac == 0 {
+            return int.to_string();
+        }
+        let frac = format!("{:0width$}", frac, width = decimals as usize);
+        format!("{}.{}", int, frac.trim_end_matches('0'))
+    }
+
+    fn unit(decimals: u32) -> Result<u64, Error> {
+        10u64.checked_pow(decimals).ok_or(Error::AmountOverflow)
+    }
+}
+
+impl fmt::Display for Amount {
+    /// honours an explicit precision, e.g. `{:.3}`, by truncating or padding
+    /// the fraction
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let formatted = self.format_with(Self::DECIMALS);
+        match f.precision() {
+            None => f.write_str(&formatted),
+            Some(precision) => {
+                let (int, frac) = formatted.split_once('.').unwrap_or((&formatted, ""));
+                if precision == 0 {
+                    return f.write_str(int);
+                }
+                let mut frac = frac.to_owned();
+                frac.truncate(precision);
+                write!(f, "{}.{:0<width$}", int, frac, width = precision)
+            }
+        }
+    }
+}
+
+impl FromStr for Amount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(s.trim(), Self::DECIMALS)
+    }
+}
+
+/// human-readable formats carry the decimal string, binary ones the base units
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        if s.is_human_readable() {
+            s.serialize_str(&self.to_string())
+        } else {
+            s.serialize_u64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        if d.is_human_readable() {
+            let s = String::deserialize(d)?;
+            s.parse().map_err(de::Error::custom)
+        } else {
+            u64::deserialize(d).map(Self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_amount {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "12.345".parse::<Amount>(),
+            Ok(Amount::from_base_units(1_234_500_000))
+        );
+        assert_eq!("7".parse::<Amount>(), Amount::from_coins(7));
+        assert_eq!(
+            ".5".parse::<Amount>(),
+            Ok(Amount::from_base_units(50_000_000))
+        );
+        assert_eq!(
+            Amount::parse_with("1.5", 2),
+            Ok(Amount::from_base_units(150))
+        );
+        assert!("".parse::<Amount>().is_err());
+        assert!("-1".parse::<Amount>().is_err());
+        assert!("1.2.3".parse::<Amount>().is_err());
+        assert!("0.000000001".parse::<Amount>().is_err());
+        assert_eq!(
+            "184467440737.09551616".parse::<Amount>(),
+            Err(Error::AmountOverflow)
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let amount = Amount::from_base_units(1_234_500_000);
+        assert_eq!(amount.to_string(), "12.345");
+        assert_eq!(format!("{:.1}", amount), "12.3");
+        assert_eq!(format!("{:.5}", amount), "12.34500");
+        assert_eq!(format!("{:.0}", amount), "12");
+        assert_eq!(Amount::from_coins(100).unwrap().to_string(), "100");
+        assert_eq!(Amount::from_base_units(150).format_with(2), "1.5");
+    }
+
+    #[test]
+    fn test_checked() {
+        let one = Amount::from_coins(1).unwrap();
+        assert_eq!(Amount::MAX.checked_add(one), Err(Error::AmountOverflow));
+        assert_eq!(Amount::ZERO.checked_sub(one), Err(Error::AmountOverflow));
+        assert_eq!(one.checked_mul(3), Amount::from_coins(3));
+        assert_eq!(Amount::checked_sum(&[one, one, one]), Amount::from_coins(3));
+    }
+
+    #[test]
+    fn test_serde() {
+        let amount: Amount = "0.1".parse().unwrap();
+        let bytes = bincode::serialize(&amount).unwrap();
+        assert_eq!(bytes, 10_000_000u64.to_le_bytes());
+        assert_eq!(bincode::deserialize::<Amount>(&bytes).unwrap(), amount);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::merkle::{self, MerkleProof};
+use crate::pow::{Target, DEFAULT_BITS};
+use crate::transaction::Transaction;
+use crate::utxo::UtxoTransaction;
+use crate::Error;
+use bitcoin_hashes::{sha256, sha256::Hash as Sha256, Hash};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// version of the serialized block and chain format
+pub const FORMAT_VERSION: u32 = 3;
+/// version of the block header, part of every block hash: unlike
+/// `FORMAT_VERSION` it only changes along with the consensus rules, and
+/// keeps the value the headers were first hashed with
+pub const HEADER_VERSION: u32 = 3;
+
+/// the fields hashed by the proof of work, committing to the transactions
+/// through their merkle root
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_hash: Sha256,
+    pub merkle_root: Sha256,
+    pub timestamp: DateTime<Utc>,
+    /// compact proof of work target, see `Target::from_bits`
+    pub bits: u32,
+    pub nonce: u64,
+}
+
+impl BlockHeader {
+    pub fn hash(&self) -> Sha256 {
+        sha256::Hash::hash(&bincode::serialize(self).expect("failed to serialize block header"))
+    }
+}
+
+/// represent a Block that pushed to BlockChain
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Block {
+    pub timestamp: DateTime<Utc>,
+    pub data: Vec<Transaction>,
+    /// transactions of a `ChainModel::Utxo` chain, the coinbase first
+    #[serde(default)]
+    pub utxo_data: Vec<UtxoTransaction>,
+    pub prev_hash: Sha256,
+    /// root of the merkle tree over `txids`
+    pub merkle_root: Sha256,
+    pub hash: Sha256,
+    pub bits: u32,
+    pub nonce: u64,
+}
+
+impl Block {
+    pub fn new(timestamp: DateTime<Utc>, data: Vec<Transaction>) -> Self {
+        let mut item = Self {
+            timestamp,
+            data,
+            utxo_data: Vec::new(),
+            prev_hash: Sha256::from_slice(&[0u8; 32]).unwrap(),
+            merkle_root: Sha256::from_slice(&[0u8; 32]).unwrap(),
+            hash: Sha256::from_slice(&[0u8; 32]).unwrap(),
+            bits: DEFAULT_BITS,
+            nonce: 0,
+        };
+        item.merkle_root = item
+            .compute_merkle_root()
+            .expect("failed to hash block transactions");
+        item.hash = item.get_hash();
+        item
+    }
+
+    pub fn new_utxo(timestamp: DateTime<Utc>, utxo_data: Vec<UtxoTransaction>) -> Self {
+        let mut item = Self::new(timestamp, Vec::new());
+        item.utxo_data = utxo_data;
+        item.merkle_root = item
+            .compute_merkle_root()
+            .expect("failed to hash block transactions");
+        item.hash = item.get_hash();
+        item
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            version: HEADER_VERSION,
+            prev_hash: self.prev_hash,
+            merkle_root: self.merkle_root,
+            timestamp: self.timestamp,
+            bits: self.bits,
+            nonce: self.nonce,
+        }
+    }
+
+    /// hash of the header, the transactions only count through `merkle_root`
+    pub fn get_hash(&self) -> Sha256 {
+        self.header().hash()
+    }
+
+    /// ids of the account transactions, then of the utxo transactions
+    pub fn txids(&self) -> Result<Vec<Sha256>, Error> {
+        let mut txids = Vec::with_capacity(self.data.len() + self.utxo_data.len());
+        for trans in self.data.iter() {
+            txids.push(trans.txid()?);
+        }
+        for tx in self.utxo_data.iter() {
+            txids.push(tx.txid()?);
+        }
+        Ok(txids)
+    }
+
+    pub fn compute_merkle_root(&self) -> Result<Sha256, Error> {
+        Ok(merkle::merkle_root(&self.txids()?))
+    }
+
+    /// proof that the transaction `txid` is in the block, checked against
+    /// the header alone by `merkle::verify_merkle_proof`
+    pub fn merkle_proof(&self, txid: &Sha256) -> Result<Option<MerkleProof>, Error> {
+        let txids = self.txids()?;
+        Ok(txids
+            .iter()
+            .position(|id| id == txid)
+            .and_then(|index| merkle::merkle_proof(&txids, index)))
+    }
+
+    /// whether the hash meets the target of `bits`
+    pub fn is_mined(&self) -> Result<bool, Error> {
+        Ok(Target::from_bits(self.bits)?.is_met_by(&self.hash))
+    }
+
+    /// expected number of hashes it took to mine the block
+    pub fn work(&self) -> Result<u128, Error> {
+        Ok(Target::from_bits(self.bits)?.work())
+    }
+
+    pub fn mine(&mut self) -> Result<(), Error> {
+        while !self.is_mined()? {
+            self.nonce += 1;
+            let hash = self.get_hash();
+            self.hash = hash;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_block {
+    use crate::block::*;
+    use crate::Amount;
+    use bitcoin_hashes::hex::ToHex;
+
+    #[test]
+    fn test_new() {
+        let now = chrono::Utc::now();
+        let trans = Vec::new();
+        let item = Block::new(now, trans);
+        assert_eq!(item.timestamp, now);
+        assert_ne!(item.prev_hash, item.hash);
+        assert_eq!(item.nonce, 0);
+    }
+
+    #[test]
+    fn test_mine() {
+        let now = chrono::Utc::now();
+        let trans = Vec::new();
+        let mut item = Block::new(now, trans);
+        assert!(item.mine().is_ok());
+        assert_ne!(item.nonce, 0);
+        assert_eq!(item.is_mined(), Ok(true));
+        assert!(item.hash.to_hex().starts_with("00"), "must start with 00");
+
+        assert_eq!(item.work().unwrap(), 256);
+        // halving the target doubles the work
+        item.bits = 0x2000_7fff;
+        assert_eq!(item.work().unwrap(), 512);
+    }
+
+    #[test]
+    fn test_mine_invalid_bits() {
+        let mut item = Block::new(chrono::Utc::now(), Vec::new());
+        item.bits = 0x2100_ffff;
+        assert_eq!(item.is_mined(), Err(Error::InvalidTarget(0x2100_ffff)));
+        assert_eq!(item.mine(), Err(Error::InvalidTarget(0x2100_ffff)));
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut item = Block::new(chrono::Utc::now(), Vec::new());
+        item.mine().unwrap();
+        let bytes = bincode::serialize(&item).unwrap();
+        assert_eq!(bincode::deserialize::<Block>(&bytes).unwrap(), item);
+        let json = serde_json::to_string(&item).unwrap();
+        assert!(json.contains(&format!("\"hash\":\"{}\"", item.hash.to_hex())));
+        assert_eq!(serde_json::from_str::<Block>(&json).unwrap(), item);
+
+        let header = item.header();
+        assert_eq!(header.version, HEADER_VERSION);
+        assert_eq!(header.hash(), item.hash);
+        assert_eq!(header.merkle_root, item.merkle_root);
+        let json = serde_json::to_string(&header).unwrap();
+        assert_eq!(serde_json::from_str::<BlockHeader>(&json).unwrap(), header);
+    }
+
+    #[test]
+    fn test_merkle_proof() {
+        use crate::constant::*;
+        use crate::transaction::Transaction;
+        let data: Vec<Transaction> = (1..=3)
+            .map(|coins| {
+                Transaction::new(KEY1.1, KEY2.3, Amount::from_coins(coins).unwrap(), None, 0)
+            })
+            .collect();
+        let mut item = Block::new(chrono::Utc::now(), data);
+        item.mine().unwrap();
+        let header = item.header();
+
+        let txid = item.data[2].txid().unwrap();
+        let proof = item.merkle_proof(&txid).unwrap().unwrap();
+        assert!(merkle::verify_merkle_proof(
+            &txid,
+            &proof,
+            &header.merkle_root
+        ));
+        let unknown = Transaction::new(KEY2.1, KEY1.3, Amount::from_coins(1).unwrap(), None, 0);
+        assert_eq!(item.merkle_proof(&unknown.txid().unwrap()), Ok(None));
+
+        // changing a transaction breaks the commitment of the header
+        item.data[0].amount = Amount::from_coins(9).unwrap();
+        assert_ne!(item.compute_merkle_root().unwrap(), header.merkle_root);
+        assert_eq!(item.get_hash(), header.hash());
+    }
+}
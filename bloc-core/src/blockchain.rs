@@ -0,0 +1,1136 @@
+use crate::genesis::GenesisConfig;
+use crate::mempool::Mempool;
+use crate::secp256k1::*;
+use crate::store::{BlockStore, FileStore};
+use crate::template::{self, BlockTemplate, TemplateLimits};
+use crate::tree::{BlockTree, ChainEvent, Reorg, MAX_FORK_DEPTH};
+use crate::utxo::{ChainModel, TxOut, UtxoTransaction, UtxoView};
+use crate::validation::{self, ChainParams, ChainState};
+use crate::{
+    block::{Block, BlockHeader, FORMAT_VERSION},
+    transaction::Transaction,
+    Address, Amount, Error,
+};
+use bitcoin_hashes::sha256::Hash as Sha256;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::path::Path;
+
+/// genesis config of a chain data directory
+pub const GENESIS_FILE: &str = "genesis.json";
+/// block log of a chain data directory
+pub const BLOCKS_FILE: &str = "blocks.dat";
+
+pub struct BlockChain {
+    /// pending transactions of a `ChainModel::Account` chain
+    pub mempool: Mempool,
+    pub chain: Vec<Block>,
+    /// milliseconds the retargeting aims for between two blocks
+    pub block_time: u32,
+    pub reward: Amount,
+    pub genesis: GenesisConfig,
+    pub chain_id: Sha256,
+    pub mint_address: PubKey,
+    /// pending transactions of a `ChainModel::Utxo` chain
+    pub utxo_transactions: Vec<UtxoTransaction>,
+    /// bounds of the blocks `mine_transaction` assembles
+    pub limits: TemplateLimits,
+    /// the ledger after the last block, see `validation`
+    pub state: ChainState,
+    /// every known block, the active chain and the branches competing with it
+    pub tree: BlockTree,
+    /// where appended blocks are persisted, if anywhere
+    store: Option<Box<dyn BlockStore + Send>>,
+}
+
+impl PartialEq for BlockChain {
+    fn eq(&self, other: &Self) -> bool {
+        self.mempool.transactions() == other.mempool.transactions()
+            && self.chain == other.chain
+            && self.state.bits == other.state.bits
+            && self.block_time == other.block_time
+            && self.reward == other.reward
+            && self.genesis == other.genesis
+            && self.chain_id == other.chain_id
+            && self.mint_address == other.mint_address
+            && self.utxo_transactions == other.utxo_transactions
+    }
+}
+
+// a clone is an in-memory snapshot, detached from the store of the original
+impl Clone for BlockChain {
+    fn clone(&self) -> Self {
+        Self {
+            mempool: self.mempool.clone(),
+            chain: self.chain.clone(),
+            block_time: self.block_time,
+            reward: self.reward,
+            genesis: self.genesis.clone(),
+            chain_id: self.chain_id,
+            mint_address: self.mint_address,
+            utxo_transactions: self.utxo_transactions.clone(),
+            limits: self.limits,
+            state: self.state.clone(),
+            tree: self.tree.clone(),
+            store: None,
+        }
+    }
+}
+
+// the exported form of a chain, the rest is derived from `genesis`
+#[derive(Serialize)]
+struct ChainExport<'a> {
+    version: u32,
+    genesis: &'a GenesisConfig,
+    bits: u32,
+    block_time: u32,
+    reward: Amount,
+    transactions: Vec<Transaction>,
+    utxo_transactions: &'a [UtxoTransaction],
+    chain: &'a [Block],
+}
+
+#[derive(Deserialize)]
+struct ChainImport {
+    version: u32,
+    genesis: GenesisConfig,
+    bits: u32,
+    block_time: u32,
+    reward: Amount,
+    transactions: Vec<Transaction>,
+    #[serde(default)]
+    utxo_transactions: Vec<UtxoTransaction>,
+    chain: Vec<Block>,
+}
+
+impl Serialize for BlockChain {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        ChainExport {
+            version: FORMAT_VERSION,
+            genesis: &self.genesis,
+            bits: self.state.bits,
+            block_time: self.block_time,
+            reward: self.reward,
+            transactions: self.mempool.transactions(),
+            utxo_transactions: &self.utxo_transactions,
+            chain: &self.chain,
+        }
+        .serialize(s)
+    }
+}
+
+/// the genesis config is trusted and every imported block is validated
+/// against it, rebuilding the state
+impl<'de> Deserialize<'de> for BlockChain {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let import = ChainImport::deserialize(d)?;
+        if import.version != FORMAT_VERSION {
+            return Err(de::Error::custom(Error::UnsupportedVersion(import.version)));
+        }
+        let mut chain = BlockChain::from_genesis(&import.genesis).map_err(de::Error::custom)?;
+        if import.chain.first() != chain.chain.first() {
+            return Err(de::Error::custom(Error::GenesisMismatch));
+        }
+        // the rules come from the genesis config the chain id commits to
+        if import.block_time != chain.block_time || import.reward != chain.reward {
+            return Err(de::Error::custom(Error::Genesis(
+                "block time or reward differs from the genesis config".to_owned(),
+            )));
+        }
+        chain.utxo_transactions = import.utxo_transactions;
+        let params = chain.params();
+        for block in import.chain.iter().skip(1) {
+            chain
+                .state
+                .connect_block(&params, block)
+                .map_err(de::Error::custom)?;
+            chain
+                .tree
+                .insert(block.clone())
+                .map_err(de::Error::custom)?;
+        }
+        chain.chain = import.chain;
+        if import.bits != chain.state.bits {
+            return Err(de::Error::custom(Error::InvalidTarget(import.bits)));
+        }
+        for trans in import.transactions {
+            chain.add_transaction(trans).map_err(de::Error::custom)?;
+        }
+        Ok(chain)
+    }
+}
+
+impl Default for BlockChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockChain {
+    /// chain of the default `GenesisConfig`
+    pub fn new() -> Self {
+        Self::from_genesis(&GenesisConfig::default()).expect("default genesis config is valid")
+    }
+
+    pub fn from_genesis(config: &GenesisConfig) -> Result<Self, Error> {
+        let genesis = config.genesis_block()?;
+        let params = ChainParams {
+            chain_id: config.chain_id()?,
+            mint_address: config.mint_address()?,
+            reward: config.reward,
+            block_time: config.block_time,
+            model: config.model,
+        };
+        Ok(Self {
+            mempool: Mempool::new(),
+            state: ChainState::genesis(&params, &genesis)?,
+            tree: BlockTree::new(genesis.clone()),
+            chain: vec![genesis],
+            block_time: config.block_time,
+            reward: config.reward,
+            genesis: config.clone(),
+            chain_id: params.chain_id,
+            mint_address: params.mint_address,
+            utxo_transactions: Vec::new(),
+            limits: TemplateLimits::default(),
+            store: None,
+        })
+    }
+
+    /// initialise the data directory `path` with `config` and open it
+    pub fn create<P: AsRef<Path>>(path: P, config: &GenesisConfig) -> Result<Self, Error> {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path)?;
+        std::fs::write(path.join(GENESIS_FILE), config.to_json()?)?;
+        Self::open(path)
+    }
+
+    /// reload the chain persisted in the data directory `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let config = GenesisConfig::load(path.join(GENESIS_FILE))?;
+        let store = FileStore::open(path.join(BLOCKS_FILE))?;
+        Self::with_store(&config, Box::new(store))
+    }
+
+    /// replay and re-validate every block of `store`, then persist the
+    /// blocks added from now on into it
+    pub fn with_store(
+        config: &GenesisConfig,
+        mut store: Box<dyn BlockStore + Send>,
+    ) -> Result<Self, Error> {
+        let mut chain = Self::from_genesis(config)?;
+        match store.get(0)? {
+            None => store.append(&chain.chain[0])?,
+            Some(genesis) if genesis == chain.chain[0] => {}
+            Some(_) => return Err(Error::GenesisMismatch),
+        }
+        for height in 1..store.len() {
+            let block = store
+                .get(height)?
+                .ok_or_else(|| Error::Serialize(format!("block {} missing from store", height)))?;
+            chain.replay_block(block)?;
+        }
+        log::debug!("replayed {} blocks from store", chain.len());
+        chain.store = Some(store);
+        Ok(chain)
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Serialize(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|e| Error::Serialize(e.to_string()))
+    }
+
+    pub fn genesis_hash(&self) -> Sha256 {
+        self.chain[0].hash
+    }
+
+    pub fn len(&self) -> usize {
+        self.chain.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chain.is_empty()
+    }
+
+    pub fn get_last_block(&self) -> &Block {
+        self.chain.last().expect("failed to obtain the last block")
+    }
+
+    pub fn model(&self) -> ChainModel {
+        self.genesis.model
+    }
+
+    /// the rules the blocks of the chain are validated with
+    pub fn params(&self) -> ChainParams {
+        ChainParams {
+            chain_id: self.chain_id,
+            mint_address: self.mint_address,
+            reward: self.reward,
+            block_time: self.block_time,
+            model: self.model(),
+        }
+    }
+
+    pub fn add_block(&mut self, mut block: Block) -> Result<(), Error> {
+        block.prev_hash = self.get_last_block().hash;
+        block.merkle_root = block.compute_merkle_root()?;
+        block.bits = self.state.bits;
+        block.hash = block.get_hash();
+        // no proof of work is spent on transactions the chain refuses
+        let params = self.params();
+        self.state.validate_transactions(&params, &block)?;
+        block.mine()?;
+        log::debug!("mined with hash: {}", block.hash);
+        self.state.connect_block(&params, &block)?;
+        if let Some(store) = self.store.as_mut() {
+            if let Err(e) = store.append(&block) {
+                self.state.disconnect_block(&params, &block, &self.chain)?;
+                return Err(e);
+            }
+        }
+        self.push_block(block);
+        self.prune_tree();
+        Ok(())
+    }
+
+    // validate a block received or stored against the chain so far before
+    // pushing it
+    fn replay_block(&mut self, block: Block) -> Result<(), Error> {
+        let params = self.params();
+        self.state.connect_block(&params, &block)?;
+        self.push_block(block);
+        Ok(())
+    }
+
+    fn push_block(&mut self, block: Block) {
+        if let Err(e) = self.mempool.remove_block(&block, &self.state) {
+            log::error!("failed to clear the mined transactions: {}", e);
+        }
+        self.mempool.expire(chrono::Utc::now());
+        self.utxo_transactions
+            .retain(|tx| !block.utxo_data.contains(tx));
+        if !self.tree.contains(&block.hash) {
+            if let Err(e) = self.tree.insert(block.clone()) {
+                log::error!("block {} left out of the tree: {}", block.hash, e);
+            }
+        }
+        self.chain.push(block);
+    }
+
+    /// accept a mined block from a peer: extend the active chain with it,
+    /// keep it on a side branch, or switch to its branch when that one has
+    /// more work than the active chain
+    pub fn submit_block(&mut self, block: Block) -> Result<ChainEvent, Error> {
+        if self.tree.contains(&block.hash) {
+            return Ok(ChainEvent::Duplicate);
+        }
+        if block.hash != block.get_hash() {
+            return Err(Error::InvalidHash);
+        }
+        if block.merkle_root != block.compute_merkle_root()? {
+            return Err(Error::InvalidMerkleRoot);
+        }
+        if !block.is_mined()? {
+            return Err(Error::InvalidHash);
+        }
+        if block.prev_hash == self.get_last_block().hash {
+            self.replay_block(block)?;
+            if let Err(e) = self.store_tip() {
+                // forgotten, so that the block may be submitted again
+                let block = self.disconnect_tip()?;
+                self.tree.remove_branch(&block.hash);
+                self.resubmit(std::slice::from_ref(&block));
+                return Err(e);
+            }
+            self.prune_tree();
+            return Ok(ChainEvent::Connected {
+                height: self.len() as u64 - 1,
+            });
+        }
+        // a side block is checked against its own branch before it is kept,
+        // so that branches cannot be grown for free
+        let parent = self
+            .tree
+            .get(&block.prev_hash)
+            .ok_or(Error::UnknownParent(block.prev_hash))?;
+        if parent.height + MAX_FORK_DEPTH < self.len() as u64 - 1 {
+            return Err(Error::StaleFork(parent.height));
+        }
+        self.tree
+            .header_state(&self.params(), &block.prev_hash)?
+            .validate_header(&block.header())?;
+        let node = self.tree.insert(block)?;
+        let (hash, height, work) = (node.block.hash, node.height, node.work);
+        if work <= self.tip_work() {
+            log::debug!("block {} kept on a side branch at {}", hash, height);
+            return Ok(ChainEvent::SideBranch { height });
+        }
+        let reorg = self.reorganize(&hash)?;
+        self.prune_tree();
+        Ok(ChainEvent::Reorganized(reorg))
+    }
+
+    // forget the side branches the active chain has outgrown
+    fn prune_tree(&mut self) {
+        if self.tree.len() > self.len() {
+            self.tree.prune(&self.chain);
+        }
+    }
+
+    fn tip_work(&self) -> u128 {
+        self.tree
+            .get(&self.get_last_block().hash)
+            .map(|node| node.work)
+            .unwrap_or(0)
+    }
+
+    fn store_tip(&mut self) -> Result<(), Error> {
+        let block = self.get_last_block().clone();
+        match self.store.as_mut() {
+            Some(store) => store.append(&block),
+            None => Ok(()),
+        }
+    }
+
+    // make the branch of `tip` the active chain; if one of its blocks is
+    // invalid the branch is dropped from that block on and the old chain
+    // is restored, as it is when the store fails to switch branches
+    fn reorganize(&mut self, tip: &Sha256) -> Result<Reorg, Error> {
+        let mut branch = Vec::new();
+        let mut hash = *tip;
+        let fork_height = loop {
+            let node = self.tree.get(&hash).ok_or(Error::UnknownParent(hash))?;
+            if self.chain.get(node.height as usize).map(|block| block.hash) == Some(hash) {
+                break node.height;
+            }
+            branch.push(node.block.clone());
+            hash = node.block.prev_hash;
+        };
+        branch.reverse();
+
+        let disconnected = self.rewind(fork_height);
+        let old: Vec<Block> = disconnected.iter().rev().cloned().collect();
+        for block in branch.iter() {
+            if let Err(e) = self.replay_block(block.clone()) {
+                log::warn!("dropping the branch of invalid block {}: {}", block.hash, e);
+                self.tree.remove_branch(&block.hash);
+                self.rewind(fork_height);
+                self.restore(old);
+                return Err(e);
+            }
+        }
+        if let Err(e) = self.store_branch(fork_height, &branch) {
+            log::error!("failed to store the branch of {}: {}", tip, e);
+            self.tree.remove_branch(&branch[0].hash);
+            self.rewind(fork_height);
+            self.restore(old.clone());
+            if let Err(e) = self.store_branch(fork_height, &old) {
+                log::error!("store left behind the active chain: {}", e);
+            }
+            return Err(e);
+        }
+        log::info!(
+            "reorganized at {}: {} blocks rolled back, {} applied",
+            fork_height,
+            disconnected.len(),
+            branch.len()
+        );
+        let resubmitted = self.resubmit(&disconnected);
+        Ok(Reorg {
+            fork_height,
+            disconnected: disconnected.iter().map(|block| block.hash).collect(),
+            connected: branch.iter().map(|block| block.hash).collect(),
+            resubmitted,
+        })
+    }
+
+    // replace the blocks of the store above `fork_height` with `branch`
+    fn store_branch(&mut self, fork_height: u64, branch: &[Block]) -> Result<(), Error> {
+        if let Some(store) = self.store.as_mut() {
+            store.truncate(fork_height + 1)?;
+            for block in branch.iter() {
+                store.append(block)?;
+            }
+        }
+        Ok(())
+    }
+
+    // pop the blocks above `height` off the active chain, the tip first;
+    // their state was moved forward by the same blocks, so undoing it only
+    // fails on a corrupted state, which the chain cannot go on with
+    fn rewind(&mut self, height: u64) -> Vec<Block> {
+        let mut disconnected = Vec::new();
+        while self.len() as u64 > height + 1 {
+            let hash = self.get_last_block().hash;
+            match self.disconnect_tip() {
+                Ok(block) => disconnected.push(block),
+                Err(e) => panic!("failed to roll back block {}: {}", hash, e),
+            }
+        }
+        disconnected
+    }
+
+    // put back `blocks`, rolled back from the active chain and valid on it
+    // before, failing only on a corrupted state like `rewind`
+    fn restore(&mut self, blocks: Vec<Block>) {
+        for block in blocks {
+            let hash = block.hash;
+            if let Err(e) = self.replay_block(block) {
+                panic!("failed to restore block {}: {}", hash, e);
+            }
+        }
+    }
+
+    // pop the last block off the active chain, it stays in the tree
+    fn disconnect_tip(&mut self) -> Result<Block, Error> {
+        debug_assert!(self.len() > 1, "genesis is never disconnected");
+        let block = self.chain.pop().expect("failed to obtain the last block");
+        let params = self.params();
+        self.state.disconnect_block(&params, &block, &self.chain)?;
+        Ok(block)
+    }
+
+    // queue again the transactions of rolled back blocks, ahead of the
+    // pending ones, dropping those the new branch made invalid
+    fn resubmit(&mut self, disconnected: &[Block]) -> usize {
+        let pending = self.mempool.drain();
+        let utxo_pending = std::mem::take(&mut self.utxo_transactions);
+        let mut resubmitted = 0;
+        for block in disconnected.iter().rev() {
+            for trans in block.data.iter() {
+                if trans.from != self.mint_address && self.add_transaction(trans.clone()).is_ok() {
+                    resubmitted += 1;
+                }
+            }
+            for tx in block.utxo_data.iter() {
+                if !tx.is_coinbase() && self.add_utxo_transaction(tx.clone()).is_ok() {
+                    resubmitted += 1;
+                }
+            }
+        }
+        for trans in pending {
+            let _ = self.add_transaction(trans);
+        }
+        for tx in utxo_pending {
+            let _ = self.add_utxo_transaction(tx);
+        }
+        resubmitted
+    }
+
+    /// hashes of the active chain from the tip down, dense for the last
+    /// blocks then doubling the step back to genesis, for a peer to find
+    /// where its chain forks from this one
+    pub fn locator(&self) -> Vec<Sha256> {
+        let mut locator = Vec::new();
+        let mut height = self.len() - 1;
+        let mut step = 1;
+        loop {
+            locator.push(self.chain[height].hash);
+            if height == 0 {
+                return locator;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+    }
+
+    /// headers of the active blocks after the first hash of `locator` on
+    /// the active chain, genesis when none is, up to `stop` included and at
+    /// most `max` of them
+    pub fn headers_after(
+        &self,
+        locator: &[Sha256],
+        stop: Option<&Sha256>,
+        max: usize,
+    ) -> Vec<BlockHeader> {
+        let fork = locator
+            .iter()
+            .find_map(|hash| {
+                let node = self.tree.get(hash)?;
+                let active = self.chain.get(node.height as usize)?.hash == *hash;
+                active.then_some(node.height as usize)
+            })
+            .unwrap_or(0);
+        let mut headers = Vec::new();
+        for block in self.chain[fork + 1..].iter().take(max) {
+            headers.push(block.header());
+            if Some(&block.hash) == stop {
+                break;
+            }
+        }
+        headers
+    }
+
+    /// total expected number of hashes it took to mine the blocks after
+    /// genesis, the chain with the most work is the one to follow
+    pub fn chain_work(&self) -> Result<u128, Error> {
+        let mut work = 0u128;
+        for block in self.chain.iter().skip(1) {
+            work = work.saturating_add(block.work()?);
+        }
+        Ok(work)
+    }
+
+    /// nonce of the next confirmed transaction of `address`
+    pub fn nonce_of(&self, address: &PubKey) -> u64 {
+        self.state.nonce_of(address)
+    }
+
+    /// nonce `address` must sign its next transaction with, counting its
+    /// pending transactions; the mint signs with the next block height
+    pub fn next_nonce(&self, address: &PubKey) -> u64 {
+        if address == &self.mint_address {
+            return self.len() as u64;
+        }
+        self.mempool.next_nonce(address, self.nonce_of(address))
+    }
+
+    /// queue `trans` for the next block, rejecting replayed and out of
+    /// order nonces and transfers its sender cannot pay for on top of its
+    /// pending ones
+    pub fn add_transaction(&mut self, trans: Transaction) -> Result<(), Error> {
+        if self.model() != ChainModel::Account {
+            return Err(Error::WrongModel);
+        }
+        if trans.from == self.mint_address {
+            return Err(Error::InvalidCoinbase);
+        }
+        let balance = self.state.balance(&Address::from(&trans.from));
+        let available = balance.saturating_sub(self.mempool.pending_debit(&trans.from)?);
+        let confirmed = self.state.nonce_of(&trans.from);
+        let queued = trans
+            .verify(&self.chain_id, &self.mint_address, available)
+            .and_then(|_| self.mempool.insert(trans, balance, confirmed));
+        if let Err(e) = queued {
+            log::error!("failed to add transaction to blockchain: {}", e);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// the unspent outputs spent and created by the pending transactions
+    pub fn pending_view(&self) -> Result<UtxoView, Error> {
+        let mut view = UtxoView::default();
+        for tx in self.utxo_transactions.iter() {
+            self.state.utxo_set.check_transaction(tx, &mut view)?;
+        }
+        Ok(view)
+    }
+
+    /// queue `tx` for the next block, rejecting it if it spends an output
+    /// already spent by a pending transaction
+    pub fn add_utxo_transaction(&mut self, tx: UtxoTransaction) -> Result<(), Error> {
+        if self.model() != ChainModel::Utxo {
+            return Err(Error::WrongModel);
+        }
+        let mut view = self.pending_view()?;
+        if let Err(e) = self.state.utxo_set.check_transaction(&tx, &mut view) {
+            log::error!("failed to add transaction to blockchain: {}", e);
+            return Err(e);
+        }
+        self.utxo_transactions.push(tx);
+        Ok(())
+    }
+
+    /// mine the block `block_template` assembles and append it
+    pub fn mine_transaction(&mut self, reward_address: &Address) -> Result<Block, Error> {
+        let template = self.block_template(reward_address)?;
+        self.add_block(template.block)?;
+        Ok(self.get_last_block().clone())
+    }
+
+    /// assemble the next block out of the pending transactions paying the
+    /// most tips within `limits`, its coinbase paying the reward and the
+    /// tips to `reward_address`
+    pub fn block_template(&self, reward_address: &Address) -> Result<BlockTemplate, Error> {
+        let now = chrono::Utc::now();
+        let (mut block, tips) = match self.model() {
+            ChainModel::Account => {
+                let reward = self.coinbase(reward_address, Amount::ZERO)?;
+                let reserved = template::block_size(&Block::new(now, vec![reward]))?;
+                let mut data = template::select_transactions(
+                    &self.mempool,
+                    self.limits.max_size.saturating_sub(reserved),
+                    self.limits.max_transactions.saturating_sub(1),
+                );
+                let tips = Amount::checked_sum(data.iter().map(|trans| &trans.tips))?;
+                data.push(self.coinbase(reward_address, tips)?);
+                (Block::new(now, data), tips)
+            }
+            ChainModel::Utxo => {
+                let coinbase = self.utxo_coinbase(reward_address, Amount::ZERO)?;
+                let mut size = template::block_size(&Block::new_utxo(now, vec![coinbase]))?;
+                let mut view = UtxoView::default();
+                let mut tips = Amount::ZERO;
+                let mut txs = Vec::new();
+                // in queue order, a transaction spending the outputs of one
+                // left out is left out too
+                for tx in self.utxo_transactions.iter() {
+                    if txs.len() + 1 >= self.limits.max_transactions {
+                        break;
+                    }
+                    let tx_size = template::utxo_size(tx)?;
+                    if size + tx_size > self.limits.max_size {
+                        continue;
+                    }
+                    let mut spent = view.clone();
+                    if let Ok(tx_tips) = self.state.utxo_set.check_transaction(tx, &mut spent) {
+                        view = spent;
+                        tips = tips.checked_add(tx_tips)?;
+                        size += tx_size;
+                        txs.push(tx.clone());
+                    }
+                }
+                txs.insert(0, self.utxo_coinbase(reward_address, tips)?);
+                (Block::new_utxo(now, txs), tips)
+            }
+        };
+        block.prev_hash = self.get_last_block().hash;
+        block.bits = self.state.bits;
+        block.merkle_root = block.compute_merkle_root()?;
+        block.hash = block.get_hash();
+        Ok(BlockTemplate {
+            size: template::block_size(&block)?,
+            block,
+            height: self.len() as u64,
+            tips,
+        })
+    }
+
+    // the reward of the next block of an account chain
+    fn coinbase(&self, reward_address: &Address, tips: Amount) -> Result<Transaction, Error> {
+        let mut reward = Transaction::new(
+            self.mint_address,
+            *reward_address,
+            self.reward.checked_add(tips)?,
+            None,
+            self.len() as u64,
+        );
+        reward.sign(&KeyPair::from(&self.genesis.mint_key), &self.chain_id)?;
+        Ok(reward)
+    }
+
+    // the coinbase of the next block of a utxo chain
+    fn utxo_coinbase(
+        &self,
+        reward_address: &Address,
+        tips: Amount,
+    ) -> Result<UtxoTransaction, Error> {
+        let mut coinbase = UtxoTransaction::coinbase(
+            self.len() as u32,
+            vec![TxOut {
+                to: *reward_address,
+                amount: self.reward.checked_add(tips)?,
+            }],
+        );
+        coinbase.sign(&KeyPair::from(&self.genesis.mint_key))?;
+        Ok(coinbase)
+    }
+
+    /// confirmed balance of `address`, the value of its unspent outputs on
+    /// a utxo chain
+    pub fn get_balance(&self, address: &Address) -> Result<Amount, Error> {
+        match self.model() {
+            ChainModel::Account => Ok(self.state.balance(address)),
+            ChainModel::Utxo => self.state.utxo_set.balance(address),
+        }
+    }
+
+    /// validate every block from genesis on, see `validation::validate_chain`
+    pub fn is_valid(&self) -> Result<(), Error> {
+        let genesis = self.genesis.genesis_block()?;
+        validation::validate_chain(&self.params(), &genesis, &self.chain).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_block_chain {
+    use crate::blockchain::*;
+    use crate::constant::*;
+
+    // chain whose genesis block funds KEY1 with 100000 coins
+    pub(crate) fn chain_with_funds() -> BlockChain {
+        BlockChain::from_genesis(&funded_genesis(100000)).unwrap()
+    }
+
+    // make sure the genesis block is deterministic
+    #[test]
+    fn test_new() {
+        let item = BlockChain::new();
+        assert_eq!(item.chain.len(), 1);
+        let item_du = BlockChain::new();
+        assert_eq!(item.chain[0], item_du.chain[0]);
+        assert_eq!(item.chain_id, item_du.chain_id);
+        assert_eq!(item.mempool.transactions(), item_du.mempool.transactions());
+        assert_eq!(item.state.bits, item_du.state.bits);
+        assert_eq!(item.block_time, item_du.block_time);
+        assert_eq!(item.reward, item_du.reward);
+
+        let funded = chain_with_funds();
+        assert_eq!(funded.chain[0], chain_with_funds().chain[0]);
+        assert_ne!(funded.genesis_hash(), item.genesis_hash());
+        assert_ne!(funded.chain_id, item.chain_id);
+    }
+
+    #[test]
+    fn test_locator() {
+        let mut chain = BlockChain::new();
+        for _ in 0..15 {
+            chain.mine_transaction(&KEY1.3).unwrap();
+        }
+        let hashes: Vec<Sha256> = chain.chain.iter().map(|block| block.hash).collect();
+        let locator = chain.locator();
+        assert_eq!(
+            locator[..10],
+            hashes[6..].iter().rev().cloned().collect::<Vec<_>>()[..]
+        );
+        assert_eq!(locator[10..], [hashes[4], hashes[0]]);
+
+        // a peer knowing the first 5 blocks is sent the 11 after them
+        let headers = chain.headers_after(&[hashes[4], hashes[0]], None, 100);
+        assert_eq!(headers.len(), 11);
+        assert_eq!(headers[0].hash(), hashes[5]);
+        let headers = chain.headers_after(&[hashes[4]], Some(&hashes[7]), 100);
+        assert_eq!(headers.len(), 3);
+        assert_eq!(chain.headers_after(&[], None, 4).len(), 4);
+        assert!(chain.headers_after(&[hashes[15]], None, 100).is_empty());
+    }
+
+    #[test]
+    fn test_add_block() {
+        let dir = tempfile::tempdir().unwrap();
+        // a target no block is mined at in the time of a test, the block
+        // must be refused before mining it
+        let config = GenesisConfig {
+            bits: 0x0301_0000,
+            ..Default::default()
+        };
+        let mut item = BlockChain::create(dir.path(), &config).unwrap();
+        let mut secp = Secp256K1::new();
+        let (_, pub1) = secp.gen_keypair();
+        let (_, pub2) = secp.gen_keypair();
+        // an unfunded sender, a foreign signature and no coinbase
+        let transaction = Transaction {
+            from: pub1,
+            to: Address::from(&pub2),
+            amount: Amount::from_coins(3000).unwrap(),
+            tips: Amount::from_coins(100).unwrap(),
+            nonce: 0,
+            signature: MINT_KEY.3,
+        };
+        let now = chrono::Utc::now();
+        let block = Block::new(now, vec![transaction]);
+        assert!(item.add_block(block).is_err());
+        assert_eq!(item.chain.len(), 1);
+        drop(item);
+
+        // nothing was persisted, the directory still opens
+        let reopened = BlockChain::open(dir.path()).unwrap();
+        assert_eq!(reopened.len(), 1);
+    }
+
+    #[test]
+    fn test_mine_trasaction() {
+        let mut chain = BlockChain::new();
+        let mut secp = Secp256K1::new();
+        let (_, pub1) = secp.gen_keypair();
+        assert!(chain.mine_transaction(&Address::from(&pub1)).is_ok());
+        dbg!(&chain.chain[1].data[0]);
+        assert_eq!(chain.chain[1].data[0].from, *MINT_PUBLIC_ADDRESS);
+        assert!(chain.chain[1].data[0].to.is_owned_by(&pub1));
+    }
+
+    #[test]
+    fn test_chain_work() {
+        let mut chain = BlockChain::new();
+        assert_eq!(chain.chain_work(), Ok(0));
+        chain.mine_transaction(&KEY1.3).unwrap();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        // the blocks of a window are mined at the same target
+        assert_eq!(chain.chain[2].work(), chain.chain[1].work());
+        assert_eq!(chain.chain_work(), Ok(256 + 256));
+        assert!(chain.chain[1..]
+            .iter()
+            .all(|block| block.is_mined() == Ok(true)));
+    }
+
+    // mine an empty block stamped `timestamp`
+    fn mine_at(chain: &mut BlockChain, timestamp: chrono::DateTime<chrono::Utc>) {
+        let mut reward = Transaction::new(
+            chain.mint_address,
+            KEY1.3,
+            chain.reward,
+            None,
+            chain.len() as u64,
+        );
+        reward.sign(&MINT_KEY_PAIR, &chain.chain_id).unwrap();
+        chain
+            .add_block(Block::new(timestamp, vec![reward]))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_retarget() {
+        use crate::pow::{Target, POW_LIMIT_BITS, RETARGET_WINDOW};
+        use crate::store::MemoryStore;
+        let config = GenesisConfig {
+            bits: POW_LIMIT_BITS,
+            block_time: 1000,
+            ..Default::default()
+        };
+        let mut chain = BlockChain::from_genesis(&config).unwrap();
+        let limit = Target::pow_limit();
+        let mut time = config.timestamp;
+
+        // a window mined ten times too fast is clamped to four times harder
+        for _ in 0..RETARGET_WINDOW {
+            time += chrono::Duration::milliseconds(100);
+            mine_at(&mut chain, time);
+        }
+        assert_eq!(chain.chain[RETARGET_WINDOW].bits, POW_LIMIT_BITS);
+        let fast = Target::from_bits(chain.state.bits).unwrap();
+        assert_eq!(
+            fast,
+            Target::from_bits(limit.scale(1, 4).to_bits()).unwrap()
+        );
+        mine_at(&mut chain, time);
+        assert_eq!(chain.chain[RETARGET_WINDOW + 1].bits, fast.to_bits());
+
+        // a window mined twice too slow makes it twice easier
+        for _ in 1..RETARGET_WINDOW {
+            time += chrono::Duration::milliseconds(2000);
+            mine_at(&mut chain, time);
+        }
+        let slow = Target::from_bits(chain.state.bits).unwrap();
+        assert_eq!(slow.to_bits(), fast.scale(2, 1).to_bits());
+        assert_eq!(chain.is_valid(), Ok(()));
+
+        // the stored blocks are replayed against the same rule
+        let mut store = MemoryStore::new();
+        for block in chain.chain.iter() {
+            store.append(block).unwrap();
+        }
+        let replayed = BlockChain::with_store(&config, Box::new(store)).unwrap();
+        assert_eq!(replayed.state, chain.state);
+
+        // a block declaring another target is rejected
+        let index = RETARGET_WINDOW + 1;
+        chain.chain[index].bits = POW_LIMIT_BITS;
+        for next in index..chain.len() {
+            chain.chain[next].prev_hash = chain.chain[next - 1].hash;
+            chain.chain[next].hash = chain.chain[next].get_hash();
+            chain.chain[next].mine().unwrap();
+        }
+        assert_eq!(chain.is_valid(), Err(Error::InvalidTarget(POW_LIMIT_BITS)));
+    }
+
+    #[test]
+    fn test_get_balance() {
+        let mut chain = chain_with_funds();
+        let mut transaction = Transaction {
+            from: KEY1.1,
+            to: KEY2.3,
+            amount: "333.3".parse().unwrap(),
+            tips: "10.25".parse().unwrap(),
+            nonce: 0,
+            signature: MINT_KEY.3,
+        };
+        transaction.sign(&KEY1.2, &chain.chain_id).unwrap();
+        dbg!(&transaction,);
+        chain.add_transaction(transaction).unwrap();
+        chain.mine_transaction(&KEY2.3).unwrap();
+        let balance1 = chain.get_balance(&KEY1.3).unwrap();
+        let balance2 = chain.get_balance(&KEY2.3).unwrap();
+        dbg!(&chain.chain);
+        assert_eq!(balance1, "99656.45".parse().unwrap());
+        assert_eq!(balance2, "443.55".parse().unwrap());
+        assert_eq!(chain.is_valid(), Ok(()));
+    }
+
+    #[test]
+    fn test_add_transaction_rejected() {
+        let mut chain = chain_with_funds();
+        let amount = Amount::from_coins(10).unwrap();
+        let mut transaction = Transaction::new(KEY2.1, KEY1.3, amount, None, 0);
+        transaction.sign(&KEY2.2, &chain.chain_id).unwrap();
+        assert_eq!(
+            chain.add_transaction(transaction),
+            Err(Error::InsufficientBalance {
+                balance: Amount::ZERO,
+                required: amount,
+            })
+        );
+        assert!(chain.mempool.is_empty());
+    }
+
+    #[test]
+    fn test_replay_rejected() {
+        let mut chain = chain_with_funds();
+        let amount = Amount::from_coins(5).unwrap();
+        let mut transaction = Transaction::new(KEY1.1, KEY2.3, amount, None, 0);
+        transaction.sign(&KEY1.2, &chain.chain_id).unwrap();
+        chain.add_transaction(transaction.clone()).unwrap();
+        assert_eq!(
+            chain.add_transaction(transaction.clone()),
+            Err(Error::DuplicateTransaction)
+        );
+        assert_eq!(chain.next_nonce(&KEY1.1), 1);
+        chain.mine_transaction(&KEY2.3).unwrap();
+        assert_eq!(chain.nonce_of(&KEY1.1), 1);
+
+        // the mined transfer cannot be queued nor mined again
+        assert!(chain.add_transaction(transaction.clone()).is_err());
+        let mut reward = Transaction::new(
+            chain.mint_address,
+            KEY2.3,
+            chain.reward,
+            None,
+            chain.len() as u64,
+        );
+        reward.sign(&MINT_KEY_PAIR, &chain.chain_id).unwrap();
+        let mut replay = Block::new(chrono::Utc::now(), vec![transaction.clone(), reward]);
+        replay.prev_hash = chain.get_last_block().hash;
+        replay.bits = chain.state.bits;
+        replay.hash = replay.get_hash();
+        replay.mine().unwrap();
+        assert_eq!(
+            chain.state.validate_block(&chain.params(), &replay),
+            Err(Error::InvalidNonce {
+                expected: 1,
+                found: 0
+            })
+        );
+
+        // nonces must not skip ahead
+        let mut skipped = Transaction::new(KEY1.1, KEY2.3, amount, None, 2);
+        skipped.sign(&KEY1.2, &chain.chain_id).unwrap();
+        assert!(matches!(
+            chain.add_transaction(skipped),
+            Err(Error::InvalidNonce { .. })
+        ));
+
+        // a transfer signed for another chain does not verify here
+        let mut foreign = Transaction::new(KEY1.1, KEY2.3, amount, None, 1);
+        foreign.sign(&KEY1.2, &BlockChain::new().chain_id).unwrap();
+        assert_eq!(chain.add_transaction(foreign), Err(Error::BadSignature));
+    }
+
+    #[test]
+    fn test_is_valid_prev_hash() {
+        let mut chain = BlockChain::new();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        assert_eq!(chain.is_valid(), Ok(()));
+        chain.chain[1].prev_hash = chain.chain[1].hash;
+        chain.chain[1].hash = chain.chain[1].get_hash();
+        assert_eq!(chain.is_valid(), Err(Error::InvalidPrevHash));
+    }
+
+    #[test]
+    fn test_is_valid_merkle_root() {
+        let mut chain = BlockChain::new();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        // rehashing the header alone cannot hide a changed transaction
+        chain.chain[1].data[0].to = KEY2.3;
+        chain.chain[1].hash = chain.chain[1].get_hash();
+        assert_eq!(chain.is_valid(), Err(Error::InvalidMerkleRoot));
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = chain_with_funds().genesis;
+        let mut chain = BlockChain::create(dir.path(), &config).unwrap();
+        let mut transaction =
+            Transaction::new(KEY1.1, KEY2.3, Amount::from_coins(5).unwrap(), None, 0);
+        transaction.sign(&KEY1.2, &chain.chain_id).unwrap();
+        chain.add_transaction(transaction).unwrap();
+        chain.mine_transaction(&KEY2.3).unwrap();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        drop(chain);
+
+        let reopened = BlockChain::open(dir.path()).unwrap();
+        assert_eq!(reopened.len(), 3);
+        assert_eq!(reopened.genesis_hash(), chain_with_funds().genesis_hash());
+        assert_eq!(
+            reopened.get_balance(&KEY2.3).unwrap(),
+            Amount::from_coins(105).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_open_rejects_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut chain = BlockChain::create(dir.path(), &GenesisConfig::default()).unwrap();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        drop(chain);
+
+        // a different genesis config must not adopt the stored blocks
+        let config = chain_with_funds().genesis;
+        std::fs::write(dir.path().join(GENESIS_FILE), config.to_json().unwrap()).unwrap();
+        assert_eq!(
+            BlockChain::open(dir.path()).err(),
+            Some(Error::GenesisMismatch)
+        );
+    }
+
+    #[test]
+    fn test_export_import() {
+        let mut chain = chain_with_funds();
+        let mut transaction = Transaction::new(KEY1.1, KEY2.3, "1.5".parse().unwrap(), None, 0);
+        transaction.sign(&KEY1.2, &chain.chain_id).unwrap();
+        chain.add_transaction(transaction.clone()).unwrap();
+        chain.mine_transaction(&KEY2.3).unwrap();
+        transaction.nonce = 1;
+        transaction.sign(&KEY1.2, &chain.chain_id).unwrap();
+        chain.add_transaction(transaction).unwrap();
+
+        let json = chain.to_json().unwrap();
+        assert!(json.contains(&KEY1.1.to_hex()));
+        assert!(json.contains(&chain.chain[1].data[0].signature.to_hex()));
+        let imported = BlockChain::from_json(&json).unwrap();
+        assert!(imported == chain);
+        assert_eq!(imported.chain_id, chain.chain_id);
+        assert_eq!(imported.nonce_of(&KEY1.1), 1);
+        assert_eq!(imported.is_valid(), Ok(()));
+
+        let bytes = bincode::serialize(&chain).unwrap();
+        let imported: BlockChain = bincode::deserialize(&bytes).unwrap();
+        assert!(imported == chain);
+        assert_eq!(imported.is_valid(), Ok(()));
+
+        let json = json.replacen(
+            &format!("\"version\": {}", FORMAT_VERSION),
+            "\"version\": 99",
+            1,
+        );
+        assert_eq!(
+            BlockChain::from_json(&json).err(),
+            Some(Error::Serialize(Error::UnsupportedVersion(99).to_string()))
+        );
+    }
+
+    #[test]
+    fn test_import_rules() {
+        // blocks minting twice the reward of the genesis config
+        let mut chain = chain_with_funds();
+        let reward = chain.reward;
+        chain.reward = reward.checked_mul(2).unwrap();
+        chain.mine_transaction(&KEY2.3).unwrap();
+
+        let mut export = serde_json::to_value(&chain).unwrap();
+        assert!(matches!(
+            serde_json::from_value::<BlockChain>(export.clone()),
+            Err(e) if e.to_string().contains("differs from the genesis config")
+        ));
+        export["reward"] = serde_json::to_value(reward).unwrap();
+        assert!(matches!(
+            serde_json::from_value::<BlockChain>(export),
+            Err(e) if e.to_string() == Error::InvalidReward.to_string()
+        ));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::genesis::{Allocation, GenesisConfig};
+use crate::secp256k1::{KeyPair, PubKey, SecKey, Secp256K1};
+use crate::{Address, Amount};
+use k256::*;
+use std::sync::Mutex;
+
+/// secret key of the default mint, sha256 of `"bloc mint key"`
+pub const MINT_SECRET_HEX: &str =
+    "915e7a73223cddd1b511e084e3466b4647a9fe41467c8bd35bff34c7a0364e86";
+
+lazy_static! {
+    pub static ref REWARD: Amount = Amount::from_coins(100).expect("reward overflowed");
+    pub static ref ZEROKEY: String = "00000000000000000000000000000000000000000000000000000000000000000000000000000000".to_owned();
+    pub static ref SECP: Mutex<Secp256K1> = Mutex::new(Secp256K1::new());
+    // fixed so that every process derives the same default genesis block
+    pub static ref MINT_KEY: (SecKey, PubKey, KeyPair, ecdsa::Signature) = {
+        let secret = SecKey::from_hex(MINT_SECRET_HEX).expect("invalid mint secret key");
+        let key_pair = KeyPair::from(&secret);
+        let public = key_pair.to_public_key().expect("invalid mint key pair");
+        let raw_bytes = [123u8; 64];
+        let signature = secret.sign(&raw_bytes);
+        //dbg!(&secret.as_ref().len(), &secret.len());
+        //dbg!(&public.to_hex().len(), &public.serialize().len());
+        (secret, public, key_pair, signature)
+    };
+    pub static ref MINT_PRIVATE_KEY: SecKey = MINT_KEY.0.clone();
+    pub static ref MINT_PUBLIC_ADDRESS: PubKey = MINT_KEY.1;
+    pub static ref MINT_KEY_PAIR: KeyPair = MINT_KEY.2.clone();
+    pub static ref KEY1: (SecKey, PubKey, KeyPair, Address) = {
+        let (secret, public) = SECP.lock().unwrap().gen_keypair();
+        let key_pair = KeyPair::from(&secret);
+        (secret, public, key_pair, Address::from(&public))
+    };
+    pub static ref KEY2: (SecKey, PubKey, KeyPair, Address) = {
+        let (secret, public) = SECP.lock().unwrap().gen_keypair();
+        let key_pair = KeyPair::from(&secret);
+        (secret, public, key_pair, Address::from(&public))
+    };
+}
+
+/// the default genesis config crediting `coins` to the address of `KEY1`,
+/// for tests to spend from
+pub fn funded_genesis(coins: u64) -> GenesisConfig {
+    GenesisConfig {
+        allocations: vec![Allocation {
+            to: KEY1.3,
+            amount: Amount::from_coins(coins).expect("allocation overflowed"),
+        }],
+        ..Default::default()
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{amount::Amount, secp256k1};
+use std::fmt;
+
+/// reasons a ledger operation is rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// the sender cannot afford `amount + tips`
+    InsufficientBalance { balance: Amount, required: Amount },
+    /// the signature does not verify against the sender's public key
+    BadSignature,
+    /// the transaction moves nothing
+    ZeroAmount,
+    /// arithmetic on amounts overflowed
+    AmountOverflow,
+    /// the string is not a decimal amount
+    InvalidAmount(String),
+    /// the signing key pair does not own the `from` address
+    KeyMismatch,
+    /// sender or recipient is the all-zero key
+    NullAddress,
+    /// the address is malformed or fails its checksum
+    Address(String),
+    /// the block does not point to the hash of its predecessor
+    InvalidPrevHash,
+    /// the hash stored in the block does not match its content
+    InvalidHash,
+    /// the merkle root of the header does not match the transactions
+    InvalidMerkleRoot,
+    /// the block does not carry exactly one reward transaction
+    InvalidCoinbase,
+    /// the reward paid by the block is not the chain reward plus the tips
+    InvalidReward,
+    /// the compact target is malformed, above the proof of work limit or
+    /// not the one expected for the block
+    InvalidTarget(u32),
+    /// ledger data failed to (de)serialize
+    Serialize(String),
+    /// malformed key material
+    Key(String),
+    /// the genesis config is malformed
+    Genesis(String),
+    /// the stored genesis block was produced by another config
+    GenesisMismatch,
+    /// the serialized data uses an unknown format version
+    UnsupportedVersion(u32),
+    /// reading or writing a file failed
+    Io(String),
+    /// the transaction spends an output that does not exist or is spent
+    UnknownOutput,
+    /// an output is spent twice in a block or in the pending transactions
+    DoubleSpend,
+    /// the transaction kind does not match the model of the chain
+    WrongModel,
+    /// the transaction is already pending
+    DuplicateTransaction,
+    /// the pending transactions pay more than the transaction
+    MempoolFull,
+    /// the block is not after the median time of the blocks before it, or
+    /// too far in the future
+    InvalidTimestamp,
+    /// the block builds on a block that is not known
+    UnknownParent(bitcoin_hashes::sha256::Hash),
+    /// the block forks from the active chain at this height, too far below
+    /// its tip
+    StaleFork(u64),
+    /// the sequence number is replayed or skips ahead of the sender's account
+    InvalidNonce { expected: u64, found: u64 },
+    /// the keystore must be unlocked to use its keys
+    WalletLocked,
+    /// the password does not open the keystore
+    WrongPassword,
+    /// no key of the keystore has this name
+    UnknownKey(String),
+    /// a key of the keystore already has this name
+    DuplicateKey(String),
+    /// the keystore file is malformed or corrupted
+    Keystore(String),
+    /// the mnemonic phrase has an unknown word, a wrong length or a wrong
+    /// checksum
+    Mnemonic(String),
+    /// the derivation path is malformed
+    DerivationPath(String),
+    /// the extended key is malformed or derives no valid key
+    ExtendedKey(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InsufficientBalance { balance, required } => write!(
+                f,
+                "insufficient balance: {} available, {} required",
+                balance, required
+            ),
+            Error::BadSignature => write!(f, "signature does not match the sender"),
+            Error::ZeroAmount => write!(f, "transaction amount is zero"),
+            Error::AmountOverflow => write!(f, "amount overflowed"),
+            Error::InvalidAmount(s) => write!(f, "invalid amount: {:?}", s),
+            Error::KeyMismatch => write!(f, "key pair does not own the sender address"),
+            Error::NullAddress => write!(f, "sender or recipient is the null address"),
+            Error::Address(desc) => write!(f, "invalid address: {}", desc),
+            Error::InvalidPrevHash => write!(f, "block does not link to its predecessor"),
+            Error::InvalidHash => write!(f, "block hash does not match its content"),
+            Error::InvalidMerkleRoot => {
+                write!(f, "merkle root does not match the block transactions")
+            }
+            Error::InvalidCoinbase => write!(f, "block must carry exactly one reward transaction"),
+            Error::InvalidReward => write!(f, "block reward does not match reward plus tips"),
+            Error::InvalidTarget(bits) => write!(f, "invalid target bits {:#010x}", bits),
+            Error::Serialize(desc) => write!(f, "serialization failed: {}", desc),
+            Error::Key(desc) => write!(f, "invalid key: {}", desc),
+            Error::Genesis(desc) => write!(f, "invalid genesis config: {}", desc),
+            Error::GenesisMismatch => write!(f, "stored genesis block does not match the config"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            Error::Io(desc) => write!(f, "io error: {}", desc),
+            Error::UnknownOutput => write!(f, "spent output is unknown or already spent"),
+            Error::DoubleSpend => write!(f, "output is spent twice"),
+            Error::WrongModel => write!(f, "transaction does not match the chain model"),
+            Error::DuplicateTransaction => write!(f, "transaction already pending"),
+            Error::MempoolFull => write!(f, "mempool full of transactions paying more"),
+            Error::InvalidTimestamp => write!(f, "block timestamp out of range"),
+            Error::UnknownParent(hash) => write!(f, "unknown parent block {}", hash),
+            Error::StaleFork(height) => {
+                write!(f, "block forks at {}, too far below the tip", height)
+            }
+            Error::InvalidNonce { expected, found } => {
+                write!(f, "invalid nonce: expected {}, found {}", expected, found)
+            }
+            Error::WalletLocked => write!(f, "keystore is locked"),
+            Error::WrongPassword => write!(f, "wrong keystore password"),
+            Error::UnknownKey(name) => write!(f, "no key named {:?}", name),
+            Error::DuplicateKey(name) => write!(f, "a key named {:?} already exists", name),
+            Error::Keystore(desc) => write!(f, "invalid keystore: {}", desc),
+            Error::Mnemonic(desc) => write!(f, "invalid mnemonic: {}", desc),
+            Error::DerivationPath(desc) => write!(f, "invalid derivation path: {}", desc),
+            Error::ExtendedKey(desc) => write!(f, "invalid extended key: {}", desc),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Serialize(e.to_string())
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(e: secp256k1::Error) -> Self {
+        Error::Key(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e.to_string())
+    }
+}
@@ -0,0 +1,215 @@
+use crate::pow::{Target, DEFAULT_BITS};
+use crate::secp256k1::{KeyPair, PubKey, SecKey};
+use crate::utxo::{ChainModel, TxOut, UtxoTransaction};
+use crate::{block::Block, constant::*, transaction::Transaction, Address, Amount, Error};
+use bitcoin_hashes::{sha256, sha256::Hash as Sha256, Hash, HashEngine};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// coins credited to `to` by the genesis block
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Allocation {
+    pub to: Address,
+    pub amount: Amount,
+}
+
+/// parameters every node of a chain must agree on
+///
+/// the same config always produces a byte-identical genesis block and
+/// chain id, since its timestamp is fixed and ecdsa signing is deterministic
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct GenesisConfig {
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub allocations: Vec<Allocation>,
+    /// compact proof of work target of the first blocks
+    pub bits: u32,
+    /// milliseconds aimed for between two blocks
+    pub block_time: u32,
+    pub reward: Amount,
+    pub mint_key: SecKey,
+    #[serde(default)]
+    pub model: ChainModel,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        Self {
+            timestamp: Utc
+                .timestamp_opt(1_648_771_200, 0)
+                .single()
+                .expect("valid genesis timestamp"),
+            allocations: Vec::new(),
+            bits: DEFAULT_BITS,
+            block_time: 30000,
+            reward: *REWARD,
+            mint_key: MINT_KEY.0.clone(),
+            model: ChainModel::default(),
+        }
+    }
+}
+
+impl GenesisConfig {
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|e| Error::Genesis(e.to_string()))
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, Error> {
+        toml::from_str(toml).map_err(|e| Error::Genesis(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Genesis(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(|e| Error::Genesis(e.to_string()))
+    }
+
+    /// read a `.toml` config, anything else is parsed as json
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&content),
+            _ => Self::from_json(&content),
+        }
+    }
+
+    pub fn mint_address(&self) -> Result<PubKey, Error> {
+        KeyPair::from(&self.mint_key)
+            .to_public_key()
+            .map_err(|e| Error::Key(e.to_string()))
+    }
+
+    /// block 0, carrying one mint-signed transaction per allocation, or a
+    /// single coinbase with one output per allocation on a utxo chain
+    pub fn genesis_block(&self) -> Result<Block, Error> {
+        Target::from_bits(self.bits)?;
+        if self
+            .allocations
+            .iter()
+            .any(|allocation| allocation.amount.is_zero())
+        {
+            return Err(Error::ZeroAmount);
+        }
+        let mut block = match self.model {
+            ChainModel::Account => Block::new(self.timestamp, self.allocation_transactions()?),
+            ChainModel::Utxo => Block::new_utxo(self.timestamp, self.allocation_coinbase()?),
+        };
+        block.bits = self.bits;
+        block.hash = block.get_hash();
+        Ok(block)
+    }
+
+    fn allocation_coinbase(&self) -> Result<Vec<UtxoTransaction>, Error> {
+        if self.allocations.is_empty() {
+            return Ok(Vec::new());
+        }
+        let outputs = self
+            .allocations
+            .iter()
+            .map(|allocation| TxOut {
+                to: allocation.to,
+                amount: allocation.amount,
+            })
+            .collect();
+        let mut coinbase = UtxoTransaction::coinbase(0, outputs);
+        coinbase.sign(&KeyPair::from(&self.mint_key))?;
+        Ok(vec![coinbase])
+    }
+
+    fn allocation_transactions(&self) -> Result<Vec<Transaction>, Error> {
+        let mint = KeyPair::from(&self.mint_key);
+        let mint_address = self.mint_address()?;
+        // the chain id hashes this block, so allocations are signed for the
+        // all-zero id; they are never replayed since only block 0 holds them
+        let chain_id = Sha256::from_inner([0u8; 32]);
+        let mut data = Vec::with_capacity(self.allocations.len());
+        for allocation in self.allocations.iter() {
+            let mut trans =
+                Transaction::new(mint_address, allocation.to, allocation.amount, None, 0);
+            trans.sign(&mint, &chain_id)?;
+            data.push(trans);
+        }
+        Ok(data)
+    }
+
+    /// identifies the chain by its genesis block and consensus parameters
+    pub fn chain_id(&self) -> Result<Sha256, Error> {
+        let genesis = self.genesis_block()?;
+        let mut hash_engine = sha256::HashEngine::default();
+        hash_engine.input(&genesis.hash);
+        hash_engine.input(&bincode::serialize(&self.bits)?);
+        hash_engine.input(&bincode::serialize(&self.block_time)?);
+        hash_engine.input(&bincode::serialize(&self.reward)?);
+        hash_engine.input(&bincode::serialize(&self.model)?);
+        Ok(sha256::Hash::from_engine(hash_engine))
+    }
+}
+
+#[cfg(test)]
+mod test_genesis {
+    use super::*;
+    use crate::secp256k1::Secp256K1;
+
+    fn config() -> GenesisConfig {
+        let mut secp = Secp256K1::new();
+        let (_, pub1) = secp.gen_keypair();
+        let (_, pub2) = secp.gen_keypair();
+        GenesisConfig {
+            allocations: vec![
+                Allocation {
+                    to: pub1.into(),
+                    amount: Amount::from_coins(500).unwrap(),
+                },
+                Allocation {
+                    to: pub2.into(),
+                    amount: "0.25".parse().unwrap(),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let config = config();
+        let block = config.genesis_block().unwrap();
+        assert_eq!(block, config.clone().genesis_block().unwrap());
+        assert_eq!(block.data.len(), 2);
+        assert_eq!(block.data[0].from, *MINT_PUBLIC_ADDRESS);
+        assert_eq!(config.chain_id().unwrap(), config.chain_id().unwrap());
+
+        let mut other = config.clone();
+        other.reward = Amount::from_coins(1).unwrap();
+        assert_eq!(other.genesis_block().unwrap(), block);
+        assert_ne!(other.chain_id().unwrap(), config.chain_id().unwrap());
+    }
+
+    #[test]
+    fn test_json_toml() {
+        let config = config();
+        let json = config.to_json().unwrap();
+        let toml = config.to_toml().unwrap();
+        assert_eq!(GenesisConfig::from_json(&json).unwrap(), config);
+        assert_eq!(GenesisConfig::from_toml(&toml).unwrap(), config);
+
+        let json = r#"{
+            "timestamp": "2022-04-01T00:00:00Z",
+            "bits": 536936447,
+            "block_time": 30000,
+            "reward": "100",
+            "mint_key": "915e7a73223cddd1b511e084e3466b4647a9fe41467c8bd35bff34c7a0364e86"
+        }"#;
+        assert_eq!(
+            GenesisConfig::from_json(json).unwrap(),
+            GenesisConfig::default()
+        );
+        assert!(matches!(
+            GenesisConfig::from_json("{}"),
+            Err(Error::Genesis(_))
+        ));
+    }
+}
@@ -0,0 +1,664 @@
+//! keys derived from one backed up secret: BIP-39 mnemonics encode the
+//! entropy as english words and stretch them into a seed, BIP-32 extended
+//! keys derive a tree of secp256k1 keys from that seed along paths such as
+//! `m/44'/0'/0'/0/1`
+use crate::secp256k1::{PubKey, SecKey, ToHex};
+use crate::Error;
+use bitcoin_hashes::{hash160, hmac, sha512, Hash, HashEngine};
+use k256::elliptic_curve::ff::PrimeField;
+use k256::elliptic_curve::group::Group;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{FieldBytes, ProjectivePoint, PublicKey, Scalar, SecretKey};
+use rand_core::{OsRng, RngCore};
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+/// child numbers from this one up derive hardened children, which cannot be
+/// derived from the parent public key
+pub const HARDENED: u32 = 1 << 31;
+
+/// version prefix of serialized extended private keys, `xprv`
+pub const XPRV_VERSION: [u8; 4] = [0x04, 0x88, 0xad, 0xe4];
+/// version prefix of serialized extended public keys, `xpub`
+pub const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+
+// hmac key of the master key derivation
+const MASTER_KEY: &[u8] = b"Bitcoin seed";
+// version, depth, parent fingerprint, child number, chain code and key
+const EXTENDED_LEN: usize = 4 + 1 + 4 + 4 + 32 + 33;
+
+/// a BIP-39 phrase of 12, 15, 18, 21 or 24 english words
+#[derive(Clone, PartialEq)]
+pub struct Mnemonic(bip39::Mnemonic);
+
+// the words are the secret, only their count is shown
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mnemonic({} words)", self.word_count())
+    }
+}
+
+impl Mnemonic {
+    /// a phrase of `word_count` words encoding fresh random entropy
+    pub fn generate(word_count: usize) -> Result<Self, Error> {
+        if !(12..=24).contains(&word_count) || !word_count.is_multiple_of(3) {
+            return Err(Error::Mnemonic(format!(
+                "{} words, must be 12, 15, 18, 21 or 24",
+                word_count
+            )));
+        }
+        let mut entropy = Zeroizing::new([0u8; 32]);
+        let len = word_count / 3 * 4;
+        OsRng.fill_bytes(&mut entropy[..len]);
+        Self::from_entropy(&entropy[..len])
+    }
+
+    /// the phrase encoding `entropy` of 16 to 32 bytes
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, Error> {
+        bip39::Mnemonic::from_entropy(entropy)
+            .map(Self)
+            .map_err(|e| Error::Mnemonic(e.to_string()))
+    }
+
+    /// check the words and the checksum of `phrase`, a word mistyped or
+    /// swapped is rejected
+    pub fn parse(phrase: &str) -> Result<Self, Error> {
+        bip39::Mnemonic::parse(phrase)
+            .map(Self)
+            .map_err(|e| Error::Mnemonic(e.to_string()))
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.0.word_count()
+    }
+
+    /// the 64 bytes seed of the phrase, salted with an optional passphrase
+    pub fn to_seed(&self, passphrase: &str) -> Zeroizing<[u8; 64]> {
+        Zeroizing::new(self.0.to_seed(passphrase))
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Self::parse(s)
+    }
+}
+
+/// child numbers from the master key down to a key, written like
+/// `m/0'/1/2h`, where `'` or `h` marks a hardened child
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    pub fn new(children: Vec<u32>) -> Self {
+        Self(children)
+    }
+
+    pub fn children(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return Err(Error::DerivationPath(format!(
+                "{:?} does not start with m",
+                s
+            )));
+        }
+        parts
+            .map(|part| {
+                let (index, hardened) = match part.strip_suffix(['\'', 'h']) {
+                    Some(index) => (index, HARDENED),
+                    None => (part, 0),
+                };
+                index
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|index| *index < HARDENED)
+                    .map(|index| index | hardened)
+                    .ok_or_else(|| Error::DerivationPath(format!("invalid child {:?}", part)))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for child in &self.0 {
+            match child & HARDENED {
+                0 => write!(f, "/{}", child)?,
+                _ => write!(f, "/{}'", child & !HARDENED)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// a secret key of the tree along with the chain code deriving its children
+#[derive(Clone, PartialEq)]
+pub struct ExtendedPrivKey {
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    secret: SecKey,
+}
+
+// the chain code derives the children as much as the secret does, neither
+// is shown
+impl fmt::Debug for ExtendedPrivKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedPrivKey")
+            .field("depth", &self.depth)
+            .field("parent_fingerprint", &self.parent_fingerprint)
+            .field("child_number", &self.child_number)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ExtendedPrivKey {
+    /// the master key of the tree grown from `seed`
+    pub fn from_seed(seed: &[u8]) -> Result<Self, Error> {
+        let (secret, chain_code) = split(hmac_sha512(MASTER_KEY, seed));
+        let secret = SecKey::new(&secret)
+            .map_err(|_| Error::ExtendedKey("seed derives an invalid master key".to_owned()))?;
+        Ok(Self {
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: 0,
+            chain_code,
+            secret,
+        })
+    }
+
+    /// the master key of the tree grown from `mnemonic` and `passphrase`
+    pub fn from_mnemonic(mnemonic: &Mnemonic, passphrase: &str) -> Result<Self, Error> {
+        Self::from_seed(mnemonic.to_seed(passphrase).as_slice())
+    }
+
+    pub fn secret_key(&self) -> SecKey {
+        self.secret.clone()
+    }
+
+    pub fn public_key(&self) -> PubKey {
+        self.to_public().key
+    }
+
+    /// the extended public key deriving the same non-hardened children
+    pub fn to_public(&self) -> ExtendedPubKey {
+        let point = secret_key(&self.secret).public_key();
+        ExtendedPubKey {
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            key: pub_key(&point),
+        }
+    }
+
+    /// the first 4 bytes of the hash160 of the public key, the parent
+    /// fingerprint of the children
+    pub fn fingerprint(&self) -> [u8; 4] {
+        self.to_public().fingerprint()
+    }
+
+    /// the child numbered `child`, hardened from `HARDENED` up
+    pub fn derive_child(&self, child: u32) -> Result<Self, Error> {
+        let depth = self.depth.checked_add(1).ok_or_else(too_deep)?;
+        let mut data = Zeroizing::new(Vec::with_capacity(37));
+        if child >= HARDENED {
+            data.push(0);
+            data.extend_from_slice(secret_bytes(&self.secret));
+        } else {
+            data.extend_from_slice(self.public_key().as_ref());
+        }
+        data.extend_from_slice(&child.to_be_bytes());
+        let (tweak, chain_code) = split(hmac_sha512(&self.chain_code, &data));
+        let parent = scalar(secret_bytes(&self.secret)).expect("extended keys hold valid scalars");
+        let secret = scalar(&tweak)
+            .and_then(|tweak| sec_key(&(tweak + parent)))
+            .ok_or_else(|| invalid_child(child))?;
+        Ok(Self {
+            depth,
+            parent_fingerprint: self.fingerprint(),
+            child_number: child,
+            chain_code,
+            secret,
+        })
+    }
+
+    /// the key at the end of `path`, from this key down
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, Error> {
+        path.children()
+            .iter()
+            .try_fold(self.clone(), |key, child| key.derive_child(*child))
+    }
+}
+
+impl fmt::Display for ExtendedPrivKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut key = [0u8; 33];
+        key[1..].copy_from_slice(secret_bytes(&self.secret));
+        let encoded = encode(
+            XPRV_VERSION,
+            self.depth,
+            self.parent_fingerprint,
+            self.child_number,
+            &self.chain_code,
+            &key,
+        );
+        f.write_str(&encoded)
+    }
+}
+
+impl FromStr for ExtendedPrivKey {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let (version, depth, parent_fingerprint, child_number, chain_code, key) = decode(s)?;
+        if version != XPRV_VERSION {
+            return Err(Error::ExtendedKey("not an xprv key".to_owned()));
+        }
+        let secret = match key.split_first() {
+            Some((0, secret)) => SecKey::try_from(secret).ok(),
+            _ => None,
+        }
+        .ok_or_else(|| Error::ExtendedKey("invalid secret key".to_owned()))?;
+        check_parent(depth, parent_fingerprint, child_number)?;
+        Ok(Self {
+            depth,
+            parent_fingerprint,
+            child_number,
+            chain_code,
+            secret,
+        })
+    }
+}
+
+/// a public key of the tree along with the chain code deriving its
+/// non-hardened children, for watching addresses without their secrets
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendedPubKey {
+    pub depth: u8,
+    pub parent_fingerprint: [u8; 4],
+    pub child_number: u32,
+    pub chain_code: [u8; 32],
+    key: PubKey,
+}
+
+impl ExtendedPubKey {
+    pub fn public_key(&self) -> PubKey {
+        self.key
+    }
+
+    /// the first 4 bytes of the hash160 of the public key
+    pub fn fingerprint(&self) -> [u8; 4] {
+        let mut fingerprint = [0u8; 4];
+        fingerprint.copy_from_slice(&hash160::Hash::hash(self.key.as_ref())[..4]);
+        fingerprint
+    }
+
+    /// the non-hardened child numbered `child`
+    pub fn derive_child(&self, child: u32) -> Result<Self, Error> {
+        if child >= HARDENED {
+            return Err(Error::ExtendedKey(format!(
+                "hardened child {}' of a public key",
+                child & !HARDENED
+            )));
+        }
+        let depth = self.depth.checked_add(1).ok_or_else(too_deep)?;
+        let mut data = Vec::with_capacity(37);
+        data.extend_from_slice(self.key.as_ref());
+        data.extend_from_slice(&child.to_be_bytes());
+        let (tweak, chain_code) = split(hmac_sha512(&self.chain_code, &data));
+        let parent = public_key(&self.key).to_projective();
+        let point = scalar(&tweak)
+            .map(|tweak| ProjectivePoint::GENERATOR * tweak + parent)
+            .filter(|point| !bool::from(point.is_identity()))
+            .and_then(|point| PublicKey::from_affine(point.to_affine()).ok())
+            .ok_or_else(|| invalid_child(child))?;
+        Ok(Self {
+            depth,
+            parent_fingerprint: self.fingerprint(),
+            child_number: child,
+            chain_code,
+            key: pub_key(&point),
+        })
+    }
+
+    /// the key at the end of `path`, which must not hold hardened children
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, Error> {
+        path.children()
+            .iter()
+            .try_fold(self.clone(), |key, child| key.derive_child(*child))
+    }
+}
+
+impl fmt::Display for ExtendedPubKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut key = [0u8; 33];
+        key.copy_from_slice(self.key.as_ref());
+        let encoded = encode(
+            XPUB_VERSION,
+            self.depth,
+            self.parent_fingerprint,
+            self.child_number,
+            &self.chain_code,
+            &key,
+        );
+        f.write_str(&encoded)
+    }
+}
+
+impl FromStr for ExtendedPubKey {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let (version, depth, parent_fingerprint, child_number, chain_code, key) = decode(s)?;
+        if version != XPUB_VERSION {
+            return Err(Error::ExtendedKey("not an xpub key".to_owned()));
+        }
+        let key =
+            PubKey::new(&key).map_err(|_| Error::ExtendedKey("invalid public key".to_owned()))?;
+        check_parent(depth, parent_fingerprint, child_number)?;
+        Ok(Self {
+            depth,
+            parent_fingerprint,
+            child_number,
+            chain_code,
+            key,
+        })
+    }
+}
+
+// the fields of a serialized extended key
+type Fields = ([u8; 4], u8, [u8; 4], u32, [u8; 32], [u8; 33]);
+
+fn encode(
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: &[u8; 32],
+    key: &[u8; 33],
+) -> String {
+    let mut data = Zeroizing::new(Vec::with_capacity(EXTENDED_LEN));
+    data.extend_from_slice(&version);
+    data.push(depth);
+    data.extend_from_slice(&parent_fingerprint);
+    data.extend_from_slice(&child_number.to_be_bytes());
+    data.extend_from_slice(chain_code);
+    data.extend_from_slice(key);
+    bs58::encode(data.as_slice()).with_check().into_string()
+}
+
+fn decode(s: &str) -> Result<Fields, Error> {
+    let data = bs58::decode(s)
+        .with_check(None)
+        .into_vec()
+        .map(Zeroizing::new)
+        .map_err(|e| Error::ExtendedKey(e.to_string()))?;
+    if data.len() != EXTENDED_LEN {
+        return Err(Error::ExtendedKey(format!(
+            "{} bytes, expected {}",
+            data.len(),
+            EXTENDED_LEN
+        )));
+    }
+    let mut fields: Fields = ([0; 4], data[4], [0; 4], 0, [0; 32], [0; 33]);
+    fields.0.copy_from_slice(&data[..4]);
+    fields.2.copy_from_slice(&data[5..9]);
+    fields.3 = u32::from_be_bytes([data[9], data[10], data[11], data[12]]);
+    fields.4.copy_from_slice(&data[13..45]);
+    fields.5.copy_from_slice(&data[45..]);
+    Ok(fields)
+}
+
+// the master key has neither parent nor child number
+fn check_parent(depth: u8, parent_fingerprint: [u8; 4], child_number: u32) -> Result<(), Error> {
+    if depth == 0 && (parent_fingerprint != [0; 4] || child_number != 0) {
+        return Err(Error::ExtendedKey(
+            "master key with a parent or a child number".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> Zeroizing<[u8; 64]> {
+    let mut engine = hmac::HmacEngine::<sha512::Hash>::new(key);
+    engine.input(data);
+    Zeroizing::new(hmac::Hmac::<sha512::Hash>::from_engine(engine).into_inner())
+}
+
+// the left half is the key or its tweak, the right half the chain code
+fn split(hash: Zeroizing<[u8; 64]>) -> (Zeroizing<[u8; 32]>, [u8; 32]) {
+    let mut left = Zeroizing::new([0u8; 32]);
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&hash[..32]);
+    right.copy_from_slice(&hash[32..]);
+    (left, right)
+}
+
+// `None` when the bytes are not below the group order
+fn scalar(bytes: &[u8; 32]) -> Option<Scalar> {
+    Scalar::from_repr(FieldBytes::from(*bytes)).into()
+}
+
+// `None` for the zero scalar, which is no key
+fn sec_key(scalar: &Scalar) -> Option<SecKey> {
+    SecKey::try_from(&scalar.to_bytes()[..]).ok()
+}
+
+fn secret_key(key: &SecKey) -> SecretKey {
+    SecretKey::from_be_bytes(secret_bytes(key)).expect("extended keys hold valid scalars")
+}
+
+fn public_key(key: &PubKey) -> PublicKey {
+    PublicKey::from_sec1_bytes(key.as_ref()).expect("extended keys hold valid points")
+}
+
+fn pub_key(point: &PublicKey) -> PubKey {
+    PubKey::try_from(point.to_encoded_point(true).as_bytes())
+        .expect("k256 public keys are points of the curve")
+}
+
+fn invalid_child(child: u32) -> Error {
+    Error::ExtendedKey(format!("child {} derives an invalid key", child))
+}
+
+fn too_deep() -> Error {
+    Error::ExtendedKey("tree deeper than 255 levels".to_owned())
+}
+
+fn secret_bytes(key: &SecKey) -> &[u8; 32] {
+    key.to_bytes().try_into().expect("secret keys are 32 bytes")
+}
+
+#[cfg(test)]
+mod test_hd {
+    use super::*;
+    use crate::secp256k1::decode_hex;
+
+    // https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki#test-vectors
+    fn check_vector(seed: &str, chains: &[(&str, &str, &str)]) {
+        let master = ExtendedPrivKey::from_seed(&decode_hex(seed).unwrap()).unwrap();
+        for (path, xprv, xpub) in chains {
+            let key = master.derive_path(&path.parse().unwrap()).unwrap();
+            assert_eq!(&key.to_string(), xprv, "{}", path);
+            assert_eq!(&key.to_public().to_string(), xpub, "{}", path);
+            assert_eq!(xprv.parse::<ExtendedPrivKey>().unwrap(), key);
+            assert_eq!(xpub.parse::<ExtendedPubKey>().unwrap(), key.to_public());
+        }
+    }
+
+    #[test]
+    fn test_bip32() {
+        check_vector(
+            "000102030405060708090a0b0c0d0e0f",
+            &[
+                ("m", "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi", "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8"),
+                ("m/0'", "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7", "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw"),
+                ("m/0'/1", "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs", "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ"),
+                ("m/0'/1/2'", "xprv9z4pot5VBttmtdRTWfWQmoH1taj2axGVzFqSb8C9xaxKymcFzXBDptWmT7FwuEzG3ryjH4ktypQSAewRiNMjANTtpgP4mLTj34bhnZX7UiM", "xpub6D4BDPcP2GT577Vvch3R8wDkScZWzQzMMUm3PWbmWvVJrZwQY4VUNgqFJPMM3No2dFDFGTsxxpG5uJh7n7epu4trkrX7x7DogT5Uv6fcLW5"),
+                ("m/0'/1/2'/2", "xprvA2JDeKCSNNZky6uBCviVfJSKyQ1mDYahRjijr5idH2WwLsEd4Hsb2Tyh8RfQMuPh7f7RtyzTtdrbdqqsunu5Mm3wDvUAKRHSC34sJ7in334", "xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV"),
+                ("m/0'/1/2'/2/1000000000", "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76", "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy"),
+            ],
+        );
+        check_vector(
+            "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a29f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542",
+            &[
+                ("m", "xprv9s21ZrQH143K31xYSDQpPDxsXRTUcvj2iNHm5NUtrGiGG5e2DtALGdso3pGz6ssrdK4PFmM8NSpSBHNqPqm55Qn3LqFtT2emdEXVYsCzC2U", "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB"),
+                ("m/0", "xprv9vHkqa6EV4sPZHYqZznhT2NPtPCjKuDKGY38FBWLvgaDx45zo9WQRUT3dKYnjwih2yJD9mkrocEZXo1ex8G81dwSM1fwqWpWkeS3v86pgKt", "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH"),
+                ("m/0/2147483647'", "xprv9wSp6B7kry3Vj9m1zSnLvN3xH8RdsPP1Mh7fAaR7aRLcQMKTR2vidYEeEg2mUCTAwCd6vnxVrcjfy2kRgVsFawNzmjuHc2YmYRmagcEPdU9", "xpub6ASAVgeehLbnwdqV6UKMHVzgqAG8Gr6riv3Fxxpj8ksbH9ebxaEyBLZ85ySDhKiLDBrQSARLq1uNRts8RuJiHjaDMBU4Zn9h8LZNnBC5y4a"),
+                ("m/0/2147483647'/1/2147483646'/2", "xprvA2nrNbFZABcdryreWet9Ea4LvTJcGsqrMzxHx98MMrotbir7yrKCEXw7nadnHM8Dq38EGfSh6dqA9QWTyefMLEcBYJUuekgW4BYPJcr9E7j", "xpub6FnCn6nSzZAw5Tw7cgR9bi15UV96gLZhjDstkXXxvCLsUXBGXPdSnLFbdpq8p9HmGsApME5hQTZ3emM2rnY5agb9rXpVGyy3bdW6EEgAtqt"),
+            ],
+        );
+        // leading zeros of the secret keys are kept
+        check_vector(
+            "4b381541583be4423346c643850da4b320e46a87ae3d2a4e6da11eba819cd4acba45d239319ac14f863b8d5ab5a0d0c64d2e8a1e7d1457df2e5a3c51c73235be",
+            &[
+                ("m", "xprv9s21ZrQH143K25QhxbucbDDuQ4naNntJRi4KUfWT7xo4EKsHt2QJDu7KXp1A3u7Bi1j8ph3EGsZ9Xvz9dGuVrtHHs7pXeTzjuxBrCmmhgC6", "xpub661MyMwAqRbcEZVB4dScxMAdx6d4nFc9nvyvH3v4gJL378CSRZiYmhRoP7mBy6gSPSCYk6SzXPTf3ND1cZAceL7SfJ1Z3GC8vBgp2epUt13"),
+                ("m/0'", "xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L", "xpub68NZiKmJWnxxS6aaHmn81bvJeTESw724CRDs6HbuccFQN9Ku14VQrADWgqbhhTHBaohPX4CjNLf9fq9MYo6oDaPPLPxSb7gwQN3ih19Zm4Y"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_public_derivation() {
+        let seed = decode_hex("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedPrivKey::from_seed(&seed).unwrap();
+        let account = master.derive_path(&"m/0'/1/2'".parse().unwrap()).unwrap();
+        let path = "m/2/1000000000".parse().unwrap();
+        let child = account.derive_path(&path).unwrap();
+        let watched = account.to_public().derive_path(&path).unwrap();
+        assert_eq!(watched, child.to_public());
+        assert_eq!(watched.public_key(), child.public_key());
+
+        // the derived keys sign like any other
+        let msg = b"hello world bloc";
+        let signature = child.secret_key().sign(msg);
+        assert!(watched.public_key().verify(msg, &signature).is_ok());
+
+        assert!(matches!(
+            account.to_public().derive_child(HARDENED),
+            Err(Error::ExtendedKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_path() {
+        let path: DerivationPath = "m/44'/0h/0'/1/23".parse().unwrap();
+        assert_eq!(path.children(), &[44 | HARDENED, HARDENED, HARDENED, 1, 23]);
+        assert_eq!(path.to_string(), "m/44'/0'/0'/1/23");
+        assert_eq!(
+            "m".parse::<DerivationPath>().unwrap(),
+            DerivationPath::default()
+        );
+        for bad in ["", "44'/0'", "m/", "m/x", "m/-1", "m/2147483648", "m/1''"] {
+            assert!(
+                matches!(bad.parse::<DerivationPath>(), Err(Error::DerivationPath(_))),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_bad_extended_key() {
+        let xprv = "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi";
+        let xpub = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+        // a typo breaks the checksum
+        let typo = xprv.replacen('Q', "R", 1);
+        assert!(matches!(
+            typo.parse::<ExtendedPrivKey>(),
+            Err(Error::ExtendedKey(_))
+        ));
+        assert!(xpub.parse::<ExtendedPrivKey>().is_err());
+        assert!(xprv.parse::<ExtendedPubKey>().is_err());
+        assert!("xprv".parse::<ExtendedPrivKey>().is_err());
+        assert!("".parse::<ExtendedPubKey>().is_err());
+    }
+
+    // https://github.com/trezor/python-mnemonic/blob/master/vectors.json
+    #[test]
+    fn test_bip39() {
+        let vectors = [
+            (
+                "00000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+                "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth title",
+                "bc09fca1804f7e69da93c2f2028eb238c227f2e9dda30cd63699232578480a4021b146ad717fbb7e451ce9eb835f43620bf5c514db0f8add49f5d121449d3e87",
+                "xprv9s21ZrQH143K3Y1sd2XVu9wtqxJRvybCfAetjUrMMco6r3v9qZTBeXiBZkS8JxWbcGJZyio8TrZtm6pkbzG8SYt1sxwNLh3Wx7to5pgiVFU",
+            ),
+            (
+                "68a79eaca2324873eacc50cb9c6eca8cc68ea5d936f98787c60c7ebc74e6ce7c",
+                "hamster diagram private dutch cause delay private meat slide toddler razor book happy fancy gospel tennis maple dilemma loan word shrug inflict delay length",
+                "64c87cde7e12ecf6704ab95bb1408bef047c22db4cc7491c4271d170a1b213d20b385bc1588d9c7b38f1b39d415665b8a9030c9ec653d75e65f847d8fc1fc440",
+                "xprv9s21ZrQH143K2XTAhys3pMNcGn261Fi5Ta2Pw8PwaVPhg3D8DWkzWQwjTJfskj8ofb81i9NP2cUNKxwjueJHHMQAnxtivTA75uUFqPFeWzk",
+            ),
+        ];
+        for (entropy, phrase, seed, xprv) in vectors {
+            let mnemonic = Mnemonic::from_entropy(&decode_hex(entropy).unwrap()).unwrap();
+            assert_eq!(mnemonic.to_string(), phrase);
+            assert_eq!(phrase.parse::<Mnemonic>().unwrap(), mnemonic);
+            assert_eq!(
+                mnemonic.to_seed("TREZOR").as_slice(),
+                decode_hex(seed).unwrap().as_slice()
+            );
+            let master = ExtendedPrivKey::from_mnemonic(&mnemonic, "TREZOR").unwrap();
+            assert_eq!(master.to_string(), xprv);
+        }
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        for words in [12, 15, 18, 21, 24] {
+            let mnemonic = Mnemonic::generate(words).unwrap();
+            assert_eq!(mnemonic.word_count(), words);
+            assert_eq!(Mnemonic::parse(&mnemonic.to_string()).unwrap(), mnemonic);
+        }
+        assert!(Mnemonic::generate(13).is_err());
+        assert!(Mnemonic::generate(27).is_err());
+
+        // a swapped word breaks the checksum, an unknown one is not in the list
+        let swapped = "about abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        let unknown = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abut";
+        let short = "abandon abandon about";
+        for bad in [swapped, unknown, short] {
+            assert!(
+                matches!(Mnemonic::parse(bad), Err(Error::Mnemonic(_))),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_debug_redacted() {
+        let mnemonic = Mnemonic::generate(12).unwrap();
+        let master = ExtendedPrivKey::from_mnemonic(&mnemonic, "").unwrap();
+        let secret = master.secret_key();
+        let printed = format!("{:?} {:?} {:?}", mnemonic, master, secret);
+        assert_eq!(format!("{:?}", secret), "SecKey(..)");
+        assert!(!printed.contains(&secret.to_hex()), "{}", printed);
+        assert!(!printed.contains(&format!("{:?}", master.chain_code)));
+        assert_eq!(format!("{:?}", mnemonic), "Mnemonic(12 words)");
+    }
+}
@@ -0,0 +1,29 @@
+//! platform-independent core of bloc: the ledger, its crypto primitives and
+//! validation rules, free of any front-end dependency
+#[macro_use]
+extern crate lazy_static;
+
+pub mod address;
+pub mod amount;
+pub mod block;
+pub mod blockchain;
+pub mod constant;
+pub mod error;
+pub mod genesis;
+pub mod hd;
+pub mod mempool;
+pub mod merkle;
+pub mod miner;
+pub mod pow;
+pub mod secp256k1;
+pub mod store;
+pub mod template;
+pub mod transaction;
+pub mod tree;
+pub mod utxo;
+pub mod validation;
+pub mod wallet;
+
+pub use address::Address;
+pub use amount::Amount;
+pub use error::Error;
@@ -0,0 +1,445 @@
+use crate::block::Block;
+use crate::secp256k1::PubKey;
+use crate::transaction::Transaction;
+use crate::validation::ChainState;
+use crate::{Address, Amount, Error};
+use bitcoin_hashes::sha256::Hash as Sha256;
+use chrono::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+/// number of transactions a pool holds before evicting the cheapest ones
+pub const MAX_POOL_SIZE: usize = 5000;
+/// milliseconds a transaction waits to be mined before it is dropped
+pub const POOL_EXPIRY: i64 = 24 * 60 * 60 * 1000;
+
+/// a pending transaction with what the pool orders it by
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolEntry {
+    pub trans: Transaction,
+    pub txid: Sha256,
+    /// serialized size in bytes
+    pub size: u64,
+    pub added: DateTime<Utc>,
+}
+
+impl PoolEntry {
+    /// higher fee rate first, tips per byte, then the oldest
+    fn priority(&self, other: &Self) -> Ordering {
+        let rate = self.trans.tips.base_units() as u128 * other.size as u128;
+        let other_rate = other.trans.tips.base_units() as u128 * self.size as u128;
+        other_rate
+            .cmp(&rate)
+            .then(self.added.cmp(&other.added))
+            .then(self.txid.cmp(&other.txid))
+    }
+}
+
+/// transactions of an account chain waiting to be mined; the transactions
+/// of a sender are kept in nonce order and never spend more than its
+/// confirmed balance
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    entries: HashMap<Sha256, PoolEntry>,
+    /// pending txids of every sender by nonce
+    senders: HashMap<PubKey, BTreeMap<u64, Sha256>>,
+    pub max_size: usize,
+    /// how long a transaction may wait, see `expire`
+    pub expiry: chrono::Duration,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Self::with_limits(MAX_POOL_SIZE, chrono::Duration::milliseconds(POOL_EXPIRY))
+    }
+
+    pub fn with_limits(max_size: usize, expiry: chrono::Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            senders: HashMap::new(),
+            max_size,
+            expiry,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, txid: &Sha256) -> bool {
+        self.entries.contains_key(txid)
+    }
+
+    pub fn get(&self, txid: &Sha256) -> Option<&PoolEntry> {
+        self.entries.get(txid)
+    }
+
+    /// number of pending transactions of `sender`
+    pub fn pending_of(&self, sender: &PubKey) -> usize {
+        self.senders
+            .get(sender)
+            .map(|txids| txids.len())
+            .unwrap_or(0)
+    }
+
+    /// amounts plus tips of the pending transactions of `sender`
+    pub fn pending_debit(&self, sender: &PubKey) -> Result<Amount, Error> {
+        let mut debit = Amount::ZERO;
+        for txid in self
+            .senders
+            .get(sender)
+            .into_iter()
+            .flat_map(|txids| txids.values())
+        {
+            let trans = &self.entries[txid].trans;
+            debit = debit.checked_add(trans.amount)?.checked_add(trans.tips)?;
+        }
+        Ok(debit)
+    }
+
+    /// nonce the next transaction of `sender` must carry, `confirmed` being
+    /// its next nonce on the chain
+    pub fn next_nonce(&self, sender: &PubKey, confirmed: u64) -> u64 {
+        confirmed + self.pending_of(sender) as u64
+    }
+
+    /// queue `trans` of a sender holding `balance` on the chain, whose next
+    /// confirmed nonce is `confirmed`; when the pool is full the transaction
+    /// with the lowest fee rate that no other depends on is evicted, which
+    /// fails with `MempoolFull` if it is `trans` itself
+    pub fn insert(
+        &mut self,
+        trans: Transaction,
+        balance: Amount,
+        confirmed: u64,
+    ) -> Result<Sha256, Error> {
+        let txid = trans.txid()?;
+        if self.contains(&txid) {
+            return Err(Error::DuplicateTransaction);
+        }
+        let expected = self.next_nonce(&trans.from, confirmed);
+        if trans.nonce != expected {
+            return Err(Error::InvalidNonce {
+                expected,
+                found: trans.nonce,
+            });
+        }
+        let pending = self.pending_debit(&trans.from)?;
+        let required = trans.amount.checked_add(trans.tips)?;
+        if pending.checked_add(required)? > balance {
+            return Err(Error::InsufficientBalance {
+                balance: balance.saturating_sub(pending),
+                required,
+            });
+        }
+        let size = bincode::serialized_size(&trans)?;
+        self.senders
+            .entry(trans.from)
+            .or_default()
+            .insert(trans.nonce, txid);
+        self.entries.insert(
+            txid,
+            PoolEntry {
+                trans,
+                txid,
+                size,
+                added: Utc::now(),
+            },
+        );
+        while self.entries.len() > self.max_size {
+            let evicted = match self.eviction_candidate() {
+                Some(evicted) => evicted,
+                None => break,
+            };
+            self.remove(&evicted);
+            if evicted == txid {
+                return Err(Error::MempoolFull);
+            }
+        }
+        Ok(txid)
+    }
+
+    // the cheapest transaction among the last ones of every sender
+    fn eviction_candidate(&self) -> Option<Sha256> {
+        self.senders
+            .values()
+            .filter_map(|txids| txids.values().next_back())
+            .map(|txid| &self.entries[txid])
+            .max_by(|a, b| a.priority(b))
+            .map(|entry| entry.txid)
+    }
+
+    /// remove the transaction `txid` and the later ones of its sender,
+    /// which cannot be mined without it
+    pub fn remove(&mut self, txid: &Sha256) -> Vec<Transaction> {
+        let (sender, nonce) = match self.entries.get(txid) {
+            Some(entry) => (entry.trans.from, entry.trans.nonce),
+            None => return Vec::new(),
+        };
+        let txids = self
+            .senders
+            .get_mut(&sender)
+            .expect("sender of a pending transaction");
+        let removed = txids.split_off(&nonce);
+        if txids.is_empty() {
+            self.senders.remove(&sender);
+        }
+        removed
+            .values()
+            .filter_map(|txid| self.entries.remove(txid))
+            .map(|entry| entry.trans)
+            .collect()
+    }
+
+    /// the pending transactions by fee rate, each sender's in nonce order,
+    /// at most `limit` of them
+    pub fn select(&self, limit: usize) -> Vec<&PoolEntry> {
+        let mut heads: Vec<_> = self
+            .senders
+            .values()
+            .map(|txids| txids.values().peekable())
+            .collect();
+        let mut selected = Vec::new();
+        while selected.len() < limit {
+            let best = heads
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(index, txids)| txids.peek().map(|txid| (index, &self.entries[*txid])))
+                .min_by(|(_, a), (_, b)| a.priority(b))
+                .map(|(index, _)| index);
+            match best {
+                Some(index) => {
+                    let txid = heads[index].next().expect("peeked");
+                    selected.push(&self.entries[txid]);
+                }
+                None => break,
+            }
+        }
+        selected
+    }
+
+    /// the pending transactions of every sender in nonce order, the senders
+    /// by the priority of their first transaction
+    pub fn by_sender(&self) -> Vec<Vec<&PoolEntry>> {
+        let mut senders: Vec<Vec<&PoolEntry>> = self
+            .senders
+            .values()
+            .map(|txids| txids.values().map(|txid| &self.entries[txid]).collect())
+            .collect();
+        senders.sort_by(|a, b| a[0].priority(b[0]));
+        senders
+    }
+
+    /// every pending transaction in the order `select` picks them
+    pub fn transactions(&self) -> Vec<Transaction> {
+        self.select(usize::MAX)
+            .into_iter()
+            .map(|entry| entry.trans.clone())
+            .collect()
+    }
+
+    /// empty the pool, each sender's transactions in nonce order
+    pub fn drain(&mut self) -> Vec<Transaction> {
+        let transactions = self.transactions();
+        self.entries.clear();
+        self.senders.clear();
+        transactions
+    }
+
+    /// drop the transactions `block` confirmed or made invalid: those of
+    /// its senders whose nonce is used, then those they can no longer pay
+    /// for out of their balance in `state`
+    pub fn remove_block(&mut self, block: &Block, state: &ChainState) -> Result<(), Error> {
+        for trans in block.data.iter() {
+            let confirmed = state.nonce_of(&trans.from);
+            let used: Vec<Sha256> = match self.senders.get(&trans.from) {
+                Some(txids) => txids.range(..confirmed).map(|(_, txid)| *txid).collect(),
+                None => continue,
+            };
+            for txid in used {
+                let txids = self.senders.get_mut(&trans.from).expect("sender");
+                txids.retain(|_, pending| pending != &txid);
+                self.entries.remove(&txid);
+            }
+            if self.pending_of(&trans.from) == 0 {
+                self.senders.remove(&trans.from);
+            }
+            let balance = state.balance(&Address::from(&trans.from));
+            while self.pending_debit(&trans.from)? > balance {
+                let last = self.senders[&trans.from]
+                    .values()
+                    .next_back()
+                    .copied()
+                    .expect("pending transactions over the balance");
+                self.remove(&last);
+            }
+        }
+        Ok(())
+    }
+
+    /// drop the transactions queued longer than `expiry` before `now`, and
+    /// the later ones of their senders
+    pub fn expire(&mut self, now: DateTime<Utc>) -> usize {
+        let expired: Vec<Sha256> = self
+            .entries
+            .values()
+            .filter(|entry| now - entry.added > self.expiry)
+            .map(|entry| entry.txid)
+            .collect();
+        expired.iter().map(|txid| self.remove(txid).len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod test_mempool {
+    use super::*;
+    use crate::blockchain::test_block_chain::chain_with_funds;
+    use crate::blockchain::BlockChain;
+    use crate::constant::*;
+
+    fn transfer(chain: &BlockChain, coins: u64, tips: u64, nonce: u64) -> Transaction {
+        let mut trans = Transaction::new(
+            KEY1.1,
+            KEY2.3,
+            Amount::from_coins(coins).unwrap(),
+            Some(Amount::from_coins(tips).unwrap()),
+            nonce,
+        );
+        trans.sign(&KEY1.2, &chain.chain_id).unwrap();
+        trans
+    }
+
+    fn funds() -> Amount {
+        Amount::from_coins(100).unwrap()
+    }
+
+    #[test]
+    fn test_insert() {
+        let chain = chain_with_funds();
+        let mut pool = Mempool::new();
+        let trans = transfer(&chain, 10, 1, 0);
+        let txid = pool.insert(trans.clone(), funds(), 0).unwrap();
+        assert_eq!(txid, trans.txid().unwrap());
+        assert_eq!(
+            pool.insert(trans, funds(), 0),
+            Err(Error::DuplicateTransaction)
+        );
+        assert_eq!(
+            pool.insert(transfer(&chain, 10, 1, 3), funds(), 0),
+            Err(Error::InvalidNonce {
+                expected: 1,
+                found: 3
+            })
+        );
+        assert_eq!(pool.next_nonce(&KEY1.1, 0), 1);
+        assert_eq!(
+            pool.pending_debit(&KEY1.1),
+            Ok(Amount::from_coins(11).unwrap())
+        );
+
+        // the pending transfers count against the balance
+        assert_eq!(
+            pool.insert(transfer(&chain, 89, 1, 1), funds(), 0),
+            Err(Error::InsufficientBalance {
+                balance: Amount::from_coins(89).unwrap(),
+                required: Amount::from_coins(90).unwrap(),
+            })
+        );
+        pool.insert(transfer(&chain, 88, 1, 1), funds(), 0).unwrap();
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_select() {
+        let chain = chain_with_funds();
+        let mut pool = Mempool::new();
+        let low = transfer(&chain, 1, 1, 0);
+        let high = transfer(&chain, 1, 5, 1);
+        pool.insert(low.clone(), funds(), 0).unwrap();
+        pool.insert(high.clone(), funds(), 0).unwrap();
+        let mut other = Transaction::new(
+            KEY2.1,
+            KEY1.3,
+            Amount::from_coins(1).unwrap(),
+            Some(Amount::from_coins(3).unwrap()),
+            0,
+        );
+        other.sign(&KEY2.2, &chain.chain_id).unwrap();
+        pool.insert(other.clone(), funds(), 0).unwrap();
+
+        // a high fee transaction waits for the earlier nonces of its sender
+        assert_eq!(pool.transactions(), vec![other, low.clone(), high]);
+        let selected = pool.select(1);
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].trans.tips, Amount::from_coins(3).unwrap());
+
+        // removing a transaction drops the later ones of its sender
+        assert_eq!(pool.remove(&low.txid().unwrap()).len(), 2);
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.pending_of(&KEY1.1), 0);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let chain = chain_with_funds();
+        let mut pool = Mempool::with_limits(2, chrono::Duration::milliseconds(POOL_EXPIRY));
+        pool.insert(transfer(&chain, 1, 2, 0), funds(), 0).unwrap();
+        pool.insert(transfer(&chain, 1, 1, 1), funds(), 0).unwrap();
+        let mut other = Transaction::new(
+            KEY2.1,
+            KEY1.3,
+            Amount::from_coins(1).unwrap(),
+            Some(Amount::from_coins(3).unwrap()),
+            0,
+        );
+        other.sign(&KEY2.2, &chain.chain_id).unwrap();
+        pool.insert(other.clone(), funds(), 0).unwrap();
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.pending_of(&KEY1.1), 1);
+
+        // a cheaper transaction than the pool holds is not kept
+        let mut cheap = Transaction::new(KEY2.1, KEY1.3, Amount::from_coins(1).unwrap(), None, 1);
+        cheap.sign(&KEY2.2, &chain.chain_id).unwrap();
+        assert_eq!(pool.insert(cheap, funds(), 0), Err(Error::MempoolFull));
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_expire() {
+        let chain = chain_with_funds();
+        let mut pool = Mempool::new();
+        pool.insert(transfer(&chain, 1, 1, 0), funds(), 0).unwrap();
+        pool.insert(transfer(&chain, 1, 1, 1), funds(), 0).unwrap();
+        assert_eq!(pool.expire(Utc::now()), 0);
+        let later = Utc::now() + chrono::Duration::milliseconds(POOL_EXPIRY + 1);
+        assert_eq!(pool.expire(later), 2);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_remove_block() {
+        let mut chain = chain_with_funds();
+        let first = transfer(&chain, 10, 1, 0);
+        let second = transfer(&chain, 10, 1, 1);
+        chain.add_transaction(first.clone()).unwrap();
+        chain.add_transaction(second.clone()).unwrap();
+
+        // a peer mines only the first transfer
+        let mut peer = chain_with_funds();
+        peer.add_transaction(first).unwrap();
+        let block = peer.mine_transaction(&KEY2.3).unwrap();
+        chain.submit_block(block).unwrap();
+        assert_eq!(chain.mempool.transactions(), vec![second]);
+        assert_eq!(chain.next_nonce(&KEY1.1), 2);
+    }
+}
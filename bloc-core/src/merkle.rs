@@ -0,0 +1,143 @@
+use bitcoin_hashes::{sha256, sha256::Hash as Sha256, Hash, HashEngine};
+use serde::{Deserialize, Serialize};
+
+/// proves that the txid at `index` is one of the `leaf_count` leaves of a
+/// merkle tree, `branch` holds the sibling hashes from the leaf upwards
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub index: u32,
+    pub leaf_count: u32,
+    pub branch: Vec<Sha256>,
+}
+
+// inner nodes are tagged so that they can never be mistaken for a txid
+fn hash_node(left: &Sha256, right: &Sha256) -> Sha256 {
+    let mut hash_engine = sha256::HashEngine::default();
+    hash_engine.input(&[1u8]);
+    hash_engine.input(left);
+    hash_engine.input(right);
+    sha256::Hash::from_engine(hash_engine)
+}
+
+// the level above `level`, an unpaired last node moves up unchanged
+fn next_level(level: &[Sha256]) -> Vec<Sha256> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_node(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks of two"),
+        })
+        .collect()
+}
+
+/// root of the merkle tree over `txids`, all zeros when there is none
+pub fn merkle_root(txids: &[Sha256]) -> Sha256 {
+    if txids.is_empty() {
+        return Sha256::from_inner([0u8; 32]);
+    }
+    let mut level = txids.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// proof that `txids[index]` is part of `merkle_root(txids)`
+pub fn merkle_proof(txids: &[Sha256], index: usize) -> Option<MerkleProof> {
+    if index >= txids.len() {
+        return None;
+    }
+    let proof_index = index;
+    let mut index = index;
+    let mut level = txids.to_vec();
+    let mut branch = Vec::new();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            branch.push(level[sibling]);
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    Some(MerkleProof {
+        index: proof_index as u32,
+        leaf_count: txids.len() as u32,
+        branch,
+    })
+}
+
+/// whether `proof` links `txid` to `merkle_root`
+pub fn verify_merkle_proof(txid: &Sha256, proof: &MerkleProof, merkle_root: &Sha256) -> bool {
+    if proof.index >= proof.leaf_count {
+        return false;
+    }
+    let mut hash = *txid;
+    let mut index = proof.index;
+    let mut width = proof.leaf_count;
+    let mut branch = proof.branch.iter();
+    while width > 1 {
+        let sibling = index ^ 1;
+        if sibling < width {
+            let sibling_hash = match branch.next() {
+                Some(sibling_hash) => sibling_hash,
+                None => return false,
+            };
+            hash = if index.is_multiple_of(2) {
+                hash_node(&hash, sibling_hash)
+            } else {
+                hash_node(sibling_hash, &hash)
+            };
+        }
+        index /= 2;
+        width = width.div_ceil(2);
+    }
+    branch.next().is_none() && &hash == merkle_root
+}
+
+#[cfg(test)]
+mod test_merkle {
+    use super::*;
+
+    fn txids(count: u8) -> Vec<Sha256> {
+        (0..count).map(|i| sha256::Hash::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn test_root() {
+        assert_eq!(merkle_root(&[]), Sha256::from_inner([0u8; 32]));
+        let ids = txids(3);
+        assert_eq!(merkle_root(&ids[..1]), ids[0]);
+        assert_eq!(
+            merkle_root(&ids),
+            hash_node(&hash_node(&ids[0], &ids[1]), &ids[2])
+        );
+        let mut swapped = ids.clone();
+        swapped.swap(0, 1);
+        assert_ne!(merkle_root(&swapped), merkle_root(&ids));
+    }
+
+    #[test]
+    fn test_proof() {
+        for count in 1..=9 {
+            let ids = txids(count);
+            let root = merkle_root(&ids);
+            for (index, txid) in ids.iter().enumerate() {
+                let proof = merkle_proof(&ids, index).unwrap();
+                assert!(verify_merkle_proof(txid, &proof, &root));
+
+                let other = sha256::Hash::hash(b"other");
+                assert!(!verify_merkle_proof(&other, &proof, &root));
+                if count > 1 {
+                    let mut moved = proof.clone();
+                    moved.index = (moved.index + 1) % moved.leaf_count;
+                    assert!(!verify_merkle_proof(txid, &moved, &root));
+                    let mut truncated = proof.clone();
+                    truncated.branch.pop();
+                    assert!(!verify_merkle_proof(txid, &truncated, &root));
+                }
+            }
+            assert_eq!(merkle_proof(&ids, count as usize), None);
+        }
+    }
+}
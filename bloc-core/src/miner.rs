@@ -0,0 +1,319 @@
+//! proof of work search, split across threads on native targets or stepped
+//! through in chunks by `mine_nonces` where there are no threads to spawn
+use crate::block::{Block, BlockHeader};
+use crate::pow::Target;
+use crate::Error;
+use chrono::{Duration as ChronoDuration, Utc};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// nonces a worker tries between two looks at the cancel flag
+const BATCH: u64 = 1024;
+
+/// shared flag stopping a `Miner`, cloned to whoever may cancel it
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// how far a mining run got
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MiningStats {
+    /// headers hashed so far
+    pub attempts: u64,
+    pub elapsed: Duration,
+    /// times the timestamp was moved after the nonces ran out
+    pub refreshes: u32,
+}
+
+impl MiningStats {
+    /// hashes per second
+    pub fn hash_rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.attempts as f64 / secs
+        }
+    }
+}
+
+/// how a mining run ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum MineOutcome {
+    Mined { block: Block, stats: MiningStats },
+    Cancelled(MiningStats),
+}
+
+/// proof of work search splitting the nonces of a block across threads
+#[derive(Debug, Clone, PartialEq)]
+pub struct Miner {
+    pub threads: usize,
+    /// nonces tried for a timestamp before it is moved forward
+    pub nonce_span: u64,
+    /// how often the progress callback is called
+    pub report_interval: Duration,
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            nonce_span: u64::MAX,
+            report_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads,
+            ..Default::default()
+        }
+    }
+
+    /// search a nonce making the hash of `block` meet its target until one
+    /// is found or `cancel` is set, calling `progress` every
+    /// `report_interval`; the block must already be linked and carry its
+    /// merkle root
+    pub fn mine<F>(
+        &self,
+        mut block: Block,
+        cancel: &CancelToken,
+        mut progress: F,
+    ) -> Result<MineOutcome, Error>
+    where
+        F: FnMut(&MiningStats),
+    {
+        let target = Target::from_bits(block.bits)?;
+        let threads = self.threads.max(1) as u64;
+        let started = Instant::now();
+        let attempts = Arc::new(AtomicU64::new(0));
+        let mut refreshes = 0;
+        let stats = |refreshes| MiningStats {
+            attempts: attempts.load(Ordering::Relaxed),
+            elapsed: started.elapsed(),
+            refreshes,
+        };
+        loop {
+            let header = block.header();
+            let found = Arc::new(AtomicBool::new(false));
+            let (sender, receiver) = mpsc::channel();
+            let handles: Vec<_> = (0..threads)
+                .map(|worker| {
+                    let header = header.clone();
+                    let (cancel, found) = (cancel.clone(), found.clone());
+                    let (attempts, sender) = (attempts.clone(), sender.clone());
+                    let span = self.nonce_span;
+                    thread::spawn(move || {
+                        let nonces = (worker..=span).step_by(threads as usize);
+                        let stop = || cancel.is_cancelled() || found.load(Ordering::Relaxed);
+                        let nonce = search(header, &target, nonces, &attempts, stop);
+                        if nonce.is_some() {
+                            found.store(true, Ordering::Relaxed);
+                        }
+                        // the receiver only hangs up once a nonce is found
+                        let _ = sender.send(nonce);
+                    })
+                })
+                .collect();
+            drop(sender);
+
+            let mut nonce = None;
+            let mut running = threads;
+            while running > 0 && nonce.is_none() {
+                match receiver.recv_timeout(self.report_interval) {
+                    Ok(result) => {
+                        running -= 1;
+                        nonce = result;
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => progress(&stats(refreshes)),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            found.store(true, Ordering::Relaxed);
+            for handle in handles {
+                handle.join().expect("mining thread panicked");
+            }
+
+            if let Some(nonce) = nonce {
+                block.nonce = nonce;
+                block.hash = block.get_hash();
+                let stats = stats(refreshes);
+                log::debug!(
+                    "mined {} after {} hashes at {:.0}H/s",
+                    block.hash,
+                    stats.attempts,
+                    stats.hash_rate()
+                );
+                return Ok(MineOutcome::Mined { block, stats });
+            }
+            if cancel.is_cancelled() {
+                return Ok(MineOutcome::Cancelled(stats(refreshes)));
+            }
+            // every nonce failed, the header has to change
+            refresh_timestamp(&mut block);
+            refreshes += 1;
+        }
+    }
+}
+
+/// try the `count` nonces from `start` on `block`, keeping the first one
+/// meeting its target; whether one did
+pub fn mine_nonces(block: &mut Block, start: u64, count: u64) -> Result<bool, Error> {
+    let target = Target::from_bits(block.bits)?;
+    let nonces = start..start.saturating_add(count);
+    match search(block.header(), &target, nonces, &AtomicU64::new(0), || {
+        false
+    }) {
+        Some(nonce) => {
+            block.nonce = nonce;
+            block.hash = block.get_hash();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// move the timestamp of `block` forward once its nonces ran out
+pub fn refresh_timestamp(block: &mut Block) {
+    block.timestamp = Utc::now().max(block.timestamp + ChronoDuration::milliseconds(1));
+    block.hash = block.get_hash();
+}
+
+// hash `header` with each of `nonces` until one meets `target` or `stop`
+// returns true, looked at every `BATCH` nonces
+fn search<I, F>(
+    mut header: BlockHeader,
+    target: &Target,
+    nonces: I,
+    attempts: &AtomicU64,
+    stop: F,
+) -> Option<u64>
+where
+    I: Iterator<Item = u64>,
+    F: Fn() -> bool,
+{
+    let mut tried = 0;
+    for nonce in nonces {
+        header.nonce = nonce;
+        tried += 1;
+        if target.is_met_by(&header.hash()) {
+            attempts.fetch_add(tried, Ordering::Relaxed);
+            return Some(nonce);
+        }
+        if tried == BATCH {
+            attempts.fetch_add(tried, Ordering::Relaxed);
+            tried = 0;
+            if stop() {
+                return None;
+            }
+        }
+    }
+    attempts.fetch_add(tried, Ordering::Relaxed);
+    None
+}
+
+#[cfg(test)]
+mod test_miner {
+    use super::*;
+    use crate::blockchain::BlockChain;
+    use crate::constant::*;
+
+    fn template() -> (BlockChain, Block) {
+        let chain = BlockChain::new();
+        let block = chain.block_template(&KEY1.3).unwrap().block;
+        (chain, block)
+    }
+
+    #[test]
+    fn test_mine() {
+        let (mut chain, block) = template();
+        let outcome = Miner::new(4).mine(block, &CancelToken::new(), |_| {});
+        let (block, stats) = match outcome {
+            Ok(MineOutcome::Mined { block, stats }) => (block, stats),
+            other => panic!("not mined: {:?}", other),
+        };
+        assert_eq!(block.is_mined(), Ok(true));
+        assert_eq!(block.hash, block.get_hash());
+        assert!(stats.attempts > 0);
+        assert!(chain.submit_block(block).is_ok());
+        assert_eq!(chain.len(), 2);
+    }
+
+    #[test]
+    fn test_nonce_exhausted() {
+        let (chain, block) = template();
+        let timestamp = block.timestamp;
+        let miner = Miner {
+            threads: 2,
+            nonce_span: 3,
+            ..Miner::new(2)
+        };
+        match miner.mine(block, &CancelToken::new(), |_| {}) {
+            Ok(MineOutcome::Mined { block, stats }) => {
+                assert!(block.nonce <= 3);
+                assert_eq!(block.is_mined(), Ok(true));
+                assert!(block.timestamp >= timestamp);
+                assert_eq!(block.timestamp > timestamp, stats.refreshes > 0);
+                assert_eq!(chain.state.validate_block(&chain.params(), &block), Ok(()));
+            }
+            other => panic!("not mined: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_mine_nonces() {
+        let (chain, mut block) = template();
+        let mut start = 0;
+        while !mine_nonces(&mut block, start, 16).unwrap() {
+            start += 16;
+        }
+        assert!(block.nonce >= start && block.nonce < start + 16);
+        assert_eq!(block.is_mined(), Ok(true));
+        assert_eq!(chain.state.validate_block(&chain.params(), &block), Ok(()));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (_, mut block) = template();
+        // about one hash in 2^32 meets it
+        block.bits = 0x1d00_ffff;
+        let cancel = CancelToken::new();
+        let miner = Miner {
+            report_interval: Duration::from_millis(10),
+            ..Miner::new(2)
+        };
+        let mut reports = Vec::new();
+        let canceller = cancel.clone();
+        let outcome = miner.mine(block, &cancel, |stats| {
+            reports.push(*stats);
+            if reports.len() == 3 {
+                canceller.cancel();
+            }
+        });
+        match outcome {
+            Ok(MineOutcome::Cancelled(stats)) => {
+                assert!(stats.attempts >= reports[2].attempts);
+                assert!(stats.hash_rate() > 0.0);
+            }
+            other => panic!("not cancelled: {:?}", other),
+        }
+        assert!(reports.len() >= 3);
+    }
+}
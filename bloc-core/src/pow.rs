@@ -0,0 +1,297 @@
+use crate::Error;
+use bitcoin_hashes::sha256::Hash as Sha256;
+use std::fmt;
+
+/// easiest target a block may be mined at, in compact form
+pub const POW_LIMIT_BITS: u32 = 0x207f_ffff;
+/// target of a new chain, about one hash in 256 meets it
+pub const DEFAULT_BITS: u32 = 0x2000_ffff;
+/// number of blocks the target stays the same for, the time they took to
+/// be mined drives the next target
+pub const RETARGET_WINDOW: usize = 10;
+/// the most a retarget can make the target harder or easier
+pub const MAX_ADJUSTMENT: i64 = 4;
+
+/// rescale the target of `bits` by the `actual` milliseconds the last
+/// window took against `block_time` between each of its blocks
+pub fn retarget(bits: u32, actual: i64, block_time: u32) -> Result<u32, Error> {
+    let expected = (RETARGET_WINDOW as i64 - 1) * block_time as i64;
+    if expected == 0 {
+        return Ok(bits);
+    }
+    let actual = actual.clamp(expected / MAX_ADJUSTMENT, expected * MAX_ADJUSTMENT);
+    log::debug!("retarget: {}ms for {}ms expected", actual, expected);
+    Ok(Target::from_bits(bits)?
+        .scale(actual as u64, expected as u64)
+        .to_bits())
+}
+
+/// 256-bit proof of work target, a block is mined when its hash read as a
+/// big endian integer is not above it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target([u8; 32]);
+
+impl Target {
+    /// decode the compact `bits` of a header: a one byte exponent followed
+    /// by a 23-bit mantissa, `mantissa * 256^(exponent - 3)`
+    pub fn from_bits(bits: u32) -> Result<Self, Error> {
+        let exponent = (bits >> 24) as i64;
+        let mantissa = bits & 0x00ff_ffff;
+        // the sign bit of the mantissa, negative targets are malformed
+        if mantissa & 0x0080_0000 != 0 {
+            return Err(Error::InvalidTarget(bits));
+        }
+        let mut target = [0u8; 32];
+        for (i, byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+            let index = 32 - exponent + i as i64;
+            if index < 0 {
+                if *byte != 0 {
+                    return Err(Error::InvalidTarget(bits));
+                }
+            } else if index < 32 {
+                target[index as usize] = *byte;
+            }
+        }
+        let target = Self(target);
+        if target == Self([0u8; 32]) || target > Self::pow_limit() {
+            return Err(Error::InvalidTarget(bits));
+        }
+        Ok(target)
+    }
+
+    /// the compact form of the target, dropping the bits below its three
+    /// most significant bytes
+    pub fn to_bits(&self) -> u32 {
+        let first = match self.0.iter().position(|byte| *byte != 0) {
+            Some(first) => first,
+            None => return 0,
+        };
+        let mut size = (32 - first) as u32;
+        let mut mantissa = self.0[first..]
+            .iter()
+            .take(3)
+            .fold(0u32, |acc, byte| acc << 8 | *byte as u32);
+        if size < 3 {
+            mantissa <<= 8 * (3 - size);
+        }
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        size << 24 | mantissa
+    }
+
+    pub fn pow_limit() -> Self {
+        let mut target = [0u8; 32];
+        target[0] = 0x7f;
+        target[1] = 0xff;
+        target[2] = 0xff;
+        Self(target)
+    }
+
+    pub fn is_met_by(&self, hash: &Sha256) -> bool {
+        let hash: &[u8] = hash.as_ref();
+        hash <= &self.0[..]
+    }
+
+    /// twice as hard to meet
+    pub fn harder(&self) -> Self {
+        let target = Self(shr1(self.0));
+        if target == Self([0u8; 32]) {
+            return *self;
+        }
+        target
+    }
+
+    /// twice as easy to meet, up to the proof of work limit
+    pub fn easier(&self) -> Self {
+        let (target, carry) = shl1(self.0);
+        let target = Self(target);
+        if carry || target > Self::pow_limit() {
+            return Self::pow_limit();
+        }
+        target
+    }
+
+    /// the target times `numerator / denominator`, kept between one and the
+    /// proof of work limit
+    pub fn scale(&self, numerator: u64, denominator: u64) -> Self {
+        let denominator = denominator.max(1) as u128;
+        let mut product = [0u8; 40];
+        let mut carry = 0u128;
+        for i in (0..32).rev() {
+            let value = self.0[i] as u128 * numerator as u128 + carry;
+            product[i + 8] = value as u8;
+            carry = value >> 8;
+        }
+        for byte in product[..8].iter_mut().rev() {
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        let mut quotient = [0u8; 40];
+        let mut remainder = 0u128;
+        for (i, byte) in product.iter().enumerate() {
+            let value = remainder << 8 | *byte as u128;
+            quotient[i] = (value / denominator) as u8;
+            remainder = value % denominator;
+        }
+        if quotient[..8].iter().any(|byte| *byte != 0) {
+            return Self::pow_limit();
+        }
+        let mut target = [0u8; 32];
+        target.copy_from_slice(&quotient[8..]);
+        let target = Self(target);
+        if target == Self([0u8; 32]) {
+            let mut one = [0u8; 32];
+            one[31] = 1;
+            return Self(one);
+        }
+        target.min(Self::pow_limit())
+    }
+
+    /// expected number of hashes to meet the target, `2^256 / (target + 1)`,
+    /// saturating at `u128::MAX`
+    pub fn work(&self) -> u128 {
+        if self.0 == [0xff; 32] {
+            return 1;
+        }
+        // 2^256 / (t + 1) == !t / (t + 1) + 1
+        let divisor = add1(self.0);
+        let dividend = self.0.map(|byte| !byte);
+        let mut remainder = [0u8; 32];
+        let mut quotient = 0u128;
+        for bit in 0..256 {
+            let (shifted, carry) = shl1(remainder);
+            remainder = shifted;
+            remainder[31] |= (dividend[bit / 8] >> (7 - bit % 8)) & 1;
+            if carry || remainder >= divisor {
+                remainder = sub(remainder, divisor);
+                if bit < 128 {
+                    return u128::MAX;
+                }
+                quotient |= 1 << (255 - bit);
+            }
+        }
+        quotient.saturating_add(1)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+fn shl1(value: [u8; 32]) -> ([u8; 32], bool) {
+    let mut shifted = [0u8; 32];
+    let mut carry = 0;
+    for i in (0..32).rev() {
+        shifted[i] = value[i] << 1 | carry;
+        carry = value[i] >> 7;
+    }
+    (shifted, carry == 1)
+}
+
+fn shr1(value: [u8; 32]) -> [u8; 32] {
+    let mut shifted = [0u8; 32];
+    let mut carry = 0;
+    for i in 0..32 {
+        shifted[i] = value[i] >> 1 | carry;
+        carry = value[i] << 7;
+    }
+    shifted
+}
+
+fn add1(mut value: [u8; 32]) -> [u8; 32] {
+    for byte in value.iter_mut().rev() {
+        let (sum, overflow) = byte.overflowing_add(1);
+        *byte = sum;
+        if !overflow {
+            break;
+        }
+    }
+    value
+}
+
+fn sub(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+    let mut difference = [0u8; 32];
+    let mut borrow = 0;
+    for i in (0..32).rev() {
+        let (d, o1) = a[i].overflowing_sub(b[i]);
+        let (d, o2) = d.overflowing_sub(borrow);
+        difference[i] = d;
+        borrow = (o1 || o2) as u8;
+    }
+    difference
+}
+
+#[cfg(test)]
+mod test_pow {
+    use super::*;
+    use bitcoin_hashes::Hash;
+
+    #[test]
+    fn test_bits() {
+        let target = Target::from_bits(DEFAULT_BITS).unwrap();
+        assert_eq!(
+            target.to_string(),
+            "00ffff0000000000000000000000000000000000000000000000000000000000"
+        );
+        assert_eq!(target.to_bits(), DEFAULT_BITS);
+        assert_eq!(
+            Target::from_bits(POW_LIMIT_BITS).unwrap(),
+            Target::pow_limit()
+        );
+        // the mainnet limit of bitcoin
+        let target = Target::from_bits(0x1d00_ffff).unwrap();
+        assert_eq!(target.to_bits(), 0x1d00_ffff);
+        assert_eq!(target.0[4..6], [0xff, 0xff]);
+
+        for bits in [0, 0x0080_0001, 0x2100_ffff, 0x2080_0000, 0x2201_0000] {
+            assert_eq!(Target::from_bits(bits), Err(Error::InvalidTarget(bits)));
+        }
+        // a sign bit in the mantissa moves into the exponent
+        let mut bytes = [0u8; 32];
+        bytes[1] = 0x80;
+        assert_eq!(Target(bytes).to_bits(), 0x2000_8000);
+    }
+
+    #[test]
+    fn test_is_met_by() {
+        let target = Target::from_bits(DEFAULT_BITS).unwrap();
+        let mut hash = [0u8; 32];
+        hash[1] = 0xff;
+        hash[2] = 0xff;
+        assert!(target.is_met_by(&Sha256::from_inner(hash)));
+        hash[3] = 1;
+        assert!(!target.is_met_by(&Sha256::from_inner(hash)));
+    }
+
+    #[test]
+    fn test_work() {
+        assert_eq!(Target([0xff; 32]).work(), 1);
+        assert_eq!(Target::pow_limit().work(), 2);
+        let mut bytes = [0u8; 32];
+        bytes[1] = 0x01;
+        assert_eq!(Target(bytes).work(), 65535);
+        let target = Target::from_bits(DEFAULT_BITS).unwrap();
+        assert_eq!(target.harder().work(), target.work() * 2);
+        assert_eq!(target.harder().easier(), target);
+        assert_eq!(Target::pow_limit().easier(), Target::pow_limit());
+        assert_eq!(Target([0u8; 32]).work(), u128::MAX);
+    }
+
+    #[test]
+    fn test_scale() {
+        let target = Target::from_bits(DEFAULT_BITS).unwrap();
+        assert_eq!(target.scale(1, 2), target.harder());
+        assert_eq!(target.scale(2, 1), target.easier());
+        assert_eq!(target.scale(3, 3), target);
+        assert_eq!(target.scale(u64::MAX, 1), Target::pow_limit());
+        assert_eq!(target.scale(0, 1).work(), u128::MAX);
+        assert_eq!(target.scale(1, 0), target);
+    }
+}
@@ -0,0 +1,543 @@
+use k256::ecdsa::signature::Signer;
+use k256::elliptic_curve::serde::{
+    de::{self, Deserializer},
+    ser::{SerializeSeq, Serializer},
+    Deserialize, Serialize,
+};
+use k256::*;
+use rand_core::OsRng;
+use std::fmt;
+use std::str::FromStr;
+
+pub struct Secp256K1 {
+    rng: OsRng,
+}
+impl Default for Secp256K1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Secp256K1 {
+    pub fn new() -> Self {
+        Self { rng: OsRng }
+    }
+
+    pub fn gen_keypair(&mut self) -> (SecKey, PubKey) {
+        let sec_key_raw = k256::ecdsa::SigningKey::random(&mut self.rng);
+        let sec_key_raw_byte = &sec_key_raw.to_bytes();
+        unsafe {
+            let sec_key_raw_arr =
+                std::mem::transmute::<k256::FieldBytes, [u8; 32]>(*sec_key_raw_byte);
+            let sec_key = SecKey(sec_key_raw_arr);
+            let pub_key_raw = sec_key_raw.verifying_key();
+            let pub_key_bytes = pub_key_raw.to_bytes();
+            let pub_key_raw_arr =
+                std::mem::transmute::<k256::CompressedPoint, [u8; 33]>(pub_key_bytes);
+            let pub_key = PubKey(pub_key_raw_arr);
+            (sec_key, pub_key)
+        }
+    }
+}
+
+pub trait ToHex {
+    fn to_hex(&self) -> String;
+    fn to_bytes(&self) -> &[u8];
+}
+
+/// a secret scalar, neither `Copy` nor shown by `Debug` so that it is not
+/// spread around or logged by accident
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct SecKey(#[serde(with = "serde_seckey")] [u8; 32]);
+impl SecKey {
+    /// the key of the scalar `key`, which must be non-zero and below the
+    /// group order
+    pub fn new(key: &[u8; 32]) -> Result<Self, Error> {
+        Self::try_from(&key[..])
+    }
+
+    pub fn from_slice(slice: &[u8; 32]) -> Result<Self, Error> {
+        Self::new(slice)
+    }
+
+    /// parse 64 hex digits of a scalar within the group order
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        Self::try_from(decode_hex(hex)?.as_slice())
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> k256::ecdsa::Signature {
+        let sign_key = k256::ecdsa::SigningKey::from_bytes(&self.0)
+            .expect("secret keys are checked on construction");
+        sign_key.sign(msg)
+    }
+}
+impl ToHex for SecKey {
+    fn to_hex(&self) -> String {
+        use std::fmt::Write;
+        let mut ret = String::with_capacity(2 * self.0.len());
+        self.0
+            .iter()
+            .for_each(|ch| write!(ret, "{:02x}", ch).expect("writing to string"));
+        ret
+    }
+
+    fn to_bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+impl fmt::Debug for SecKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecKey(..)")
+    }
+}
+impl TryFrom<&[u8]> for SecKey {
+    type Error = Error;
+    /// accept the 32 big-endian bytes of a non-zero scalar below the order
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes: [u8; 32] = fixed(bytes)?;
+        k256::SecretKey::from_be_bytes(&bytes).map_err(|_| Error::InvalidScalar)?;
+        Ok(Self(bytes))
+    }
+}
+impl FromStr for SecKey {
+    type Err = Error;
+    fn from_str(hex: &str) -> Result<Self, Error> {
+        Self::from_hex(hex)
+    }
+}
+impl fmt::Display for SecKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct PubKey(#[serde(with = "serde_pubkey")] [u8; 33]);
+impl PubKey {
+    /// the key of the compressed point `key`, which must lie on the curve
+    pub fn new(key: &[u8; 33]) -> Result<Self, Error> {
+        Self::try_from(&key[..])
+    }
+
+    pub fn from_slice(slice: &[u8; 33]) -> Result<Self, Error> {
+        Self::new(slice)
+    }
+
+    /// parse 66 hex digits of a compressed point of the curve
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        Self::try_from(decode_hex(hex)?.as_slice())
+    }
+
+    pub fn verify(&self, msg: &[u8], signature: &ecdsa::Signature) -> Result<(), ecdsa::Error> {
+        let verkey = k256::ecdsa::VerifyingKey::from_sec1_bytes(&self.0)?;
+        use k256::ecdsa::signature::Verifier;
+        verkey.verify(msg, signature)
+    }
+}
+impl AsRef<[u8]> for PubKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+impl ToHex for PubKey {
+    fn to_hex(&self) -> String {
+        use std::fmt::Write;
+        let mut ret = String::with_capacity(2 * self.0.len());
+        self.0
+            .iter()
+            .for_each(|ch| write!(ret, "{:02x}", ch).expect("writing to string"));
+        ret
+    }
+
+    fn to_bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+impl TryFrom<&[u8]> for PubKey {
+    type Error = Error;
+    /// accept the 33 bytes of a compressed point lying on the curve
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes: [u8; 33] = fixed(bytes)?;
+        k256::PublicKey::from_sec1_bytes(&bytes).map_err(|_| Error::InvalidPoint)?;
+        Ok(Self(bytes))
+    }
+}
+impl FromStr for PubKey {
+    type Err = Error;
+    fn from_str(hex: &str) -> Result<Self, Error> {
+        Self::from_hex(hex)
+    }
+}
+impl fmt::Display for PubKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+// `bytes` as an array of exactly `N` bytes
+fn fixed<const N: usize>(bytes: &[u8]) -> Result<[u8; N], Error> {
+    bytes.try_into().map_err(|_| Error::InvalidLength {
+        expected: N,
+        found: bytes.len(),
+    })
+}
+
+// human-readable formats carry keys as hex strings, binary ones as raw bytes
+mod serde_pubkey {
+    use super::*;
+    pub fn serialize<S>(key: &[u8; 33], s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if s.is_human_readable() {
+            return s.serialize_str(&PubKey(*key).to_hex());
+        }
+        let mut seq = s.serialize_seq(Some(33))?;
+        for e in key {
+            seq.serialize_element(&e)?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<[u8; 33], D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if d.is_human_readable() {
+            let hex = String::deserialize(d)?;
+            return PubKey::from_hex(&hex)
+                .map(|key| key.0)
+                .map_err(de::Error::custom);
+        }
+        let bytes = Vec::<u8>::deserialize(d)?;
+        PubKey::try_from(bytes.as_slice())
+            .map(|key| key.0)
+            .map_err(de::Error::custom)
+    }
+}
+
+mod serde_seckey {
+    use super::*;
+    pub fn serialize<S>(key: &[u8; 32], s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if s.is_human_readable() {
+            return s.serialize_str(&SecKey(*key).to_hex());
+        }
+        key.serialize(s)
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<[u8; 32], D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if d.is_human_readable() {
+            let hex = String::deserialize(d)?;
+            return SecKey::from_hex(&hex)
+                .map(|key| key.0)
+                .map_err(de::Error::custom);
+        }
+        let bytes = <[u8; 32]>::deserialize(d)?;
+        SecKey::try_from(bytes.as_slice())
+            .map(|key| key.0)
+            .map_err(de::Error::custom)
+    }
+}
+
+/// hex in human-readable formats, raw bytes otherwise, for use as
+/// `#[serde(with = "serde_signature")]` on `ecdsa::Signature` fields
+pub mod serde_signature {
+    use super::*;
+    pub fn serialize<S>(signature: &ecdsa::Signature, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if s.is_human_readable() {
+            return s.serialize_str(&signature.to_hex());
+        }
+        signature.serialize(s)
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<ecdsa::Signature, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if !d.is_human_readable() {
+            return ecdsa::Signature::deserialize(d);
+        }
+        let hex = String::deserialize(d)?;
+        let bytes = decode_hex(&hex).map_err(de::Error::custom)?;
+        ecdsa::Signature::try_from(bytes.as_slice()).map_err(de::Error::custom)
+    }
+}
+
+/// decode a hex string of any case into bytes
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    // checked char by char first, so that neither a sign accepted by
+    // `from_str_radix` nor a multibyte character slips through
+    if let Some((index, ch)) = hex.char_indices().find(|(_, ch)| !ch.is_ascii_hexdigit()) {
+        return Err(Error::InvalidHex { index, ch });
+    }
+    if !hex.len().is_multiple_of(2) {
+        return Err(Error::OddLength(hex.len()));
+    }
+    let digit = |ch: u8| (ch as char).to_digit(16).unwrap_or_default() as u8;
+    Ok(hex
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| digit(pair[0]) << 4 | digit(pair[1]))
+        .collect())
+}
+
+//#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone)]
+pub struct KeyPair(elliptic_curve::JwkEcKey);
+impl From<&SecKey> for KeyPair {
+    fn from(sec_key: &SecKey) -> Self {
+        let sign_key = k256::ecdsa::SigningKey::from_bytes(&sec_key.0)
+            .expect("secret keys are checked on construction");
+        let key: elliptic_curve::SecretKey<k256::Secp256k1> =
+            elliptic_curve::SecretKey::from(&sign_key);
+        Self(elliptic_curve::JwkEcKey::from(key))
+    }
+}
+impl KeyPair {
+    /// Is this JWK a keypair that includes a private key?
+    pub fn is_keypair(&self) -> bool {
+        self.0.is_keypair()
+    }
+
+    pub fn to_public_key(&self) -> Result<PubKey, Box<dyn std::error::Error>> {
+        let raw_key = self.0.to_public_key()?;
+        let verkey = k256::ecdsa::VerifyingKey::from(raw_key);
+        unsafe {
+            let raw_bytes =
+                std::mem::transmute::<k256::CompressedPoint, [u8; 33]>(verkey.to_bytes());
+            Ok(PubKey(raw_bytes))
+        }
+    }
+
+    pub fn to_secret_key(&self) -> Result<SecKey, Box<dyn std::error::Error>> {
+        let seckey: elliptic_curve::SecretKey<k256::Secp256k1> = self.0.to_secret_key()?;
+        unsafe {
+            let bytes = std::mem::transmute::<k256::FieldBytes, [u8; 32]>(seckey.to_be_bytes());
+            Ok(SecKey(bytes))
+        }
+    }
+}
+
+impl ToHex for ecdsa::Signature {
+    fn to_hex(&self) -> String {
+        unsafe {
+            let bytes = std::mem::transmute::<ecdsa::Signature, [u8; 64]>(*self);
+            use std::fmt::Write;
+            let mut ret = String::with_capacity(128);
+            bytes
+                .iter()
+                .for_each(|ch| write!(ret, "{:02x}", ch).expect("writing to string"));
+            ret
+        }
+    }
+
+    fn to_bytes(&self) -> &[u8] {
+        unsafe { std::mem::transmute::<&ecdsa::Signature, &[u8; 64]>(self) }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    /// a hex sequence of an odd number of digits
+    OddLength(usize),
+    /// the character at byte `index` is not a hex digit
+    InvalidHex {
+        index: usize,
+        ch: char,
+    },
+    InvalidLength {
+        expected: usize,
+        found: usize,
+    },
+    /// the bytes do not encode a compressed point of the curve
+    InvalidPoint,
+    /// the scalar is zero or not below the group order
+    InvalidScalar,
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OddLength(len) => write!(f, "odd number of hex digits {}", len),
+            Error::InvalidHex { index, ch } => {
+                write!(f, "invalid hex digit {:?} at {}", ch, index)
+            }
+            Error::InvalidLength { expected, found } => {
+                write!(f, "expected {} bytes, found {}", expected, found)
+            }
+            Error::InvalidPoint => write!(f, "not a point of the curve"),
+            Error::InvalidScalar => write!(f, "secret key out of range"),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod test_all {
+    use super::*;
+
+    #[test]
+    fn test_int() {
+        let mut secp = Secp256K1::new();
+        let (seckey, pubkey) = secp.gen_keypair();
+        dbg!(seckey.to_hex(), pubkey.to_hex());
+        let keypair = KeyPair::from(&seckey);
+        assert_eq!(seckey.0, keypair.to_secret_key().unwrap().0);
+        assert_eq!(pubkey.0, keypair.to_public_key().unwrap().0);
+        let msg = b"hello world bloc";
+        let signature = seckey.sign(msg);
+        let ver_res = pubkey.verify(msg, &signature);
+        assert!(ver_res.is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test_parse {
+    use super::*;
+    use proptest::prelude::*;
+
+    // order of the group of secp256k1
+    const ORDER: &str = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+    #[test]
+    fn test_round_trip() {
+        let (seckey, pubkey) = Secp256K1::new().gen_keypair();
+        assert_eq!(seckey.to_string().parse::<SecKey>().unwrap(), seckey);
+        assert_eq!(pubkey.to_string().parse::<PubKey>().unwrap(), pubkey);
+        assert_eq!(
+            PubKey::from_hex(&pubkey.to_hex().to_uppercase()),
+            Ok(pubkey)
+        );
+        assert_eq!(SecKey::try_from(seckey.to_bytes()), Ok(seckey.clone()));
+        assert_eq!(PubKey::try_from(pubkey.as_ref()), Ok(pubkey));
+
+        let bytes = bincode::serialize(&pubkey).unwrap();
+        assert_eq!(bincode::deserialize::<PubKey>(&bytes).unwrap(), pubkey);
+        let bytes = bincode::serialize(&seckey).unwrap();
+        assert_eq!(bincode::deserialize::<SecKey>(&bytes).unwrap(), seckey);
+    }
+
+    #[test]
+    fn test_bad_hex() {
+        assert_eq!(decode_hex("abc"), Err(Error::OddLength(3)));
+        assert_eq!(
+            decode_hex("+f"),
+            Err(Error::InvalidHex { index: 0, ch: '+' })
+        );
+        assert_eq!(
+            decode_hex("0é"),
+            Err(Error::InvalidHex { index: 1, ch: 'é' })
+        );
+        // two bytes of utf-8 as long as a hex pair
+        assert!(PubKey::from_hex(&format!("é{}", "0".repeat(64))).is_err());
+        assert!(SecKey::from_hex(&format!("{}é", "1".repeat(62))).is_err());
+        assert_eq!(
+            SecKey::from_hex("0102"),
+            Err(Error::InvalidLength {
+                expected: 32,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_bad_keys() {
+        let (_, pubkey) = Secp256K1::new().gen_keypair();
+        let x = &pubkey.to_hex()[2..];
+        // no point has x = 0, and only the compressed tags are 33 bytes long
+        let zero = format!("02{}", "00".repeat(32));
+        assert_eq!(zero.parse::<PubKey>(), Err(Error::InvalidPoint));
+        assert_eq!(
+            format!("04{}", x).parse::<PubKey>(),
+            Err(Error::InvalidPoint)
+        );
+        assert_eq!(
+            format!("00{}", x).parse::<PubKey>(),
+            Err(Error::InvalidPoint)
+        );
+        let mut point = [0; 33];
+        point[0] = 2;
+        assert_eq!(PubKey::new(&point), Err(Error::InvalidPoint));
+        let bytes = bincode::serialize(&point.to_vec()).unwrap();
+        assert!(bincode::deserialize::<PubKey>(&bytes).is_err());
+        let json = serde_json::to_string(&zero).unwrap();
+        assert!(serde_json::from_str::<PubKey>(&json).is_err());
+
+        assert_eq!("00".repeat(32).parse::<SecKey>(), Err(Error::InvalidScalar));
+        assert_eq!(ORDER.parse::<SecKey>(), Err(Error::InvalidScalar));
+        assert_eq!("ff".repeat(32).parse::<SecKey>(), Err(Error::InvalidScalar));
+        let below = format!("{}0", &ORDER[..63]);
+        assert!(below.parse::<SecKey>().is_ok());
+        let bytes = bincode::serialize(&[0u8; 32]).unwrap();
+        assert!(bincode::deserialize::<SecKey>(&bytes).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_no_panic_on_str(s in "\\PC*", hex in "[0-9a-fA-F]{0,70}") {
+            for s in [&s, &hex] {
+                let _ = decode_hex(s);
+                let _ = s.parse::<PubKey>();
+                let _ = s.parse::<SecKey>();
+                let _ = serde_json::from_str::<PubKey>(&format!("{:?}", s));
+                let _ = serde_json::from_str::<SecKey>(&format!("{:?}", s));
+            }
+        }
+
+        #[test]
+        fn test_no_panic_on_bytes(bytes in proptest::collection::vec(any::<u8>(), 0..80)) {
+            let _ = PubKey::try_from(bytes.as_slice());
+            let _ = SecKey::try_from(bytes.as_slice());
+            let _ = bincode::deserialize::<PubKey>(&bytes);
+            let _ = bincode::deserialize::<SecKey>(&bytes);
+        }
+
+        #[test]
+        fn test_hex_round_trip(bytes in proptest::collection::vec(any::<u8>(), 0..80)) {
+            let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            prop_assert_eq!(decode_hex(&hex), Ok(bytes.clone()));
+            prop_assert_eq!(decode_hex(&hex.to_uppercase()), Ok(bytes));
+        }
+
+        #[test]
+        fn test_no_panic_on_secret(bytes in prop_oneof![
+            Just([0u8; 32]),
+            Just([0xff; 32]),
+            proptest::array::uniform32(any::<u8>()),
+        ]) {
+            // out of range scalars, zero first, are refused instead of
+            // panicking once used to sign
+            match SecKey::new(&bytes) {
+                Ok(seckey) => {
+                    let signature = seckey.sign(b"bloc");
+                    let own = KeyPair::from(&seckey).to_public_key().unwrap();
+                    prop_assert!(own.verify(b"bloc", &signature).is_ok());
+                }
+                Err(e) => prop_assert_eq!(e, Error::InvalidScalar),
+            }
+            prop_assert_eq!(SecKey::from_slice(&bytes), SecKey::new(&bytes));
+        }
+
+        #[test]
+        fn test_parsed_keys_are_valid(bytes in proptest::array::uniform32(any::<u8>()), odd in any::<bool>()) {
+            let mut point = [if odd { 3 } else { 2 }; 33];
+            point[1..].copy_from_slice(&bytes);
+            // whatever parses can sign and verify
+            if let (Ok(seckey), Ok(pubkey)) = (SecKey::try_from(&bytes[..]), PubKey::try_from(&point[..])) {
+                let signature = seckey.sign(b"bloc");
+                prop_assert!(pubkey.verify(b"bloc", &signature).is_err());
+                let own = KeyPair::from(&seckey).to_public_key().unwrap();
+                prop_assert!(own.verify(b"bloc", &signature).is_ok());
+            }
+        }
+    }
+}
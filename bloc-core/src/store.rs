@@ -0,0 +1,287 @@
+use crate::{block::Block, Error};
+use bitcoin_hashes::sha256::Hash as Sha256;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// persistence of the blocks of a chain, indexed by height and hash
+pub trait BlockStore {
+    /// append `block` at height `len()`
+    fn append(&mut self, block: &Block) -> Result<(), Error>;
+
+    fn get(&self, height: u64) -> Result<Option<Block>, Error>;
+
+    fn height_of(&self, hash: &Sha256) -> Option<u64>;
+
+    fn len(&self) -> u64;
+
+    /// drop the blocks from `height` on, before appending another branch
+    fn truncate(&mut self, height: u64) -> Result<(), Error>;
+
+    fn get_by_hash(&self, hash: &Sha256) -> Result<Option<Block>, Error> {
+        match self.height_of(hash) {
+            Some(height) => self.get(height),
+            None => Ok(None),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// keeps blocks in memory only, e.g. for the browser or tests
+#[derive(Default)]
+pub struct MemoryStore {
+    blocks: Vec<Block>,
+    heights: HashMap<Sha256, u64>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlockStore for MemoryStore {
+    fn append(&mut self, block: &Block) -> Result<(), Error> {
+        self.heights.insert(block.hash, self.blocks.len() as u64);
+        self.blocks.push(block.clone());
+        Ok(())
+    }
+
+    fn get(&self, height: u64) -> Result<Option<Block>, Error> {
+        Ok(self.blocks.get(height as usize).cloned())
+    }
+
+    fn height_of(&self, hash: &Sha256) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    fn len(&self) -> u64 {
+        self.blocks.len() as u64
+    }
+
+    fn truncate(&mut self, height: u64) -> Result<(), Error> {
+        for block in self.blocks.drain(height.min(self.len()) as usize..) {
+            self.heights.remove(&block.hash);
+        }
+        Ok(())
+    }
+}
+
+// every record is `len: u32 | crc32(payload): u32 | payload`, little endian
+const RECORD_HEADER: usize = 8;
+
+/// append-only log of bincode-encoded blocks
+///
+/// each record is checksummed and synced to disk before `append` returns; a
+/// torn or corrupted tail left by a crash is cut off when the log is reopened
+pub struct FileStore {
+    file: File,
+    offsets: Vec<u64>,
+    heights: HashMap<Sha256, u64>,
+    end: u64,
+}
+
+impl FileStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw)?;
+
+        let mut store = Self {
+            file,
+            offsets: Vec::new(),
+            heights: HashMap::new(),
+            end: 0,
+        };
+        let mut offset = 0;
+        while let Some(payload) = Self::parse_record(&raw[offset..]) {
+            let block: Block = bincode::deserialize(payload)?;
+            store.heights.insert(block.hash, store.offsets.len() as u64);
+            store.offsets.push(offset as u64);
+            offset += RECORD_HEADER + payload.len();
+        }
+        store.end = offset as u64;
+        if store.end < raw.len() as u64 {
+            log::warn!(
+                "dropping {} bytes of incomplete or corrupted block log",
+                raw.len() as u64 - store.end
+            );
+            store.file.set_len(store.end)?;
+            store.file.sync_all()?;
+        }
+        Ok(store)
+    }
+
+    // the payload of the record at the head of `raw`, if it is complete
+    // and its checksum matches
+    fn parse_record(raw: &[u8]) -> Option<&[u8]> {
+        if raw.len() < RECORD_HEADER {
+            return None;
+        }
+        let len = u32::from_le_bytes(raw[0..4].try_into().ok()?) as usize;
+        let crc = u32::from_le_bytes(raw[4..8].try_into().ok()?);
+        let payload = raw.get(RECORD_HEADER..RECORD_HEADER + len)?;
+        (crc32fast::hash(payload) == crc).then_some(payload)
+    }
+}
+
+impl BlockStore for FileStore {
+    fn append(&mut self, block: &Block) -> Result<(), Error> {
+        let payload = bincode::serialize(block)?;
+        let len = u32::try_from(payload.len()).map_err(|e| Error::Serialize(e.to_string()))?;
+        let mut record = Vec::with_capacity(RECORD_HEADER + payload.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        self.file.seek(SeekFrom::Start(self.end))?;
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+
+        self.heights.insert(block.hash, self.offsets.len() as u64);
+        self.offsets.push(self.end);
+        self.end += record.len() as u64;
+        Ok(())
+    }
+
+    fn get(&self, height: u64) -> Result<Option<Block>, Error> {
+        let offset = match self.offsets.get(height as usize) {
+            Some(offset) => *offset,
+            None => return Ok(None),
+        };
+        let mut file = &self.file;
+        let mut header = [0u8; RECORD_HEADER];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let mut payload = vec![0u8; len as usize];
+        file.read_exact(&mut payload)?;
+        if crc32fast::hash(&payload)
+            != u32::from_le_bytes([header[4], header[5], header[6], header[7]])
+        {
+            return Err(Error::Serialize(format!(
+                "checksum mismatch of block {}",
+                height
+            )));
+        }
+        Ok(Some(bincode::deserialize(&payload)?))
+    }
+
+    fn height_of(&self, hash: &Sha256) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    fn len(&self) -> u64 {
+        self.offsets.len() as u64
+    }
+
+    fn truncate(&mut self, height: u64) -> Result<(), Error> {
+        let end = match self.offsets.get(height as usize) {
+            Some(offset) => *offset,
+            None => return Ok(()),
+        };
+        self.file.set_len(end)?;
+        self.file.sync_all()?;
+        self.offsets.truncate(height as usize);
+        self.heights
+            .retain(|_, block_height| *block_height < height);
+        self.end = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_store {
+    use super::*;
+    use crate::blockchain::BlockChain;
+    use crate::constant::*;
+
+    fn blocks() -> Vec<Block> {
+        let mut chain = BlockChain::new();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        chain.mine_transaction(&KEY2.3).unwrap();
+        chain.chain
+    }
+
+    #[test]
+    fn test_append_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let blocks = blocks();
+        let mut store = FileStore::open(&path).unwrap();
+        for block in blocks.iter() {
+            store.append(block).unwrap();
+        }
+        drop(store);
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get(1).unwrap().as_ref(), Some(&blocks[1]));
+        assert_eq!(
+            store.get_by_hash(&blocks[2].hash).unwrap().as_ref(),
+            Some(&blocks[2])
+        );
+        assert_eq!(store.height_of(&blocks[0].hash), Some(0));
+        assert_eq!(store.get(3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let blocks = blocks();
+        let mut store = FileStore::open(&path).unwrap();
+        store.append(&blocks[0]).unwrap();
+        store.append(&blocks[1]).unwrap();
+        let intact = store.end;
+        drop(store);
+
+        // a crash in the middle of writing the third record
+        let record = bincode::serialize(&blocks[2]).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&(record.len() as u32).to_le_bytes())
+            .unwrap();
+        file.write_all(&crc32fast::hash(&record).to_le_bytes())
+            .unwrap();
+        file.write_all(&record[..record.len() / 2]).unwrap();
+        drop(file);
+
+        let mut store = FileStore::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), intact);
+        store.append(&blocks[2]).unwrap();
+        drop(store);
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.get(2).unwrap().as_ref(), Some(&blocks[2]));
+    }
+
+    #[test]
+    fn test_corrupted_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let blocks = blocks();
+        let mut store = FileStore::open(&path).unwrap();
+        store.append(&blocks[0]).unwrap();
+        store.append(&blocks[1]).unwrap();
+        let second = store.offsets[1];
+        drop(store);
+
+        let mut raw = std::fs::read(&path).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 0xff;
+        std::fs::write(&path, raw).unwrap();
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.end, second);
+    }
+}
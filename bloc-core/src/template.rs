@@ -0,0 +1,194 @@
+use crate::block::Block;
+use crate::mempool::{Mempool, PoolEntry};
+use crate::transaction::Transaction;
+use crate::utxo::UtxoTransaction;
+use crate::{Amount, Error};
+
+/// serialized size in bytes a mined block may reach
+pub const MAX_BLOCK_SIZE: u64 = 1_000_000;
+/// number of transactions a mined block may hold, the coinbase included
+pub const MAX_BLOCK_TRANSACTIONS: usize = 4096;
+
+/// bounds of the blocks a chain assembles from its pending transactions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemplateLimits {
+    pub max_size: u64,
+    pub max_transactions: usize,
+}
+
+impl Default for TemplateLimits {
+    fn default() -> Self {
+        Self {
+            max_size: MAX_BLOCK_SIZE,
+            max_transactions: MAX_BLOCK_TRANSACTIONS,
+        }
+    }
+}
+
+/// a candidate block on top of the chain, ready to be mined
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTemplate {
+    /// linked to the tip at the next target, with its coinbase
+    pub block: Block,
+    pub height: u64,
+    /// tips of the selected transactions, paid by the coinbase with the reward
+    pub tips: Amount,
+    /// serialized size of the block
+    pub size: u64,
+}
+
+/// pick the pending transactions paying the most tips into `max_size`
+/// bytes and `max_count` transactions: every sender's transactions in
+/// nonce order, taking at each step the run of a sender's next ones with
+/// the highest tips per byte, so a cheap transaction can be carried by the
+/// later ones of its sender
+pub fn select_transactions(mempool: &Mempool, max_size: u64, max_count: usize) -> Vec<Transaction> {
+    let mut senders: Vec<&[&PoolEntry]> = Vec::new();
+    let by_sender = mempool.by_sender();
+    senders.extend(by_sender.iter().map(|entries| entries.as_slice()));
+    let mut selected = Vec::new();
+    let mut size = 0u64;
+    loop {
+        // (sender, run length, tips, size) of the best run
+        let mut best: Option<(usize, usize, u128, u128)> = None;
+        for (index, entries) in senders.iter().enumerate() {
+            let (mut run_tips, mut run_size) = (0u128, 0u128);
+            for (count, entry) in entries.iter().enumerate() {
+                if selected.len() + count + 1 > max_count
+                    || size + run_size as u64 + entry.size > max_size
+                {
+                    break;
+                }
+                run_tips += entry.trans.tips.base_units() as u128;
+                run_size += entry.size as u128;
+                let better = match best {
+                    None => true,
+                    Some((_, _, best_tips, best_size)) => {
+                        run_tips * best_size > best_tips * run_size
+                    }
+                };
+                if better {
+                    best = Some((index, count + 1, run_tips, run_size));
+                }
+            }
+        }
+        let (index, count, _, run_size) = match best {
+            Some(best) => best,
+            None => break,
+        };
+        let (run, rest) = senders[index].split_at(count);
+        selected.extend(run.iter().map(|entry| entry.trans.clone()));
+        size += run_size as u64;
+        senders[index] = rest;
+    }
+    selected
+}
+
+/// serialized size of `block`
+pub fn block_size(block: &Block) -> Result<u64, Error> {
+    Ok(bincode::serialized_size(block)?)
+}
+
+/// serialized size of `tx` inside a block
+pub fn utxo_size(tx: &UtxoTransaction) -> Result<u64, Error> {
+    Ok(bincode::serialized_size(tx)?)
+}
+
+#[cfg(test)]
+mod test_template {
+    use super::*;
+    use crate::blockchain::test_block_chain::chain_with_funds;
+    use crate::blockchain::BlockChain;
+    use crate::constant::*;
+
+    fn transfer(chain: &BlockChain, from: usize, tips: u64, nonce: u64) -> Transaction {
+        let (key, to) = match from {
+            1 => (&KEY1.2, KEY2.3),
+            _ => (&KEY2.2, KEY1.3),
+        };
+        let mut trans = Transaction::new(
+            key.to_public_key().unwrap(),
+            to,
+            Amount::from_coins(1).unwrap(),
+            Some(Amount::from_base_units(tips)),
+            nonce,
+        );
+        trans.sign(key, &chain.chain_id).unwrap();
+        trans
+    }
+
+    #[test]
+    fn test_select() {
+        let mut chain = chain_with_funds();
+        chain.mine_transaction(&KEY2.3).unwrap();
+        let cheap = transfer(&chain, 1, 1, 0);
+        let carried = transfer(&chain, 1, 100, 1);
+        let middle = transfer(&chain, 2, 40, 0);
+        for trans in [cheap.clone(), carried.clone(), middle.clone()] {
+            chain.add_transaction(trans).unwrap();
+        }
+        // the cheap transaction rides along its expensive successor
+        assert_eq!(
+            select_transactions(&chain.mempool, u64::MAX, usize::MAX),
+            vec![cheap.clone(), carried, middle.clone()]
+        );
+        // with room for one, the best single transaction wins
+        assert_eq!(
+            select_transactions(&chain.mempool, u64::MAX, 1),
+            vec![middle.clone()]
+        );
+        let size = bincode::serialized_size(&cheap).unwrap();
+        assert_eq!(
+            select_transactions(&chain.mempool, size, usize::MAX),
+            vec![middle]
+        );
+        assert!(select_transactions(&chain.mempool, size - 1, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_block_template() {
+        let mut chain = chain_with_funds();
+        let tips = 25;
+        for nonce in 0..3 {
+            chain
+                .add_transaction(transfer(&chain, 1, tips, nonce))
+                .unwrap();
+        }
+        chain.limits.max_transactions = 3;
+        let template = chain.block_template(&KEY2.3).unwrap();
+        assert_eq!(template.height, 1);
+        assert_eq!(template.tips, Amount::from_base_units(2 * tips));
+        assert_eq!(template.block.data.len(), 3);
+        let coinbase = template.block.data.last().unwrap();
+        assert_eq!(coinbase.from, chain.mint_address);
+        assert_eq!(coinbase.to, KEY2.3);
+        assert_eq!(
+            coinbase.amount,
+            chain.reward.checked_add(template.tips).unwrap()
+        );
+        assert_eq!(template.size, block_size(&template.block).unwrap());
+        assert_eq!(template.block.prev_hash, chain.get_last_block().hash);
+
+        // the transactions left out wait for the next block
+        chain.mine_transaction(&KEY2.3).unwrap();
+        assert_eq!(chain.chain[1].data.len(), 3);
+        assert_eq!(chain.mempool.len(), 1);
+        assert_eq!(chain.is_valid(), Ok(()));
+    }
+
+    #[test]
+    fn test_block_template_size() {
+        let mut chain = chain_with_funds();
+        for nonce in 0..3 {
+            chain
+                .add_transaction(transfer(&chain, 1, 1, nonce))
+                .unwrap();
+        }
+        let full = chain.block_template(&KEY2.3).unwrap();
+        assert_eq!(full.block.data.len(), 4);
+        chain.limits.max_size = full.size - 1;
+        let template = chain.block_template(&KEY2.3).unwrap();
+        assert_eq!(template.block.data.len(), 3);
+        assert!(template.size <= chain.limits.max_size);
+    }
+}
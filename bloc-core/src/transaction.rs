@@ -0,0 +1,209 @@
+use crate::{blockchain::*, constant::*, Address, Amount, Error};
+use bitcoin_hashes::{sha256, sha256::Hash as Sha256, Hash, HashEngine};
+//use secp256k1::{ecdsa::Signature, KeyPair, Message, PubKey, *};
+use crate::secp256k1::{serde_signature, KeyPair, PubKey, ToHex};
+use k256::ecdsa::Signature;
+use serde::{Deserialize, Serialize};
+
+/// represent a transaction sent by a peer, paying `amount` from the key
+/// `from` to the address `to`
+///
+/// `nonce` is the number of transactions `from` sent before, or the block
+/// height for the reward paid by the mint, so a signed transfer is only
+/// valid once
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Transaction {
+    pub from: PubKey,
+    pub to: Address,
+    pub amount: Amount,
+    pub tips: Amount,
+    pub nonce: u64,
+    #[serde(with = "serde_signature")]
+    pub signature: Signature,
+}
+
+impl Transaction {
+    pub fn new(
+        from: PubKey,
+        to: Address,
+        amount: Amount,
+        tips: Option<Amount>,
+        nonce: u64,
+    ) -> Self {
+        let tips = tips.unwrap_or_default();
+        Self {
+            from,
+            to,
+            amount,
+            tips,
+            nonce,
+            signature: MINT_KEY.3,
+        }
+    }
+
+    /// hash of the signed transaction
+    pub fn txid(&self) -> Result<Sha256, Error> {
+        Ok(sha256::Hash::hash(&bincode::serialize(self)?))
+    }
+
+    /// the message signed by `from`, bound to the chain `chain_id`
+    pub fn transaction_msg(&self, chain_id: &Sha256) -> Result<sha256::Hash, Error> {
+        let mut hash_engine = sha256::HashEngine::default();
+        hash_engine.input(chain_id);
+        hash_engine.input(self.from.as_ref());
+        hash_engine.input(self.to.as_ref());
+        hash_engine.input(&bincode::serialize(&self.amount)?);
+        hash_engine.input(&bincode::serialize(&self.tips)?);
+        hash_engine.input(&bincode::serialize(&self.nonce)?);
+        Ok(sha256::Hash::from_engine(hash_engine))
+    }
+
+    /// check the transaction against the confirmed balance of `from` on `chain`
+    pub fn is_valid(&self, chain: &BlockChain) -> Result<(), Error> {
+        let balance = chain.get_balance(&Address::from(&self.from))?;
+        self.verify(&chain.chain_id, &chain.mint_address, balance)
+    }
+
+    /// check the transaction of the chain `chain_id` when `from` holds
+    /// `balance`, which the mint is not limited by
+    pub fn verify(&self, chain_id: &Sha256, mint: &PubKey, balance: Amount) -> Result<(), Error> {
+        let msg = self.transaction_msg(chain_id)?;
+        if self.from.to_hex().starts_with(&ZEROKEY as &str) || self.to.is_null() {
+            return Err(Error::NullAddress);
+        }
+        if self.amount.is_zero() {
+            return Err(Error::ZeroAmount);
+        }
+        if &self.from != mint {
+            let required = self.amount.checked_add(self.tips)?;
+            log::debug!("balance check: {} >= {}", balance, required);
+            if balance < required {
+                return Err(Error::InsufficientBalance { balance, required });
+            }
+        }
+        self.from
+            .verify(msg.as_inner(), &self.signature)
+            .map_err(|_| Error::BadSignature)
+    }
+
+    pub fn sign(&mut self, key_pair: &KeyPair, chain_id: &Sha256) -> Result<(), Error> {
+        let public_key = key_pair
+            .to_public_key()
+            .map_err(|e| Error::Key(e.to_string()))?;
+        if public_key != self.from {
+            log::debug!(
+                "the public key not matched: {} | {}",
+                public_key.to_hex(),
+                self.from.to_hex()
+            );
+            return Err(Error::KeyMismatch);
+        }
+        let msg = self.transaction_msg(chain_id)?;
+        log::debug!("sign msg: {}", msg);
+        let secret_key = key_pair
+            .to_secret_key()
+            .map_err(|e| Error::Key(e.to_string()))?;
+        self.signature = secret_key.sign(&msg);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_transaction {
+    use crate::secp256k1::{ToHex, *};
+    use crate::transaction::*;
+    use crate::Error;
+
+    #[test]
+    fn test_new() {
+        let mut secp = Secp256K1::new();
+        let (_, pub1) = secp.gen_keypair();
+        let (_, pub2) = secp.gen_keypair();
+        let from = pub1;
+        let to = Address::from(&pub2);
+        let amount = Amount::from_coins(3000).unwrap();
+        let item = Transaction::new(from, to, amount, None, 0);
+        assert_eq!(item.from, pub1);
+        assert_eq!(item.to, to);
+        assert_eq!(item.amount, amount);
+        assert_eq!(item.tips, Amount::ZERO);
+        assert_eq!(item.signature.to_bytes(), MINT_KEY.3.to_bytes());
+    }
+
+    #[test]
+    fn test_transaction_msg() {
+        let mut secp = Secp256K1::new();
+        let (_, pub1) = secp.gen_keypair();
+        let (_, pub2) = secp.gen_keypair();
+        let from = pub1;
+        let to = Address::from(&pub2);
+        let amount = Amount::from_coins(3000).unwrap();
+        let item = Transaction::new(from, to, amount, None, 0);
+        let msg = item.transaction_msg(&BlockChain::new().chain_id);
+        assert!(msg.is_ok(), "failed to hash transaction into message");
+        let msg = msg.unwrap();
+        dbg!(&msg);
+        assert_eq!(msg.to_vec().len(), 32);
+    }
+
+    #[test]
+    fn test_sign() {
+        let mut secp = Secp256K1::new();
+        let (secret1, pub1) = secp.gen_keypair();
+        let key_pair1 = KeyPair::from(&secret1);
+        assert_eq!(
+            key_pair1.to_public_key().unwrap(),
+            pub1,
+            "it should equal for the public key"
+        );
+        let (_, pub2) = secp.gen_keypair();
+        let amount = Amount::from_coins(3000).unwrap();
+        let chain_id = BlockChain::new().chain_id;
+        let mut item = Transaction::new(pub1, pub2.into(), amount, None, 0);
+        assert!(item.sign(&key_pair1, &chain_id).is_ok());
+        assert_ne!(item.signature, MINT_KEY.3, "signature not signed");
+    }
+
+    #[test]
+    fn test_sign_key_mismatch() {
+        let mut secp = Secp256K1::new();
+        let (_, pub1) = secp.gen_keypair();
+        let (secret2, pub2) = secp.gen_keypair();
+        let chain_id = BlockChain::new().chain_id;
+        let mut item = Transaction::new(
+            pub1,
+            pub2.into(),
+            Amount::from_coins(3000).unwrap(),
+            None,
+            0,
+        );
+        assert_eq!(
+            item.sign(&KeyPair::from(&secret2), &chain_id),
+            Err(Error::KeyMismatch)
+        );
+        assert_eq!(item.signature, MINT_KEY.3, "signature must be untouched");
+    }
+
+    #[test]
+    fn test_is_valid() {
+        let chain = crate::blockchain::test_block_chain::chain_with_funds();
+        let mut item = Transaction::new(KEY1.1, KEY2.3, Amount::ZERO, None, 0);
+        item.sign(&KEY1.2, &chain.chain_id).unwrap();
+        assert_eq!(item.is_valid(&chain), Err(Error::ZeroAmount));
+
+        let amount = Amount::from_coins(99999).unwrap();
+        let tips = "1.5".parse().unwrap();
+        let mut item = Transaction::new(KEY1.1, KEY2.3, amount, Some(tips), 0);
+        item.sign(&KEY1.2, &chain.chain_id).unwrap();
+        assert_eq!(
+            item.is_valid(&chain),
+            Err(Error::InsufficientBalance {
+                balance: Amount::from_coins(100000).unwrap(),
+                required: "100000.5".parse().unwrap(),
+            })
+        );
+
+        let item = Transaction::new(KEY1.1, KEY2.3, Amount::from_coins(300).unwrap(), None, 0);
+        assert_eq!(item.is_valid(&chain), Err(Error::BadSignature));
+    }
+}
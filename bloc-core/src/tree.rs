@@ -0,0 +1,495 @@
+use crate::validation::{ChainParams, HeaderState, KEPT_TIMESTAMPS};
+use crate::{block::Block, Error};
+use bitcoin_hashes::sha256::Hash as Sha256;
+use std::collections::{HashMap, HashSet};
+
+/// how far below the tip of the active chain a side branch may fork, deeper
+/// branches are refused and pruned
+pub const MAX_FORK_DEPTH: u64 = 100;
+
+/// a block of the tree with its position and the work of its branch
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeNode {
+    pub block: Block,
+    pub height: u64,
+    /// work of the blocks from genesis to this one, genesis excluded
+    pub work: u128,
+}
+
+/// every valid-looking block known to a chain, active or on a competing
+/// branch, indexed by hash
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTree {
+    nodes: HashMap<Sha256, TreeNode>,
+    genesis: Sha256,
+}
+
+impl BlockTree {
+    pub fn new(genesis: Block) -> Self {
+        let hash = genesis.hash;
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            hash,
+            TreeNode {
+                block: genesis,
+                height: 0,
+                work: 0,
+            },
+        );
+        Self {
+            nodes,
+            genesis: hash,
+        }
+    }
+
+    pub fn get(&self, hash: &Sha256) -> Option<&TreeNode> {
+        self.nodes.get(hash)
+    }
+
+    pub fn contains(&self, hash: &Sha256) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// add `block` under its parent, which must already be in the tree
+    pub fn insert(&mut self, block: Block) -> Result<&TreeNode, Error> {
+        let parent = self
+            .nodes
+            .get(&block.prev_hash)
+            .ok_or(Error::UnknownParent(block.prev_hash))?;
+        let height = parent.height + 1;
+        let work = parent.work.saturating_add(block.work()?);
+        let hash = block.hash;
+        Ok(self.nodes.entry(hash).or_insert(TreeNode {
+            block,
+            height,
+            work,
+        }))
+    }
+
+    /// the blocks no other block builds on
+    pub fn tips(&self) -> Vec<&TreeNode> {
+        let parents: HashSet<&Sha256> = self
+            .nodes
+            .values()
+            .filter(|node| node.block.hash != self.genesis)
+            .map(|node| &node.block.prev_hash)
+            .collect();
+        self.nodes
+            .values()
+            .filter(|node| !parents.contains(&node.block.hash))
+            .collect()
+    }
+
+    /// the state after `hash`, which a block building on it must match
+    pub fn header_state(&self, params: &ChainParams, hash: &Sha256) -> Result<HeaderState, Error> {
+        let last = self.nodes.get(hash).ok_or(Error::UnknownParent(*hash))?;
+        let mut timestamps = vec![last.block.timestamp];
+        let mut node = last;
+        while timestamps.len() < KEPT_TIMESTAMPS && node.height > 0 {
+            node = self
+                .nodes
+                .get(&node.block.prev_hash)
+                .ok_or(Error::UnknownParent(node.block.prev_hash))?;
+            timestamps.push(node.block.timestamp);
+        }
+        timestamps.reverse();
+        HeaderState::after(params, last.height, &last.block, timestamps)
+    }
+
+    /// the tip with the most work, the lowest one on a tie
+    pub fn best_tip(&self) -> &TreeNode {
+        self.tips()
+            .into_iter()
+            .max_by(|a, b| a.work.cmp(&b.work).then(b.height.cmp(&a.height)))
+            .expect("the tree holds at least genesis")
+    }
+
+    /// drop the side branches forking from `active`, the active chain, more
+    /// than `MAX_FORK_DEPTH` blocks below its tip
+    pub fn prune(&mut self, active: &[Block]) {
+        let tip_height = active.len() as u64 - 1;
+        let is_active = |node: &TreeNode| {
+            active.get(node.height as usize).map(|block| block.hash) == Some(node.block.hash)
+        };
+        let stale: Vec<Sha256> = self
+            .nodes
+            .values()
+            .filter(|node| !is_active(node))
+            .filter(|node| {
+                self.nodes.get(&node.block.prev_hash).is_some_and(|parent| {
+                    is_active(parent) && parent.height + MAX_FORK_DEPTH < tip_height
+                })
+            })
+            .map(|node| node.block.hash)
+            .collect();
+        for hash in stale.iter() {
+            self.remove_branch(hash);
+        }
+    }
+
+    /// drop `hash` and every block building on it
+    pub fn remove_branch(&mut self, hash: &Sha256) {
+        if hash == &self.genesis {
+            return;
+        }
+        let mut removed = HashSet::new();
+        removed.insert(*hash);
+        loop {
+            let descendants: Vec<Sha256> = self
+                .nodes
+                .values()
+                .filter(|node| {
+                    removed.contains(&node.block.prev_hash) && !removed.contains(&node.block.hash)
+                })
+                .map(|node| node.block.hash)
+                .collect();
+            if descendants.is_empty() {
+                break;
+            }
+            removed.extend(descendants);
+        }
+        self.nodes.retain(|hash, _| !removed.contains(hash));
+    }
+}
+
+/// what submitting a block did to the active chain
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainEvent {
+    /// the block is already known
+    Duplicate,
+    /// the block extended the active chain
+    Connected { height: u64 },
+    /// the block is kept on a branch with no more work than the active one
+    SideBranch { height: u64 },
+    /// a branch with more work replaced the tip of the active chain
+    Reorganized(Reorg),
+}
+
+/// a switch of the active chain to another branch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reorg {
+    /// height of the last block both branches share
+    pub fork_height: u64,
+    /// hashes of the blocks rolled back, from the old tip down
+    pub disconnected: Vec<Sha256>,
+    /// hashes of the blocks applied, from the fork up to the new tip
+    pub connected: Vec<Sha256>,
+    /// transactions of the rolled back blocks returned to the pending ones
+    pub resubmitted: usize,
+}
+
+#[cfg(test)]
+mod test_tree {
+    use super::*;
+    use crate::blockchain::test_block_chain::chain_with_funds;
+    use crate::blockchain::BlockChain;
+    use crate::constant::*;
+    use crate::genesis::GenesisConfig;
+    use crate::store::{BlockStore, MemoryStore};
+    use crate::transaction::Transaction;
+    use crate::utxo::{ChainModel, UtxoView};
+    use crate::Amount;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    // a store whose next append fails once `fail` is raised
+    #[derive(Default, Clone)]
+    struct FlakyStore {
+        blocks: Arc<Mutex<MemoryStore>>,
+        fail: Arc<AtomicBool>,
+    }
+
+    impl BlockStore for FlakyStore {
+        fn append(&mut self, block: &Block) -> Result<(), Error> {
+            if self.fail.swap(false, Ordering::SeqCst) {
+                return Err(Error::Io("disk full".to_owned()));
+            }
+            self.blocks.lock().unwrap().append(block)
+        }
+
+        fn get(&self, height: u64) -> Result<Option<Block>, Error> {
+            self.blocks.lock().unwrap().get(height)
+        }
+
+        fn height_of(&self, hash: &Sha256) -> Option<u64> {
+            self.blocks.lock().unwrap().height_of(hash)
+        }
+
+        fn len(&self) -> u64 {
+            self.blocks.lock().unwrap().len()
+        }
+
+        fn truncate(&mut self, height: u64) -> Result<(), Error> {
+            self.blocks.lock().unwrap().truncate(height)
+        }
+    }
+
+    impl FlakyStore {
+        fn blocks(&self) -> Vec<Block> {
+            (0..self.len())
+                .map(|height| self.get(height).unwrap().unwrap())
+                .collect()
+        }
+    }
+
+    fn transfer(chain: &BlockChain, coins: u64) -> Transaction {
+        let mut trans = Transaction::new(
+            KEY1.1,
+            KEY2.3,
+            Amount::from_coins(coins).unwrap(),
+            None,
+            chain.next_nonce(&KEY1.1),
+        );
+        trans.sign(&KEY1.2, &chain.chain_id).unwrap();
+        trans
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut chain = BlockChain::new();
+        let mut fork = chain.clone();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+
+        let mut tree = BlockTree::new(chain.chain[0].clone());
+        tree.insert(chain.chain[1].clone()).unwrap();
+        assert_eq!(
+            tree.insert(fork.chain[2].clone()).err(),
+            Some(Error::UnknownParent(fork.chain[1].hash))
+        );
+        tree.insert(fork.chain[1].clone()).unwrap();
+        tree.insert(fork.chain[2].clone()).unwrap();
+        assert_eq!(tree.tips().len(), 2);
+        let best = tree.best_tip();
+        assert_eq!(best.block, fork.chain[2]);
+        assert_eq!(best.height, 2);
+        assert_eq!(best.work, fork.chain_work().unwrap());
+
+        tree.remove_branch(&fork.chain[1].hash);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.best_tip().block, chain.chain[1]);
+    }
+
+    #[test]
+    fn test_reorg() {
+        let mut chain = chain_with_funds();
+        let mut fork = chain.clone();
+        let trans = transfer(&chain, 5);
+        chain.add_transaction(trans.clone()).unwrap();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        let old_tip = chain.get_last_block().hash;
+
+        fork.mine_transaction(&KEY2.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        assert_eq!(
+            chain.submit_block(fork.chain[1].clone()),
+            Ok(ChainEvent::SideBranch { height: 1 })
+        );
+        assert_eq!(chain.get_last_block().hash, old_tip);
+        assert_eq!(
+            chain.submit_block(fork.chain[1].clone()),
+            Ok(ChainEvent::Duplicate)
+        );
+
+        let event = chain.submit_block(fork.chain[2].clone()).unwrap();
+        assert_eq!(
+            event,
+            ChainEvent::Reorganized(Reorg {
+                fork_height: 0,
+                disconnected: vec![old_tip],
+                connected: vec![fork.chain[1].hash, fork.chain[2].hash],
+                resubmitted: 1,
+            })
+        );
+        assert_eq!(chain.chain, fork.chain);
+        assert_eq!(chain.mempool.transactions(), vec![trans]);
+        assert_eq!(chain.nonce_of(&KEY1.1), 0);
+        assert_eq!(
+            chain.get_balance(&KEY2.3),
+            Ok(Amount::from_coins(200).unwrap())
+        );
+
+        // the returned transfer is mined again on the new branch
+        chain.mine_transaction(&KEY1.3).unwrap();
+        assert_eq!(chain.nonce_of(&KEY1.1), 1);
+        assert_eq!(chain.is_valid(), Ok(()));
+        assert_eq!(chain.tree.len(), 5);
+    }
+
+    #[test]
+    fn test_reorg_invalid_branch() {
+        let mut chain = chain_with_funds();
+        let mut fork = chain.clone();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        let tip = chain.get_last_block().clone();
+
+        // a branch paying its miner twice the reward
+        fork.reward = fork.reward.checked_mul(2).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        chain.submit_block(fork.chain[1].clone()).unwrap();
+        assert_eq!(
+            chain.submit_block(fork.chain[2].clone()),
+            Err(Error::InvalidReward)
+        );
+        assert_eq!(chain.get_last_block(), &tip);
+        assert!(!chain.tree.contains(&fork.chain[1].hash));
+        assert_eq!(chain.is_valid(), Ok(()));
+
+        let mut orphan = fork.chain[2].clone();
+        orphan.prev_hash = orphan.hash;
+        orphan.hash = orphan.get_hash();
+        orphan.mine().unwrap();
+        assert!(matches!(
+            chain.submit_block(orphan),
+            Err(Error::UnknownParent(_))
+        ));
+    }
+
+    #[test]
+    fn test_side_branch_checks() {
+        // a target kept constant, for the chain to grow past the fork depth
+        let config = GenesisConfig {
+            block_time: 0,
+            ..Default::default()
+        };
+        let mut chain = BlockChain::from_genesis(&config).unwrap();
+        let mut fork = chain.clone();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+
+        // mined at an easier target than its parent asks for
+        let mut easy = fork.chain[1].clone();
+        easy.bits = crate::pow::POW_LIMIT_BITS;
+        easy.hash = easy.get_hash();
+        easy.mine().unwrap();
+        assert_eq!(
+            chain.submit_block(easy),
+            Err(Error::InvalidTarget(crate::pow::POW_LIMIT_BITS))
+        );
+        assert_eq!(chain.tree.len(), 2);
+
+        assert_eq!(
+            chain.submit_block(fork.chain[1].clone()),
+            Ok(ChainEvent::SideBranch { height: 1 })
+        );
+        for _ in 0..MAX_FORK_DEPTH {
+            chain.mine_transaction(&KEY1.3).unwrap();
+        }
+        // the branch forked from genesis is pruned, and not taken back
+        assert!(!chain.tree.contains(&fork.chain[1].hash));
+        assert_eq!(chain.tree.len(), chain.len());
+        assert_eq!(
+            chain.submit_block(fork.chain[1].clone()),
+            Err(Error::StaleFork(0))
+        );
+    }
+
+    #[test]
+    fn test_store_failure() {
+        let store = FlakyStore::default();
+        let config = GenesisConfig::default();
+        let mut chain = BlockChain::with_store(&config, Box::new(store.clone())).unwrap();
+        let mut other = chain.clone();
+        let mut fork = chain.clone();
+        other.mine_transaction(&KEY2.3).unwrap();
+        fork.mine_transaction(&KEY1.3).unwrap();
+        fork.mine_transaction(&KEY1.3).unwrap();
+
+        store.fail.store(true, Ordering::SeqCst);
+        let block = other.chain[1].clone();
+        assert!(matches!(
+            chain.submit_block(block.clone()),
+            Err(Error::Io(_))
+        ));
+        assert_eq!(chain.len(), 1);
+        assert!(!chain.tree.contains(&block.hash));
+        assert_eq!(
+            chain.submit_block(block),
+            Ok(ChainEvent::Connected { height: 1 })
+        );
+        assert_eq!(store.blocks(), chain.chain);
+
+        // a branch the store cannot switch to leaves the active chain as it
+        // was, in memory and on disk
+        let tip = chain.get_last_block().clone();
+        chain.submit_block(fork.chain[1].clone()).unwrap();
+        store.fail.store(true, Ordering::SeqCst);
+        assert!(matches!(
+            chain.submit_block(fork.chain[2].clone()),
+            Err(Error::Io(_))
+        ));
+        assert_eq!(chain.get_last_block(), &tip);
+        assert_eq!(chain.is_valid(), Ok(()));
+        assert_eq!(store.blocks(), chain.chain);
+
+        for block in fork.chain[1..].iter() {
+            chain.submit_block(block.clone()).unwrap();
+        }
+        assert_eq!(chain.chain, fork.chain);
+        assert_eq!(store.blocks(), chain.chain);
+    }
+
+    #[test]
+    fn test_reorg_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut chain = BlockChain::create(dir.path(), &GenesisConfig::default()).unwrap();
+        let mut fork = chain.clone();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        for block in fork.chain[1..].iter() {
+            chain.submit_block(block.clone()).unwrap();
+        }
+        drop(chain);
+
+        let reopened = BlockChain::open(dir.path()).unwrap();
+        assert_eq!(reopened.chain, fork.chain);
+        let store = crate::store::FileStore::open(dir.path().join("blocks.dat")).unwrap();
+        assert_eq!(store.len(), 3);
+    }
+
+    #[test]
+    fn test_reorg_utxo() {
+        let config = GenesisConfig {
+            model: ChainModel::Utxo,
+            ..funded_genesis(1000)
+        };
+        let mut chain = BlockChain::from_genesis(&config).unwrap();
+        let mut fork = chain.clone();
+        let tx = chain
+            .state
+            .utxo_set
+            .build_transaction(
+                &KEY1.2,
+                KEY2.3,
+                Amount::from_coins(10).unwrap(),
+                Amount::ZERO,
+                &UtxoView::default(),
+            )
+            .unwrap();
+        chain.add_utxo_transaction(tx.clone()).unwrap();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        fork.mine_transaction(&KEY2.3).unwrap();
+        for block in fork.chain[1..].iter() {
+            chain.submit_block(block.clone()).unwrap();
+        }
+        assert_eq!(chain.state.utxo_set.len(), fork.state.utxo_set.len());
+        assert_eq!(chain.utxo_transactions, vec![tx]);
+        assert_eq!(
+            chain.get_balance(&KEY1.3),
+            Ok(Amount::from_coins(1000).unwrap())
+        );
+    }
+}
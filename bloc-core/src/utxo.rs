@@ -0,0 +1,559 @@
+use crate::block::Block;
+use crate::secp256k1::{serde_signature, KeyPair, PubKey};
+use crate::{constant::*, Address, Amount, Error};
+use bitcoin_hashes::{sha256, sha256::Hash as Sha256, Hash, HashEngine};
+use k256::ecdsa::Signature;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// how a chain tracks ownership of coins
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainModel {
+    /// `Transaction`s moving `amount` from one key to another
+    #[default]
+    Account,
+    /// `UtxoTransaction`s spending previous outputs into new ones
+    Utxo,
+}
+
+/// reference to the `index`-th output of the transaction `txid`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutPoint {
+    pub txid: Sha256,
+    pub index: u32,
+}
+
+impl OutPoint {
+    /// the input of a coinbase, carrying the block height so that no two
+    /// coinbase transactions share a txid
+    pub fn coinbase(height: u32) -> Self {
+        Self {
+            txid: Sha256::from_inner([0u8; 32]),
+            index: height,
+        }
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.txid.into_inner() == [0u8; 32]
+    }
+}
+
+/// spends the output `prev_out`, signed by `public_key`, which must hash
+/// to the address the output was paid to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxIn {
+    pub prev_out: OutPoint,
+    pub public_key: PubKey,
+    #[serde(with = "serde_signature")]
+    pub signature: Signature,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxOut {
+    pub to: Address,
+    pub amount: Amount,
+}
+
+/// transaction of a `ChainModel::Utxo` chain, the difference between its
+/// inputs and outputs is the tip collected by the miner
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UtxoTransaction {
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+}
+
+impl UtxoTransaction {
+    pub fn new(inputs: Vec<OutPoint>, outputs: Vec<TxOut>) -> Self {
+        let inputs = inputs
+            .into_iter()
+            .map(|prev_out| TxIn {
+                prev_out,
+                public_key: MINT_KEY.1,
+                signature: MINT_KEY.3,
+            })
+            .collect();
+        Self { inputs, outputs }
+    }
+
+    /// issues `outputs` out of nothing at `height`, must be signed by the mint
+    pub fn coinbase(height: u32, outputs: Vec<TxOut>) -> Self {
+        Self::new(vec![OutPoint::coinbase(height)], outputs)
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].prev_out.is_coinbase()
+    }
+
+    /// the message signed by every input, covering all inputs and outputs
+    pub fn sighash(&self) -> Result<Sha256, Error> {
+        let prev_outs: Vec<&OutPoint> = self.inputs.iter().map(|input| &input.prev_out).collect();
+        let mut hash_engine = sha256::HashEngine::default();
+        hash_engine.input(&bincode::serialize(&prev_outs)?);
+        hash_engine.input(&bincode::serialize(&self.outputs)?);
+        Ok(sha256::Hash::from_engine(hash_engine))
+    }
+
+    pub fn txid(&self) -> Result<Sha256, Error> {
+        Ok(sha256::Hash::hash(&bincode::serialize(self)?))
+    }
+
+    pub fn output_total(&self) -> Result<Amount, Error> {
+        Amount::checked_sum(self.outputs.iter().map(|output| &output.amount))
+    }
+
+    /// sign every input with `key_pair`
+    pub fn sign(&mut self, key_pair: &KeyPair) -> Result<(), Error> {
+        for index in 0..self.inputs.len() {
+            self.sign_input(index, key_pair)?;
+        }
+        Ok(())
+    }
+
+    /// sign the input at `index`, for transactions spending outputs of
+    /// several keys
+    pub fn sign_input(&mut self, index: usize, key_pair: &KeyPair) -> Result<(), Error> {
+        let msg = self.sighash()?;
+        let secret_key = key_pair
+            .to_secret_key()
+            .map_err(|e| Error::Key(e.to_string()))?;
+        let public_key = key_pair
+            .to_public_key()
+            .map_err(|e| Error::Key(e.to_string()))?;
+        let input = self.inputs.get_mut(index).ok_or(Error::UnknownOutput)?;
+        input.public_key = public_key;
+        input.signature = secret_key.sign(&msg);
+        Ok(())
+    }
+}
+
+/// unspent outputs on top of a `UtxoSet`, collecting the changes of a block
+/// or of the pending transactions before they are applied
+#[derive(Default, Clone)]
+pub struct UtxoView {
+    created: HashMap<OutPoint, TxOut>,
+    spent: HashSet<OutPoint>,
+}
+
+impl UtxoView {
+    pub fn is_spent(&self, out_point: &OutPoint) -> bool {
+        self.spent.contains(out_point)
+    }
+}
+
+/// the outputs of a `ChainModel::Utxo` chain not spent yet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UtxoSet {
+    unspent: HashMap<OutPoint, TxOut>,
+    // outputs spent by each connected block, to restore on disconnect
+    undo: Vec<Vec<(OutPoint, TxOut)>>,
+}
+
+impl UtxoSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, out_point: &OutPoint) -> Option<&TxOut> {
+        self.unspent.get(out_point)
+    }
+
+    pub fn len(&self) -> usize {
+        self.unspent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unspent.is_empty()
+    }
+
+    /// unspent outputs paid to `address`, ordered by outpoint
+    pub fn unspent_of(&self, address: &Address) -> Vec<(OutPoint, TxOut)> {
+        let mut unspent: Vec<_> = self
+            .unspent
+            .iter()
+            .filter(|(_, output)| &output.to == address)
+            .map(|(out_point, output)| (*out_point, output.clone()))
+            .collect();
+        unspent.sort_by_key(|(out_point, _)| *out_point);
+        unspent
+    }
+
+    pub fn balance(&self, address: &Address) -> Result<Amount, Error> {
+        Amount::checked_sum(
+            self.unspent
+                .values()
+                .filter(|output| &output.to == address)
+                .map(|output| &output.amount),
+        )
+    }
+
+    fn lookup<'a>(&'a self, view: &'a UtxoView, out_point: &OutPoint) -> Option<&'a TxOut> {
+        if view.spent.contains(out_point) {
+            return None;
+        }
+        view.created
+            .get(out_point)
+            .or_else(|| self.unspent.get(out_point))
+    }
+
+    /// validate a regular transaction against the set and `view`, then record
+    /// its spends and outputs in `view`, returning the tips it pays
+    pub fn check_transaction(
+        &self,
+        tx: &UtxoTransaction,
+        view: &mut UtxoView,
+    ) -> Result<Amount, Error> {
+        if tx.inputs.is_empty() || tx.outputs.is_empty() {
+            return Err(Error::ZeroAmount);
+        }
+        if tx.outputs.iter().any(|output| output.amount.is_zero()) {
+            return Err(Error::ZeroAmount);
+        }
+        let msg = tx.sighash()?;
+        let mut input_total = Amount::ZERO;
+        let mut spending = HashSet::new();
+        for input in tx.inputs.iter() {
+            if input.prev_out.is_coinbase() {
+                return Err(Error::InvalidCoinbase);
+            }
+            if !spending.insert(input.prev_out) || view.spent.contains(&input.prev_out) {
+                return Err(Error::DoubleSpend);
+            }
+            let prev = self
+                .lookup(view, &input.prev_out)
+                .ok_or(Error::UnknownOutput)?;
+            if !prev.to.is_owned_by(&input.public_key) {
+                return Err(Error::BadSignature);
+            }
+            input
+                .public_key
+                .verify(msg.as_inner(), &input.signature)
+                .map_err(|_| Error::BadSignature)?;
+            input_total = input_total.checked_add(prev.amount)?;
+        }
+        let output_total = tx.output_total()?;
+        let tips =
+            input_total
+                .checked_sub(output_total)
+                .map_err(|_| Error::InsufficientBalance {
+                    balance: input_total,
+                    required: output_total,
+                })?;
+        self.record(tx, view)?;
+        Ok(tips)
+    }
+
+    fn record(&self, tx: &UtxoTransaction, view: &mut UtxoView) -> Result<(), Error> {
+        let txid = tx.txid()?;
+        if !tx.is_coinbase() {
+            for input in tx.inputs.iter() {
+                view.created.remove(&input.prev_out);
+                view.spent.insert(input.prev_out);
+            }
+        }
+        for (index, output) in tx.outputs.iter().enumerate() {
+            let out_point = OutPoint {
+                txid,
+                index: index as u32,
+            };
+            if self.unspent.contains_key(&out_point) || view.created.contains_key(&out_point) {
+                return Err(Error::DoubleSpend);
+            }
+            view.created.insert(out_point, output.clone());
+        }
+        Ok(())
+    }
+
+    /// validate the transactions of `block` at `height`: a single leading
+    /// coinbase signed by `mint` paying `reward` plus tips, except in the
+    /// genesis block which only issues the initial allocations
+    pub fn check_block(
+        &self,
+        block: &Block,
+        height: u32,
+        mint: &PubKey,
+        reward: Amount,
+    ) -> Result<UtxoView, Error> {
+        if !block.data.is_empty() {
+            return Err(Error::WrongModel);
+        }
+        let mut view = UtxoView::default();
+        let (coinbase, txs) = match block.utxo_data.split_first() {
+            Some(split) => split,
+            None if height == 0 => return Ok(view),
+            None => return Err(Error::InvalidCoinbase),
+        };
+        if !coinbase.is_coinbase() || coinbase.inputs[0].prev_out != OutPoint::coinbase(height) {
+            return Err(Error::InvalidCoinbase);
+        }
+        mint.verify(
+            coinbase.sighash()?.as_inner(),
+            &coinbase.inputs[0].signature,
+        )
+        .map_err(|_| Error::BadSignature)?;
+        let mut tips = Amount::ZERO;
+        for tx in txs.iter() {
+            tips = tips.checked_add(self.check_transaction(tx, &mut view)?)?;
+        }
+        if height != 0 && coinbase.output_total()? != reward.checked_add(tips)? {
+            return Err(Error::InvalidReward);
+        }
+        self.record(coinbase, &mut view)?;
+        Ok(view)
+    }
+
+    /// validate `block` and apply its spends and outputs
+    pub fn connect_block(
+        &mut self,
+        block: &Block,
+        height: u32,
+        mint: &PubKey,
+        reward: Amount,
+    ) -> Result<(), Error> {
+        let view = self.check_block(block, height, mint, reward)?;
+        let mut spent = Vec::with_capacity(view.spent.len());
+        for out_point in view.spent {
+            if let Some(output) = self.unspent.remove(&out_point) {
+                spent.push((out_point, output));
+            }
+        }
+        self.unspent.extend(view.created);
+        self.undo.push(spent);
+        Ok(())
+    }
+
+    /// revert `block`, which must be the last connected one
+    pub fn disconnect_block(&mut self, block: &Block) -> Result<(), Error> {
+        let spent = self.undo.pop().ok_or(Error::UnknownOutput)?;
+        for tx in block.utxo_data.iter() {
+            let txid = tx.txid()?;
+            for index in 0..tx.outputs.len() {
+                self.unspent.remove(&OutPoint {
+                    txid,
+                    index: index as u32,
+                });
+            }
+        }
+        self.unspent.extend(spent);
+        Ok(())
+    }
+
+    /// pay `amount` plus `tips` to `to` out of the outputs of `key_pair`,
+    /// sending the change back to it and skipping outputs spent in `view`
+    pub fn build_transaction(
+        &self,
+        key_pair: &KeyPair,
+        to: Address,
+        amount: Amount,
+        tips: Amount,
+        view: &UtxoView,
+    ) -> Result<UtxoTransaction, Error> {
+        let from = key_pair
+            .to_public_key()
+            .map(Address::from)
+            .map_err(|e| Error::Key(e.to_string()))?;
+        let required = amount.checked_add(tips)?;
+        let mut inputs = Vec::new();
+        let mut collected = Amount::ZERO;
+        for (out_point, output) in self.unspent_of(&from) {
+            if collected >= required {
+                break;
+            }
+            if view.is_spent(&out_point) {
+                continue;
+            }
+            collected = collected.checked_add(output.amount)?;
+            inputs.push(out_point);
+        }
+        if collected < required {
+            return Err(Error::InsufficientBalance {
+                balance: collected,
+                required,
+            });
+        }
+        let mut outputs = vec![TxOut { to, amount }];
+        let change = collected.checked_sub(required)?;
+        if !change.is_zero() {
+            outputs.push(TxOut {
+                to: from,
+                amount: change,
+            });
+        }
+        let mut tx = UtxoTransaction::new(inputs, outputs);
+        tx.sign(key_pair)?;
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod test_utxo {
+    use super::*;
+    use crate::blockchain::BlockChain;
+    use crate::genesis::GenesisConfig;
+
+    fn utxo_chain() -> BlockChain {
+        let config = GenesisConfig {
+            model: ChainModel::Utxo,
+            ..funded_genesis(1000)
+        };
+        BlockChain::from_genesis(&config).unwrap()
+    }
+
+    #[test]
+    fn test_change_output() {
+        let mut chain = utxo_chain();
+        assert_eq!(
+            chain.get_balance(&KEY1.3),
+            Ok(Amount::from_coins(1000).unwrap())
+        );
+        let amount = Amount::from_coins(300).unwrap();
+        let tips = Amount::from_coins(1).unwrap();
+        let tx = chain
+            .state
+            .utxo_set
+            .build_transaction(&KEY1.2, KEY2.3, amount, tips, &UtxoView::default())
+            .unwrap();
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].to, KEY1.3);
+        chain.add_utxo_transaction(tx).unwrap();
+        chain.mine_transaction(&KEY2.3).unwrap();
+
+        assert_eq!(
+            chain.get_balance(&KEY1.3),
+            Ok(Amount::from_coins(699).unwrap())
+        );
+        assert_eq!(
+            chain.get_balance(&KEY2.3),
+            Ok(Amount::from_coins(401).unwrap())
+        );
+        assert_eq!(chain.is_valid(), Ok(()));
+    }
+
+    #[test]
+    fn test_double_spend() {
+        let mut chain = utxo_chain();
+        let amount = Amount::from_coins(10).unwrap();
+        let tx1 = chain
+            .state
+            .utxo_set
+            .build_transaction(&KEY1.2, KEY2.3, amount, Amount::ZERO, &UtxoView::default())
+            .unwrap();
+        let tx2 = chain
+            .state
+            .utxo_set
+            .build_transaction(&KEY1.2, KEY1.3, amount, Amount::ZERO, &UtxoView::default())
+            .unwrap();
+        assert_eq!(tx1.inputs[0].prev_out, tx2.inputs[0].prev_out);
+
+        // across the pending transactions
+        chain.add_utxo_transaction(tx1.clone()).unwrap();
+        assert_eq!(
+            chain.add_utxo_transaction(tx2.clone()),
+            Err(Error::DoubleSpend)
+        );
+
+        // inside a block
+        let height = chain.len() as u32;
+        let mut view = UtxoView::default();
+        assert!(chain
+            .state
+            .utxo_set
+            .check_transaction(&tx1, &mut view)
+            .is_ok());
+        assert_eq!(
+            chain.state.utxo_set.check_transaction(&tx2, &mut view),
+            Err(Error::DoubleSpend)
+        );
+        let mut coinbase = UtxoTransaction::coinbase(
+            height,
+            vec![TxOut {
+                to: KEY2.3,
+                amount: chain.reward,
+            }],
+        );
+        coinbase.sign(&MINT_KEY_PAIR).unwrap();
+        let block = Block::new_utxo(chrono::Utc::now(), vec![coinbase, tx1, tx2]);
+        assert_eq!(
+            chain
+                .state
+                .utxo_set
+                .check_block(&block, height, &chain.mint_address, chain.reward)
+                .err(),
+            Some(Error::DoubleSpend)
+        );
+    }
+
+    #[test]
+    fn test_connect_disconnect() {
+        let mut chain = utxo_chain();
+        let before = chain.state.utxo_set.clone();
+        let tx = chain
+            .state
+            .utxo_set
+            .build_transaction(
+                &KEY1.2,
+                KEY2.3,
+                Amount::from_coins(5).unwrap(),
+                Amount::ZERO,
+                &UtxoView::default(),
+            )
+            .unwrap();
+        chain.add_utxo_transaction(tx).unwrap();
+        let block = chain.mine_transaction(&KEY1.3).unwrap();
+        assert_eq!(chain.state.utxo_set.len(), 3);
+
+        chain.state.utxo_set.disconnect_block(&block).unwrap();
+        assert_eq!(chain.state.utxo_set.unspent, before.unspent);
+        assert_eq!(chain.state.utxo_set.undo.len(), before.undo.len());
+    }
+
+    #[test]
+    fn test_wrong_signer() {
+        let chain = utxo_chain();
+        let (out_point, _) = chain.state.utxo_set.unspent_of(&KEY1.3)[0].clone();
+        let mut tx = UtxoTransaction::new(
+            vec![out_point],
+            vec![TxOut {
+                to: KEY2.3,
+                amount: Amount::from_coins(1).unwrap(),
+            }],
+        );
+        tx.sign(&KEY2.2).unwrap();
+        assert_eq!(
+            chain
+                .state
+                .utxo_set
+                .check_transaction(&tx, &mut UtxoView::default()),
+            Err(Error::BadSignature)
+        );
+    }
+
+    #[test]
+    fn test_model_export() {
+        let mut chain = utxo_chain();
+        let trans = crate::transaction::Transaction::new(KEY1.1, KEY2.3, Amount::ZERO, None, 0);
+        assert_eq!(chain.add_transaction(trans), Err(Error::WrongModel));
+        let tx = chain
+            .state
+            .utxo_set
+            .build_transaction(
+                &KEY1.2,
+                KEY2.3,
+                Amount::from_coins(7).unwrap(),
+                Amount::ZERO,
+                &chain.pending_view().unwrap(),
+            )
+            .unwrap();
+        chain.add_utxo_transaction(tx.clone()).unwrap();
+        chain.mine_transaction(&KEY2.3).unwrap();
+        chain.add_utxo_transaction(tx).unwrap_err();
+
+        let imported = BlockChain::from_json(&chain.to_json().unwrap()).unwrap();
+        assert!(imported == chain);
+        assert_eq!(imported.state.utxo_set, chain.state.utxo_set);
+        assert_eq!(
+            BlockChain::new().add_utxo_transaction(UtxoTransaction::new(vec![], vec![])),
+            Err(Error::WrongModel)
+        );
+    }
+}
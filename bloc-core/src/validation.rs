@@ -0,0 +1,626 @@
+use crate::block::{Block, BlockHeader, HEADER_VERSION};
+use crate::pow::{self, Target, RETARGET_WINDOW};
+use crate::secp256k1::PubKey;
+use crate::utxo::{ChainModel, UtxoSet};
+use crate::{Address, Amount, Error};
+use bitcoin_hashes::sha256::Hash as Sha256;
+use chrono::prelude::*;
+use std::collections::HashMap;
+
+/// number of last blocks whose median timestamp a new block must be after
+pub const MEDIAN_TIME_SPAN: usize = 11;
+/// how far ahead of the local clock a block may be stamped, in milliseconds
+pub const MAX_FUTURE_DRIFT: i64 = 2 * 60 * 60 * 1000;
+
+// timestamps kept by a state, enough for the median time and a retarget
+pub(crate) const KEPT_TIMESTAMPS: usize = if MEDIAN_TIME_SPAN > RETARGET_WINDOW {
+    MEDIAN_TIME_SPAN
+} else {
+    RETARGET_WINDOW
+};
+
+/// the rules a chain validates its blocks with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainParams {
+    pub chain_id: Sha256,
+    pub mint_address: PubKey,
+    pub reward: Amount,
+    /// milliseconds the retargeting aims for between two blocks
+    pub block_time: u32,
+    pub model: ChainModel,
+}
+
+/// the ledger after a block, which the next block is validated against
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainState {
+    /// height and hash of the last block
+    pub height: u64,
+    pub tip: Sha256,
+    /// compact target the next block must be mined at
+    pub bits: u32,
+    /// timestamps of the last blocks, the last one at the end
+    pub timestamps: Vec<DateTime<Utc>>,
+    /// non-zero balances of a `ChainModel::Account` chain
+    pub balances: HashMap<Address, Amount>,
+    /// next nonce of every account that sent a confirmed transaction
+    pub nonces: HashMap<PubKey, u64>,
+    /// unspent outputs of a `ChainModel::Utxo` chain, empty otherwise
+    pub utxo_set: UtxoSet,
+}
+
+/// the part of a `ChainState` the headers alone move, enough to check the
+/// next header before its block is downloaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderState {
+    pub height: u64,
+    pub tip: Sha256,
+    /// compact target the next header must be mined at
+    pub bits: u32,
+    /// timestamps of the last headers, the last one at the end
+    pub timestamps: Vec<DateTime<Utc>>,
+}
+
+impl HeaderState {
+    /// the state after the last of `blocks`, the chain from genesis on
+    pub fn from_blocks(params: &ChainParams, blocks: &[Block]) -> Result<Self, Error> {
+        let last = blocks.last().ok_or(Error::GenesisMismatch)?;
+        let start = blocks.len().saturating_sub(KEPT_TIMESTAMPS);
+        let timestamps = blocks[start..]
+            .iter()
+            .map(|block| block.timestamp)
+            .collect();
+        Self::after(params, blocks.len() as u64 - 1, last, timestamps)
+    }
+
+    /// the state after `last`, at `height`, `timestamps` being those of the
+    /// blocks up to it, oldest first
+    pub fn after(
+        params: &ChainParams,
+        height: u64,
+        last: &Block,
+        mut timestamps: Vec<DateTime<Utc>>,
+    ) -> Result<Self, Error> {
+        let start = timestamps.len().saturating_sub(KEPT_TIMESTAMPS);
+        timestamps.drain(..start);
+        Ok(Self {
+            height,
+            tip: last.hash,
+            bits: next_bits(params, height, last.bits, &timestamps)?,
+            timestamps,
+        })
+    }
+
+    pub fn median_time(&self) -> DateTime<Utc> {
+        median_time(&self.timestamps)
+    }
+
+    /// check `header` as the next one: its link, target, proof of work and
+    /// timestamp
+    pub fn validate_header(&self, header: &BlockHeader) -> Result<(), Error> {
+        if header.version != HEADER_VERSION {
+            return Err(Error::UnsupportedVersion(header.version));
+        }
+        if header.prev_hash != self.tip {
+            return Err(Error::InvalidPrevHash);
+        }
+        if header.bits != self.bits {
+            return Err(Error::InvalidTarget(header.bits));
+        }
+        if !Target::from_bits(header.bits)?.is_met_by(&header.hash()) {
+            return Err(Error::InvalidHash);
+        }
+        if header.timestamp <= self.median_time()
+            || header.timestamp > Utc::now() + chrono::Duration::milliseconds(MAX_FUTURE_DRIFT)
+        {
+            return Err(Error::InvalidTimestamp);
+        }
+        Ok(())
+    }
+
+    /// validate `header` and move the state past it
+    pub fn connect_header(
+        &mut self,
+        params: &ChainParams,
+        header: &BlockHeader,
+    ) -> Result<(), Error> {
+        self.validate_header(header)?;
+        self.height += 1;
+        self.tip = header.hash();
+        push_timestamp(&mut self.timestamps, header.timestamp);
+        self.bits = next_bits(params, self.height, header.bits, &self.timestamps)?;
+        Ok(())
+    }
+}
+
+// the balances and nonces a block of an account chain moves to
+struct AccountChanges {
+    balances: HashMap<Address, Amount>,
+    nonces: HashMap<PubKey, u64>,
+}
+
+impl ChainState {
+    /// the state after `genesis`, whose transactions are trusted
+    pub fn genesis(params: &ChainParams, genesis: &Block) -> Result<Self, Error> {
+        let mut state = Self {
+            height: 0,
+            tip: genesis.hash,
+            bits: genesis.bits,
+            timestamps: vec![genesis.timestamp],
+            balances: HashMap::new(),
+            nonces: HashMap::new(),
+            utxo_set: UtxoSet::new(),
+        };
+        state.apply_transactions(params, genesis, 0)?;
+        Ok(state)
+    }
+
+    /// confirmed balance of `address` on an account chain
+    pub fn balance(&self, address: &Address) -> Amount {
+        self.balances.get(address).copied().unwrap_or(Amount::ZERO)
+    }
+
+    /// nonce of the next confirmed transaction of `address`
+    pub fn nonce_of(&self, address: &PubKey) -> u64 {
+        self.nonces.get(address).copied().unwrap_or(0)
+    }
+
+    /// the median timestamp of the last blocks, a new block must be later
+    pub fn median_time(&self) -> DateTime<Utc> {
+        median_time(&self.timestamps)
+    }
+
+    pub fn header_state(&self) -> HeaderState {
+        HeaderState {
+            height: self.height,
+            tip: self.tip,
+            bits: self.bits,
+            timestamps: self.timestamps.clone(),
+        }
+    }
+
+    /// check `block` as the next block: its header, proof of work and
+    /// timestamp, then its transactions against the balances before it
+    pub fn validate_block(&self, params: &ChainParams, block: &Block) -> Result<(), Error> {
+        if block.hash != block.get_hash() {
+            return Err(Error::InvalidHash);
+        }
+        if block.merkle_root != block.compute_merkle_root()? {
+            return Err(Error::InvalidMerkleRoot);
+        }
+        self.header_state().validate_header(&block.header())?;
+        self.validate_transactions(params, block)
+    }
+
+    /// check the transactions of `block` as the next block against the
+    /// balances before it, leaving its header out, e.g. before mining it
+    pub fn validate_transactions(&self, params: &ChainParams, block: &Block) -> Result<(), Error> {
+        let height = self.height + 1;
+        match params.model {
+            ChainModel::Account => self
+                .account_changes(params, block, height, true)
+                .map(|_| ()),
+            ChainModel::Utxo => self
+                .utxo_set
+                .check_block(block, height as u32, &params.mint_address, params.reward)
+                .map(|_| ()),
+        }
+    }
+
+    /// validate `block` and move the state past it
+    pub fn connect_block(&mut self, params: &ChainParams, block: &Block) -> Result<(), Error> {
+        self.validate_block(params, block)?;
+        self.apply_block(params, block)
+    }
+
+    /// move the state past `block` without validating its header, nor the
+    /// transactions of an account chain
+    pub fn apply_block(&mut self, params: &ChainParams, block: &Block) -> Result<(), Error> {
+        self.apply_transactions(params, block, self.height + 1)?;
+        self.height += 1;
+        self.tip = block.hash;
+        push_timestamp(&mut self.timestamps, block.timestamp);
+        self.bits = next_bits(params, self.height, block.bits, &self.timestamps)?;
+        Ok(())
+    }
+
+    /// revert `block`, the last applied one, `blocks` being the chain
+    /// from genesis up to its parent
+    pub fn disconnect_block(
+        &mut self,
+        params: &ChainParams,
+        block: &Block,
+        blocks: &[Block],
+    ) -> Result<(), Error> {
+        let header = HeaderState::from_blocks(params, blocks)?;
+        match params.model {
+            ChainModel::Account => {
+                for trans in block.data.iter().rev() {
+                    let credit = self.balance(&trans.to).saturating_sub(trans.amount);
+                    self.set_balance(trans.to, credit);
+                    if trans.from == params.mint_address {
+                        continue;
+                    }
+                    let sender = Address::from(&trans.from);
+                    let debit = trans.amount.checked_add(trans.tips)?;
+                    let balance = self.balance(&sender).checked_add(debit)?;
+                    self.set_balance(sender, balance);
+                    if trans.nonce == 0 {
+                        self.nonces.remove(&trans.from);
+                    } else {
+                        self.nonces.insert(trans.from, trans.nonce);
+                    }
+                }
+            }
+            ChainModel::Utxo => self.utxo_set.disconnect_block(block)?,
+        }
+        self.height = header.height;
+        self.tip = header.tip;
+        self.bits = header.bits;
+        self.timestamps = header.timestamps;
+        Ok(())
+    }
+
+    fn set_balance(&mut self, address: Address, balance: Amount) {
+        if balance.is_zero() {
+            self.balances.remove(&address);
+        } else {
+            self.balances.insert(address, balance);
+        }
+    }
+
+    fn apply_transactions(
+        &mut self,
+        params: &ChainParams,
+        block: &Block,
+        height: u64,
+    ) -> Result<(), Error> {
+        match params.model {
+            ChainModel::Account => {
+                let changes = self.account_changes(params, block, height, false)?;
+                for (address, balance) in changes.balances {
+                    self.set_balance(address, balance);
+                }
+                self.nonces.extend(changes.nonces);
+                Ok(())
+            }
+            ChainModel::Utxo => self.utxo_set.connect_block(
+                block,
+                height as u32,
+                &params.mint_address,
+                params.reward,
+            ),
+        }
+    }
+
+    // the balances and nonces after the transfers of `block` at `height`;
+    // when `checked`, every transfer is validated against the balance of
+    // its sender before the block, less what it already sent in the block,
+    // and the block must pay exactly one reward plus its tips
+    fn account_changes(
+        &self,
+        params: &ChainParams,
+        block: &Block,
+        height: u64,
+        checked: bool,
+    ) -> Result<AccountChanges, Error> {
+        if checked && !block.utxo_data.is_empty() {
+            return Err(Error::WrongModel);
+        }
+        let mut changes = AccountChanges {
+            balances: HashMap::new(),
+            nonces: HashMap::new(),
+        };
+        let mut tips = Amount::ZERO;
+        let mut reward = Amount::ZERO;
+        let mut coinbase = 0;
+        let mut sent: HashMap<PubKey, Amount> = HashMap::new();
+        for trans in block.data.iter() {
+            let from_mint = trans.from == params.mint_address;
+            let sender = Address::from(&trans.from);
+            let balance = changes
+                .balances
+                .get(&sender)
+                .copied()
+                .unwrap_or_else(|| self.balance(&sender));
+            if checked {
+                let expected = if from_mint {
+                    height
+                } else {
+                    changes
+                        .nonces
+                        .get(&trans.from)
+                        .copied()
+                        .unwrap_or_else(|| self.nonce_of(&trans.from))
+                };
+                if trans.nonce != expected {
+                    return Err(Error::InvalidNonce {
+                        expected,
+                        found: trans.nonce,
+                    });
+                }
+                let spendable = self
+                    .balance(&sender)
+                    .saturating_sub(sent.get(&trans.from).copied().unwrap_or(Amount::ZERO));
+                trans.verify(&params.chain_id, &params.mint_address, spendable)?;
+            }
+            if from_mint {
+                coinbase += 1;
+                reward = trans.amount;
+            } else {
+                tips = tips.checked_add(trans.tips)?;
+                let debit = trans.amount.checked_add(trans.tips)?;
+                let total = sent.get(&trans.from).copied().unwrap_or(Amount::ZERO);
+                sent.insert(trans.from, total.checked_add(debit)?);
+                changes
+                    .balances
+                    .insert(sender, balance.saturating_sub(debit));
+                changes.nonces.insert(trans.from, trans.nonce + 1);
+            }
+            let credit = changes
+                .balances
+                .get(&trans.to)
+                .copied()
+                .unwrap_or_else(|| self.balance(&trans.to));
+            changes
+                .balances
+                .insert(trans.to, credit.checked_add(trans.amount)?);
+        }
+        if checked {
+            if coinbase != 1 {
+                return Err(Error::InvalidCoinbase);
+            }
+            if reward.checked_sub(tips) != Ok(params.reward) {
+                return Err(Error::InvalidReward);
+            }
+        }
+        Ok(changes)
+    }
+}
+
+fn median_time(timestamps: &[DateTime<Utc>]) -> DateTime<Utc> {
+    let start = timestamps.len().saturating_sub(MEDIAN_TIME_SPAN);
+    let mut timestamps = timestamps[start..].to_vec();
+    timestamps.sort();
+    timestamps[timestamps.len() / 2]
+}
+
+fn push_timestamp(timestamps: &mut Vec<DateTime<Utc>>, timestamp: DateTime<Utc>) {
+    timestamps.push(timestamp);
+    if timestamps.len() > KEPT_TIMESTAMPS {
+        timestamps.remove(0);
+    }
+}
+
+// the target of the block following the one at `height` mined at `bits`:
+// the same, rescaled once every `RETARGET_WINDOW` blocks by the time the
+// last window took; the genesis timestamp is fixed by the config, so
+// windows start at height 1
+fn next_bits(
+    params: &ChainParams,
+    height: u64,
+    bits: u32,
+    timestamps: &[DateTime<Utc>],
+) -> Result<u32, Error> {
+    let len = height as usize + 1;
+    if len <= RETARGET_WINDOW || !(len - 1).is_multiple_of(RETARGET_WINDOW) {
+        return Ok(bits);
+    }
+    let last = timestamps[timestamps.len() - 1];
+    let first = timestamps[timestamps.len() - RETARGET_WINDOW];
+    pow::retarget(bits, (last - first).num_milliseconds(), params.block_time)
+}
+
+/// validate every block of `blocks` from `genesis` on, returning the state
+/// after the last one
+pub fn validate_chain(
+    params: &ChainParams,
+    genesis: &Block,
+    blocks: &[Block],
+) -> Result<ChainState, Error> {
+    match blocks.first() {
+        Some(first) if first == genesis => {}
+        _ => return Err(Error::GenesisMismatch),
+    }
+    let mut state = ChainState::genesis(params, genesis)?;
+    for block in blocks[1..].iter() {
+        state.connect_block(params, block)?;
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod test_validation {
+    use super::*;
+    use crate::blockchain::test_block_chain::chain_with_funds;
+    use crate::blockchain::BlockChain;
+    use crate::constant::*;
+    use crate::transaction::Transaction;
+
+    // a mined block of `data` on top of `chain`
+    fn next_block(chain: &BlockChain, data: Vec<Transaction>) -> Block {
+        let mut block = Block::new(chrono::Utc::now(), data);
+        block.prev_hash = chain.get_last_block().hash;
+        block.bits = chain.state.bits;
+        block.hash = block.get_hash();
+        block.mine().unwrap();
+        block
+    }
+
+    fn reward(chain: &BlockChain, amount: Amount) -> Transaction {
+        let mut reward =
+            Transaction::new(chain.mint_address, KEY2.3, amount, None, chain.len() as u64);
+        reward.sign(&MINT_KEY_PAIR, &chain.chain_id).unwrap();
+        reward
+    }
+
+    #[test]
+    fn test_validate_chain() {
+        let mut chain = chain_with_funds();
+        chain.mine_transaction(&KEY1.3).unwrap();
+        chain.mine_transaction(&KEY2.3).unwrap();
+        let params = chain.params();
+        let state = validate_chain(&params, &chain.chain[0], &chain.chain).unwrap();
+        assert_eq!(state, chain.state);
+        assert_eq!(state.height, 2);
+        assert_eq!(state.balance(&KEY1.3), Amount::from_coins(100100).unwrap());
+
+        let other = BlockChain::new();
+        assert_eq!(
+            validate_chain(&params, &other.chain[0], &chain.chain).err(),
+            Some(Error::GenesisMismatch)
+        );
+        assert_eq!(
+            validate_chain(&params, &chain.chain[0], &[]).err(),
+            Some(Error::GenesisMismatch)
+        );
+    }
+
+    #[test]
+    fn test_coinbase() {
+        let chain = chain_with_funds();
+        let params = chain.params();
+        let block = next_block(&chain, vec![reward(&chain, chain.reward)]);
+        assert_eq!(chain.state.validate_block(&params, &block), Ok(()));
+
+        let block = next_block(&chain, Vec::new());
+        assert_eq!(
+            chain.state.validate_block(&params, &block),
+            Err(Error::InvalidCoinbase)
+        );
+        let twice = vec![reward(&chain, chain.reward), reward(&chain, chain.reward)];
+        assert_eq!(
+            chain
+                .state
+                .validate_block(&params, &next_block(&chain, twice)),
+            Err(Error::InvalidCoinbase)
+        );
+        // the tips of the block must be paid to the miner, not kept back
+        let mut trans = Transaction::new(
+            KEY1.1,
+            KEY2.3,
+            Amount::from_coins(5).unwrap(),
+            Some(Amount::from_coins(1).unwrap()),
+            0,
+        );
+        trans.sign(&KEY1.2, &chain.chain_id).unwrap();
+        let block = next_block(&chain, vec![trans, reward(&chain, chain.reward)]);
+        assert_eq!(
+            chain.state.validate_block(&params, &block),
+            Err(Error::InvalidReward)
+        );
+    }
+
+    #[test]
+    fn test_balance_before_block() {
+        let chain = chain_with_funds();
+        let params = chain.params();
+        // KEY2 cannot spend the reward of the block paying it
+        let mut trans = Transaction::new(KEY2.1, KEY1.3, chain.reward, None, 0);
+        trans.sign(&KEY2.2, &chain.chain_id).unwrap();
+        let block = next_block(&chain, vec![reward(&chain, chain.reward), trans]);
+        assert_eq!(
+            chain.state.validate_block(&params, &block),
+            Err(Error::InsufficientBalance {
+                balance: Amount::ZERO,
+                required: chain.reward,
+            })
+        );
+
+        // nor can KEY1 spend its funds twice within a block
+        let amount = Amount::from_coins(60000).unwrap();
+        let mut first = Transaction::new(KEY1.1, KEY2.3, amount, None, 0);
+        first.sign(&KEY1.2, &chain.chain_id).unwrap();
+        let mut second = Transaction::new(KEY1.1, KEY2.3, amount, None, 1);
+        second.sign(&KEY1.2, &chain.chain_id).unwrap();
+        let block = next_block(&chain, vec![first, second, reward(&chain, chain.reward)]);
+        assert_eq!(
+            chain.state.validate_block(&params, &block),
+            Err(Error::InsufficientBalance {
+                balance: Amount::from_coins(40000).unwrap(),
+                required: amount,
+            })
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let mut chain = BlockChain::new();
+        for _ in 0..MEDIAN_TIME_SPAN {
+            chain.mine_transaction(&KEY1.3).unwrap();
+        }
+        let params = chain.params();
+        let median = chain.state.median_time();
+        assert_eq!(median, chain.chain[chain.len() - 6].timestamp);
+
+        let mut block = Block::new(median, vec![reward(&chain, chain.reward)]);
+        block.prev_hash = chain.get_last_block().hash;
+        block.bits = chain.state.bits;
+        block.hash = block.get_hash();
+        block.mine().unwrap();
+        assert_eq!(
+            chain.state.validate_block(&params, &block),
+            Err(Error::InvalidTimestamp)
+        );
+
+        block.timestamp = Utc::now() + chrono::Duration::milliseconds(MAX_FUTURE_DRIFT + 60 * 1000);
+        block.hash = block.get_hash();
+        block.mine().unwrap();
+        assert_eq!(
+            chain.state.validate_block(&params, &block),
+            Err(Error::InvalidTimestamp)
+        );
+    }
+
+    #[test]
+    fn test_header_chain() {
+        let mut chain = BlockChain::new();
+        for _ in 0..RETARGET_WINDOW + 2 {
+            chain.mine_transaction(&KEY1.3).unwrap();
+        }
+        let params = chain.params();
+        let mut state = HeaderState::from_blocks(&params, &chain.chain[..1]).unwrap();
+        for block in chain.chain[1..].iter() {
+            state.connect_header(&params, &block.header()).unwrap();
+        }
+        assert_eq!(state, chain.state.header_state());
+        assert_eq!(
+            HeaderState::from_blocks(&params, &chain.chain).unwrap(),
+            state
+        );
+
+        let mut state = HeaderState::from_blocks(&params, &chain.chain[..3]).unwrap();
+        let mut header = chain.chain[4].header();
+        assert_eq!(state.validate_header(&header), Err(Error::InvalidPrevHash));
+        header = chain.chain[3].header();
+        header.nonce += 1;
+        while Target::from_bits(header.bits)
+            .unwrap()
+            .is_met_by(&header.hash())
+        {
+            header.nonce += 1;
+        }
+        assert_eq!(state.validate_header(&header), Err(Error::InvalidHash));
+        state
+            .connect_header(&params, &chain.chain[3].header())
+            .unwrap();
+        assert_eq!(state.tip, chain.chain[3].hash);
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let mut chain = chain_with_funds();
+        let before = chain.state.clone();
+        let mut trans = Transaction::new(KEY1.1, KEY2.3, Amount::from_coins(5).unwrap(), None, 0);
+        trans.sign(&KEY1.2, &chain.chain_id).unwrap();
+        chain.add_transaction(trans).unwrap();
+        let block = chain.mine_transaction(&KEY2.3).unwrap();
+        assert_ne!(chain.state, before);
+
+        let params = chain.params();
+        chain
+            .state
+            .disconnect_block(&params, &block, &chain.chain[..1])
+            .unwrap();
+        assert_eq!(chain.state, before);
+    }
+}
@@ -0,0 +1,441 @@
+//! password-encrypted keystore holding the secret keys of a user; the keys
+//! are only decrypted inside the signing calls, callers get public keys and
+//! signatures back
+use crate::secp256k1::{decode_hex, KeyPair, PubKey, SecKey, Secp256K1, ToHex};
+use crate::transaction::Transaction;
+use crate::utxo::UtxoTransaction;
+use crate::Error;
+use bitcoin_hashes::sha256::Hash as Sha256;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use k256::ecdsa::Signature;
+use rand_core::{OsRng, RngCore};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// version of the keystore file format
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+// plaintext of the password check, encrypted when the keystore is created
+const CHECK: &[u8] = b"bloc keystore";
+
+/// cost of the scrypt derivation of the encryption key from the password
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    /// log2 of the cpu and memory cost
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    /// the interactive login parameters recommended for scrypt, 32MiB and
+    /// a fraction of a second
+    fn default() -> Self {
+        Self {
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+/// a stored key as listed, without its secret
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyInfo {
+    pub name: String,
+    pub public_key: PubKey,
+}
+
+// bytes sealed with chacha20-poly1305 under a nonce used once
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Sealed {
+    #[serde(with = "serde_bytes_hex")]
+    nonce: Vec<u8>,
+    #[serde(with = "serde_bytes_hex")]
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct StoredKey {
+    name: String,
+    public_key: PubKey,
+    secret: Sealed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct KeystoreFile {
+    version: u32,
+    kdf: KdfParams,
+    #[serde(with = "serde_bytes_hex")]
+    salt: Vec<u8>,
+    /// `CHECK` sealed, to tell a wrong password from a corrupted key
+    check: Sealed,
+    keys: Vec<StoredKey>,
+}
+
+/// keys sealed under one password, created or loaded locked and unlocked
+/// with the password for the time they are used
+///
+/// each secret is sealed with chacha20-poly1305 under a key derived with
+/// scrypt from the password, authenticating the name and public key it
+/// belongs to
+pub struct Keystore {
+    file: KeystoreFile,
+    // the derived encryption key while unlocked
+    key: Option<Zeroizing<[u8; 32]>>,
+}
+
+impl Keystore {
+    /// an empty keystore sealed with `password`, left unlocked
+    pub fn create(password: &str, kdf: KdfParams) -> Result<Self, Error> {
+        let mut salt = vec![0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(password, &salt, &kdf)?;
+        let check = seal(&key, CHECK, &[])?;
+        Ok(Self {
+            file: KeystoreFile {
+                version: KEYSTORE_VERSION,
+                kdf,
+                salt,
+                check,
+                keys: Vec::new(),
+            },
+            key: Some(key),
+        })
+    }
+
+    /// read a keystore written by `save`, locked
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// write the keystore to `path` through a temporary file, so a crash
+    /// never leaves half of it
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        std::fs::write(&temp, self.to_json()?)?;
+        std::fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let file: KeystoreFile =
+            serde_json::from_str(json).map_err(|e| Error::Keystore(e.to_string()))?;
+        if file.version != KEYSTORE_VERSION {
+            return Err(Error::UnsupportedVersion(file.version));
+        }
+        Ok(Self { file, key: None })
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(&self.file).map_err(|e| Error::Serialize(e.to_string()))
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.key.is_none()
+    }
+
+    /// derive the encryption key from `password`, kept until `lock`
+    pub fn unlock(&mut self, password: &str) -> Result<(), Error> {
+        self.key = Some(self.check_password(password)?);
+        Ok(())
+    }
+
+    /// forget the encryption key, wiping it from memory
+    pub fn lock(&mut self) {
+        self.key = None;
+    }
+
+    /// the stored keys, also while locked
+    pub fn list(&self) -> Vec<KeyInfo> {
+        self.file
+            .keys
+            .iter()
+            .map(|stored| KeyInfo {
+                name: stored.name.clone(),
+                public_key: stored.public_key,
+            })
+            .collect()
+    }
+
+    /// the public key stored under `name`
+    pub fn public_key(&self, name: &str) -> Result<PubKey, Error> {
+        Ok(self.stored(name)?.public_key)
+    }
+
+    /// generate a random key stored under `name`
+    pub fn generate(&mut self, name: &str) -> Result<PubKey, Error> {
+        let (secret, _) = Secp256K1::new().gen_keypair();
+        self.import(name, &secret)
+    }
+
+    /// store `secret` under `name`
+    pub fn import(&mut self, name: &str, secret: &SecKey) -> Result<PubKey, Error> {
+        let key = self.key.as_ref().ok_or(Error::WalletLocked)?;
+        if self.file.keys.iter().any(|stored| stored.name == name) {
+            return Err(Error::DuplicateKey(name.to_owned()));
+        }
+        let public_key = public_key_of(secret)?;
+        let secret = seal(key, secret.to_bytes(), &aad(name, &public_key))?;
+        self.file.keys.push(StoredKey {
+            name: name.to_owned(),
+            public_key,
+            secret,
+        });
+        Ok(public_key)
+    }
+
+    /// the secret key stored under `name`, for a backup; the password is
+    /// asked again even while unlocked
+    pub fn export(&self, name: &str, password: &str) -> Result<SecKey, Error> {
+        let key = self.check_password(password)?;
+        self.open_secret(&key, self.stored(name)?)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        let index = self
+            .file
+            .keys
+            .iter()
+            .position(|stored| stored.name == name)
+            .ok_or_else(|| Error::UnknownKey(name.to_owned()))?;
+        self.file.keys.remove(index);
+        Ok(())
+    }
+
+    /// sign `msg` with the key stored under `name`
+    pub fn sign(&self, name: &str, msg: &[u8]) -> Result<Signature, Error> {
+        self.with_secret(name, |secret| Ok(secret.sign(msg)))
+    }
+
+    /// sign `trans` of an account chain with the key stored under `name`
+    pub fn sign_transaction(
+        &self,
+        name: &str,
+        trans: &mut Transaction,
+        chain_id: &Sha256,
+    ) -> Result<(), Error> {
+        self.with_secret(name, |secret| trans.sign(&KeyPair::from(secret), chain_id))
+    }
+
+    /// sign every input of `tx` with the key stored under `name`
+    pub fn sign_utxo_transaction(&self, name: &str, tx: &mut UtxoTransaction) -> Result<(), Error> {
+        self.with_secret(name, |secret| tx.sign(&KeyPair::from(secret)))
+    }
+
+    fn stored(&self, name: &str) -> Result<&StoredKey, Error> {
+        self.file
+            .keys
+            .iter()
+            .find(|stored| stored.name == name)
+            .ok_or_else(|| Error::UnknownKey(name.to_owned()))
+    }
+
+    fn check_password(&self, password: &str) -> Result<Zeroizing<[u8; 32]>, Error> {
+        let key = derive_key(password, &self.file.salt, &self.file.kdf)?;
+        match open(&key, &self.file.check, &[]) {
+            Ok(check) if check.as_slice() == CHECK => Ok(key),
+            _ => Err(Error::WrongPassword),
+        }
+    }
+
+    fn open_secret(&self, key: &[u8; 32], stored: &StoredKey) -> Result<SecKey, Error> {
+        let bytes = open(key, &stored.secret, &aad(&stored.name, &stored.public_key))
+            .map_err(|_| Error::Keystore(format!("key {} is corrupted", stored.name)))?;
+        let bytes: &[u8; 32] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::Keystore(format!("key {} is corrupted", stored.name)))?;
+        SecKey::new(bytes).map_err(|_| Error::Keystore(format!("key {} is corrupted", stored.name)))
+    }
+
+    // run `f` on the decrypted secret of `name`, the plaintext buffer is
+    // wiped once decoded
+    fn with_secret<T, F>(&self, name: &str, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&SecKey) -> Result<T, Error>,
+    {
+        let key = self.key.as_ref().ok_or(Error::WalletLocked)?;
+        let secret = self.open_secret(key, self.stored(name)?)?;
+        f(&secret)
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8], kdf: &KdfParams) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let params = scrypt::Params::new(kdf.log_n, kdf.r, kdf.p)
+        .map_err(|e| Error::Keystore(format!("invalid scrypt parameters: {}", e)))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    scrypt::scrypt(password.as_bytes(), salt, &params, key.as_mut())
+        .map_err(|e| Error::Keystore(e.to_string()))?;
+    Ok(key)
+}
+
+// binds a sealed secret to the name and public key stored with it
+fn aad(name: &str, public_key: &PubKey) -> Vec<u8> {
+    let mut aad = public_key.as_ref().to_vec();
+    aad.extend_from_slice(name.as_bytes());
+    aad
+}
+
+fn seal(key: &[u8; 32], msg: &[u8], aad: &[u8]) -> Result<Sealed, Error> {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = ChaCha20Poly1305::new(&Key::from(*key))
+        .encrypt(&Nonce::from(nonce), Payload { msg, aad })
+        .map_err(|_| Error::Keystore("encryption failed".to_owned()))?;
+    Ok(Sealed {
+        nonce: nonce.to_vec(),
+        ciphertext,
+    })
+}
+
+fn open(key: &[u8; 32], sealed: &Sealed, aad: &[u8]) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let nonce: [u8; NONCE_LEN] = sealed
+        .nonce
+        .as_slice()
+        .try_into()
+        .map_err(|_| Error::Keystore("invalid nonce length".to_owned()))?;
+    ChaCha20Poly1305::new(&Key::from(*key))
+        .decrypt(
+            &Nonce::from(nonce),
+            Payload {
+                msg: &sealed.ciphertext,
+                aad,
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| Error::Keystore("decryption failed".to_owned()))
+}
+
+// the public key of `secret`, rejecting keys out of the curve order
+fn public_key_of(secret: &SecKey) -> Result<PubKey, Error> {
+    KeyPair::from(secret)
+        .to_public_key()
+        .map_err(|e| Error::Key(e.to_string()))
+}
+
+// bytes as a hex string
+mod serde_bytes_hex {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&bitcoin_hashes::hex::ToHex::to_hex(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(d)?;
+        decode_hex(&hex).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test_wallet {
+    use super::*;
+    use crate::constant::*;
+    use crate::Amount;
+
+    // cheap enough for debug builds
+    const FAST: KdfParams = KdfParams {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+
+    #[test]
+    fn test_keys() {
+        let mut keystore = Keystore::create("hunter2", FAST).unwrap();
+        assert!(!keystore.is_locked());
+        let generated = keystore.generate("spending").unwrap();
+        assert_eq!(keystore.import("imported", &KEY1.0), Ok(KEY1.1));
+        assert_eq!(
+            keystore.import("imported", &KEY2.0),
+            Err(Error::DuplicateKey("imported".to_owned()))
+        );
+        assert!(SecKey::new(&[0; 32]).is_err());
+        assert_eq!(
+            keystore.list(),
+            vec![
+                KeyInfo {
+                    name: "spending".to_owned(),
+                    public_key: generated
+                },
+                KeyInfo {
+                    name: "imported".to_owned(),
+                    public_key: KEY1.1
+                },
+            ]
+        );
+
+        let mut trans = Transaction::new(KEY1.1, KEY2.3, Amount::from_coins(1).unwrap(), None, 0);
+        let chain_id = crate::blockchain::BlockChain::new().chain_id;
+        keystore
+            .sign_transaction("imported", &mut trans, &chain_id)
+            .unwrap();
+        let mut expected = trans.clone();
+        expected.sign(&KEY1.2, &chain_id).unwrap();
+        assert_eq!(trans, expected);
+        let signature = keystore.sign("spending", b"message").unwrap();
+        assert!(generated.verify(b"message", &signature).is_ok());
+
+        assert_eq!(
+            keystore.export("imported", "hunter3"),
+            Err(Error::WrongPassword)
+        );
+        assert_eq!(keystore.export("imported", "hunter2"), Ok(KEY1.0.clone()));
+        keystore.remove("imported").unwrap();
+        assert_eq!(
+            keystore.sign("imported", b"message"),
+            Err(Error::UnknownKey("imported".to_owned()))
+        );
+
+        keystore.lock();
+        assert_eq!(
+            keystore.sign("spending", b"message"),
+            Err(Error::WalletLocked)
+        );
+        assert_eq!(keystore.generate("other"), Err(Error::WalletLocked));
+        assert_eq!(keystore.unlock("hunter3"), Err(Error::WrongPassword));
+        assert!(keystore.is_locked());
+        keystore.unlock("hunter2").unwrap();
+        assert!(keystore.sign("spending", b"message").is_ok());
+    }
+
+    #[test]
+    fn test_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.json");
+        let mut keystore = Keystore::create("correct horse", FAST).unwrap();
+        keystore.import("main", &KEY2.0).unwrap();
+        keystore.save(&path).unwrap();
+
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(!json.contains(&KEY2.0.to_hex()));
+        let mut loaded = Keystore::load(&path).unwrap();
+        assert!(loaded.is_locked());
+        assert_eq!(loaded.public_key("main"), Ok(KEY2.1));
+        loaded.unlock("correct horse").unwrap();
+        assert_eq!(loaded.export("main", "correct horse"), Ok(KEY2.0.clone()));
+
+        // a secret moved to another name no longer decrypts
+        let tampered = json.replace("\"main\"", "\"other\"");
+        let mut tampered = Keystore::from_json(&tampered).unwrap();
+        tampered.unlock("correct horse").unwrap();
+        assert!(matches!(
+            tampered.sign("other", b"message"),
+            Err(Error::Keystore(_))
+        ));
+
+        let newer = json.replace("\"version\": 1", "\"version\": 2");
+        assert!(matches!(
+            Keystore::from_json(&newer),
+            Err(Error::UnsupportedVersion(2))
+        ));
+        assert!(matches!(Keystore::from_json("{}"), Err(Error::Keystore(_))));
+    }
+}
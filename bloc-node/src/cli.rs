@@ -0,0 +1,812 @@
+use crate::node::{Node, NodeConfig};
+use crate::rpc::{self, RpcServer};
+use crate::Error;
+use bitcoin_hashes::hex::ToHex as _;
+use bitcoin_hashes::sha256::Hash as Sha256;
+use bloc_core::blockchain::{BlockChain, GENESIS_FILE};
+use bloc_core::genesis::GenesisConfig;
+use bloc_core::miner::{CancelToken, MineOutcome, Miner};
+use bloc_core::secp256k1::{PubKey, SecKey};
+use bloc_core::transaction::Transaction;
+use bloc_core::utxo::ChainModel;
+use bloc_core::wallet::{KdfParams, Keystore};
+use bloc_core::{Address, Amount};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use zeroize::Zeroizing;
+
+/// written in the data directory by `bloc run`, holding the address of its
+/// rpc server; the other commands go through it while the node runs
+pub const LOCK_FILE: &str = "node.lock";
+/// address peers connect to by default
+pub const DEFAULT_LISTEN: &str = "0.0.0.0:9333";
+/// address of the rpc server by default
+pub const DEFAULT_RPC: &str = "127.0.0.1:9332";
+/// keystore of the data directory, used when no --wallet is given
+pub const WALLET_FILE: &str = "wallet.json";
+
+pub const USAGE: &str = "\
+usage: bloc [--datadir DIR] COMMAND [ARGS]
+
+commands:
+  init [--genesis FILE]                 create the data directory from a genesis
+                                        config, .toml or .json, or the default one
+  run [--listen ADDR] [--rpc ADDR] [--connect ADDR]...
+                                        start a node on the chain of the data directory
+  mine --address ADDRESS [--count N]    mine N blocks rewarding ADDRESS
+  send --name NAME --to ADDRESS --amount AMOUNT [--tip AMOUNT] [--wallet FILE]
+                                        sign a transfer with the key NAME of the wallet
+                                        and relay it through the node
+  wallet create [--wallet FILE]         create a wallet sealed with a password
+  wallet generate NAME [--wallet FILE]  store a new key as NAME, print its address
+  wallet import NAME [--wallet FILE]    store the secret key typed at the prompt as NAME
+  wallet list [--wallet FILE]           the names, public keys and addresses stored
+  balance ADDRESS                       balance of ADDRESS
+  address PUBKEY                        the address of PUBKEY
+  block HASH|HEIGHT                     a block of the chain as json
+  tx TXID                               a transaction and its block as json
+  export [--out FILE]                   the whole chain as json
+
+the data directory defaults to $BLOC_DATADIR, then ~/.bloc; while `bloc run`
+holds it the other commands query its node. the wallet defaults to wallet.json
+in the data directory, passwords and secret keys are read at the terminal";
+
+/// run the command line `args`, without the program name, writing what it
+/// prints to `out`
+pub fn run(args: &[String], out: &mut dyn Write) -> Result<(), Error> {
+    run_with(args, &mut |prompt| rpassword::prompt_password(prompt), out)
+}
+
+/// `run`, asking `prompt` for the passwords and secret keys the command
+/// needs instead of reading them at the terminal
+pub fn run_with(
+    args: &[String],
+    prompt: &mut dyn FnMut(&str) -> io::Result<String>,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let args = Args::parse(args)?;
+    let datadir = match args.option("datadir") {
+        Some(dir) => PathBuf::from(dir),
+        None => default_datadir(),
+    };
+    match args.command.as_str() {
+        "init" => {
+            args.expect(0, &["genesis"])?;
+            init(&datadir, args.option("genesis"), out)
+        }
+        "run" => {
+            args.expect(0, &["listen", "rpc", "connect"])?;
+            run_node(&datadir, &args)
+        }
+        "mine" => {
+            args.expect(0, &["address", "count"])?;
+            let address = parse_address(args.required("address")?)?;
+            let count = match args.option("count") {
+                Some(count) => count
+                    .parse()
+                    .map_err(|_| Error::Usage(format!("invalid count {}", count)))?,
+                None => 1,
+            };
+            mine(&datadir, &address, count, out)
+        }
+        "send" => {
+            args.expect(0, &["name", "to", "amount", "tip", "wallet"])?;
+            send(&datadir, &args, prompt, out)
+        }
+        "wallet" => wallet(&datadir, &args, prompt, out),
+        "balance" => {
+            args.expect(1, &[])?;
+            let address = parse_address(&args.positional[0])?;
+            let balance = match open(&datadir)? {
+                Chain::Local(chain) => chain.get_balance(&address)?.to_string(),
+                Chain::Remote(addr) => {
+                    let balance = rpc::call(addr, "getbalance", json!([address.to_string()]))?;
+                    balance.as_str().unwrap_or_default().to_owned()
+                }
+            };
+            writeln!(out, "{}", balance)?;
+            Ok(())
+        }
+        "address" => {
+            args.expect(1, &[])?;
+            let key = parse_key(&args.positional[0])?;
+            writeln!(out, "{}", Address::from(&key))?;
+            Ok(())
+        }
+        "block" => {
+            args.expect(1, &[])?;
+            let block = match args.positional[0].parse::<u64>() {
+                Ok(height) => json!(height),
+                Err(_) => json!(args.positional[0]),
+            };
+            let block = match open(&datadir)? {
+                Chain::Local(chain) => rpc::get_block(&chain, &block)?,
+                Chain::Remote(addr) => rpc::call(addr, "getblock", json!([block]))?,
+            };
+            print_json(out, &block)
+        }
+        "tx" => {
+            args.expect(1, &[])?;
+            let txid = Sha256::from_str(&args.positional[0])
+                .map_err(|_| Error::Usage(format!("invalid txid {}", args.positional[0])))?;
+            let trans = match open(&datadir)? {
+                Chain::Local(chain) => rpc::get_transaction(&chain, &txid)?,
+                Chain::Remote(addr) => rpc::call(addr, "gettransaction", json!([txid.to_hex()]))?,
+            };
+            print_json(out, &trans)
+        }
+        "export" => {
+            args.expect(0, &["out"])?;
+            let chain = match open(&datadir)? {
+                Chain::Local(chain) => chain,
+                Chain::Remote(_) => {
+                    return Err(Error::Usage(
+                        "stop the node holding the data directory to export it".to_owned(),
+                    ))
+                }
+            };
+            let json = chain.to_json()?;
+            match args.option("out") {
+                Some(path) => fs::write(path, json)?,
+                None => writeln!(out, "{}", json)?,
+            }
+            Ok(())
+        }
+        "help" => {
+            writeln!(out, "{}", USAGE)?;
+            Ok(())
+        }
+        command => Err(Error::Usage(format!(
+            "unknown command {}\n\n{}",
+            command, USAGE
+        ))),
+    }
+}
+
+// the command, its positional arguments and its `--name value` options
+struct Args {
+    command: String,
+    positional: Vec<String>,
+    options: HashMap<String, Vec<String>>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut command = None;
+        let mut positional = Vec::new();
+        let mut options: HashMap<String, Vec<String>> = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                command = Some("help".to_owned());
+                break;
+            }
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| Error::Usage(format!("--{} needs a value", name)))?;
+                    options
+                        .entry(name.to_owned())
+                        .or_default()
+                        .push(value.clone());
+                }
+                None if command.is_none() => command = Some(arg.clone()),
+                None => positional.push(arg.clone()),
+            }
+        }
+        Ok(Self {
+            command: command.ok_or_else(|| Error::Usage(USAGE.to_owned()))?,
+            positional,
+            options,
+        })
+    }
+
+    // the command takes `count` positional arguments and the options `names`
+    // besides `--datadir`
+    fn expect(&self, count: usize, names: &[&str]) -> Result<(), Error> {
+        if self.positional.len() != count {
+            return Err(Error::Usage(format!(
+                "{} takes {} argument(s), see bloc help",
+                self.command, count
+            )));
+        }
+        match self
+            .options
+            .keys()
+            .find(|name| *name != "datadir" && !names.contains(&name.as_str()))
+        {
+            Some(name) => Err(Error::Usage(format!(
+                "{} has no option --{}",
+                self.command, name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // the last value given to `--name`
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .get(name)
+            .and_then(|values| values.last())
+            .map(|value| value.as_str())
+    }
+
+    fn required(&self, name: &str) -> Result<&str, Error> {
+        self.option(name)
+            .ok_or_else(|| Error::Usage(format!("{} needs --{}", self.command, name)))
+    }
+
+    fn values(&self, name: &str) -> &[String] {
+        self.options.get(name).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+// where the chain is: opened from the data directory, or held by the node
+// whose rpc server is at the address
+enum Chain {
+    Local(Box<BlockChain>),
+    Remote(SocketAddr),
+}
+
+fn default_datadir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("BLOC_DATADIR") {
+        return PathBuf::from(dir);
+    }
+    let home = std::env::var_os("HOME").unwrap_or_else(|| ".".into());
+    Path::new(&home).join(".bloc")
+}
+
+fn open(datadir: &Path) -> Result<Chain, Error> {
+    match fs::read_to_string(datadir.join(LOCK_FILE)) {
+        Ok(addr) => {
+            let addr = addr.trim();
+            return addr
+                .parse()
+                .map(Chain::Remote)
+                .map_err(|_| Error::Usage(format!("{} holds no rpc address", LOCK_FILE)));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    if !datadir.join(GENESIS_FILE).exists() {
+        return Err(Error::Usage(format!(
+            "no chain in {}, create one with bloc init",
+            datadir.display()
+        )));
+    }
+    Ok(Chain::Local(Box::new(BlockChain::open(datadir)?)))
+}
+
+fn init(datadir: &Path, genesis: Option<&str>, out: &mut dyn Write) -> Result<(), Error> {
+    if datadir.join(GENESIS_FILE).exists() {
+        return Err(Error::Usage(format!(
+            "{} already holds a chain",
+            datadir.display()
+        )));
+    }
+    let config = match genesis {
+        Some(path) => GenesisConfig::load(path)?,
+        None => GenesisConfig::default(),
+    };
+    let chain = BlockChain::create(datadir, &config)?;
+    writeln!(
+        out,
+        "created chain {} in {}",
+        chain.chain_id,
+        datadir.display()
+    )?;
+    writeln!(out, "genesis block {}", chain.genesis_hash())?;
+    Ok(())
+}
+
+// removes the lock file of the data directory once the node stopped
+struct Lock(PathBuf);
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn run_node(datadir: &Path, args: &Args) -> Result<(), Error> {
+    let parse_addr = |addr: &str| {
+        addr.parse::<SocketAddr>()
+            .map_err(|_| Error::Usage(format!("invalid address {}", addr)))
+    };
+    let listen = parse_addr(args.option("listen").unwrap_or(DEFAULT_LISTEN))?;
+    let rpc_addr = parse_addr(args.option("rpc").unwrap_or(DEFAULT_RPC))?;
+    let chain = match open(datadir)? {
+        Chain::Local(chain) => *chain,
+        Chain::Remote(addr) => {
+            return Err(Error::Usage(format!(
+                "a node with rpc on {} holds {}, remove {} if it is not running",
+                addr,
+                datadir.display(),
+                LOCK_FILE
+            )))
+        }
+    };
+    let path = datadir.join(LOCK_FILE);
+    let mut lock_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let _lock = Lock(path);
+
+    let node = Arc::new(Node::start(
+        chain,
+        NodeConfig {
+            listen,
+            ..NodeConfig::default()
+        },
+    )?);
+    for addr in args.values("connect") {
+        let addr = parse_addr(addr)?;
+        if let Err(e) = node.connect(addr) {
+            log::warn!("failed to connect to {}: {}", addr, e);
+        }
+    }
+    let mut server = RpcServer::start(node.clone(), rpc_addr)?;
+    write!(lock_file, "{}", server.local_addr())?;
+    lock_file.sync_all()?;
+
+    let (stop, stopped) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = stop.send(());
+    })
+    .map_err(|e| Error::Usage(format!("failed to handle ctrl-c: {}", e)))?;
+    log::info!(
+        "node on {} with rpc on {}, ctrl-c stops it",
+        node.local_addr(),
+        server.local_addr()
+    );
+    let _ = stopped.recv();
+    server.shutdown();
+    Ok(())
+}
+
+fn mine(datadir: &Path, address: &Address, count: u64, out: &mut dyn Write) -> Result<(), Error> {
+    match open(datadir)? {
+        Chain::Local(mut chain) => {
+            let miner = Miner::default();
+            let mut mined = 0;
+            while mined < count {
+                let template = chain.block_template(address)?;
+                let block = match miner.mine(template.block, &CancelToken::new(), |_| {})? {
+                    MineOutcome::Mined { block, .. } => block,
+                    MineOutcome::Cancelled(_) => continue,
+                };
+                let hash = block.hash;
+                chain.submit_block(block)?;
+                writeln!(out, "{}", hash)?;
+                mined += 1;
+            }
+        }
+        Chain::Remote(addr) => {
+            let hashes = rpc::call(addr, "mine", json!([count, address.to_string()]))?;
+            for hash in hashes.as_array().into_iter().flatten() {
+                writeln!(out, "{}", hash.as_str().unwrap_or_default())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// the node relays the transfer, a data directory on its own has no mempool
+fn send(
+    datadir: &Path,
+    args: &Args,
+    prompt: &mut dyn FnMut(&str) -> io::Result<String>,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let name = args.required("name")?;
+    let to = parse_address(args.required("to")?)?;
+    let amount = parse_amount(args.required("amount")?)?;
+    let tip = args.option("tip").map(parse_amount).transpose()?;
+    let addr = match open(datadir)? {
+        Chain::Remote(addr) => addr,
+        Chain::Local(_) => {
+            return Err(Error::Usage(
+                "send relays the transfer through a node, start one with bloc run".to_owned(),
+            ))
+        }
+    };
+    let config = GenesisConfig::load(datadir.join(GENESIS_FILE))?;
+    if config.model != ChainModel::Account {
+        return Err(Error::Usage(
+            "send only transfers on account chains".to_owned(),
+        ));
+    }
+    let mut keystore = Keystore::load(wallet_file(datadir, args))?;
+    let from = keystore.public_key(name)?;
+    let nonce = rpc::call(addr, "getnonce", json!([from.as_ref().to_hex()]))?
+        .as_u64()
+        .ok_or_else(|| Error::Protocol("getnonce answered no number".to_owned()))?;
+    let mut trans = Transaction::new(from, to, amount, tip, nonce);
+    keystore.unlock(&Zeroizing::new(prompt("password: ")?))?;
+    keystore.sign_transaction(name, &mut trans, &config.chain_id()?)?;
+    keystore.lock();
+    let raw = bincode::serialize(&trans)?.to_hex();
+    let txid = rpc::call(addr, "sendrawtransaction", json!([raw]))?;
+    writeln!(out, "{}", txid.as_str().unwrap_or_default())?;
+    Ok(())
+}
+
+fn wallet(
+    datadir: &Path,
+    args: &Args,
+    prompt: &mut dyn FnMut(&str) -> io::Result<String>,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let path = wallet_file(datadir, args);
+    match args.positional.first().map(String::as_str) {
+        Some("create") => {
+            args.expect(1, &["wallet"])?;
+            if path.exists() {
+                return Err(Error::Usage(format!(
+                    "{} already holds a wallet",
+                    path.display()
+                )));
+            }
+            let password = Zeroizing::new(prompt("new password: ")?);
+            if password.is_empty() {
+                return Err(Error::Usage("the password is empty".to_owned()));
+            }
+            if *password != *Zeroizing::new(prompt("repeat password: ")?) {
+                return Err(Error::Usage("the passwords differ".to_owned()));
+            }
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            Keystore::create(&password, KdfParams::default())?.save(&path)?;
+            writeln!(out, "created wallet {}", path.display())?;
+        }
+        Some(command @ ("generate" | "import")) => {
+            args.expect(2, &["wallet"])?;
+            let name = &args.positional[1];
+            let mut keystore = Keystore::load(&path)?;
+            keystore.unlock(&Zeroizing::new(prompt("password: ")?))?;
+            let key = if command == "generate" {
+                keystore.generate(name)?
+            } else {
+                let secret = Zeroizing::new(prompt("secret key: ")?);
+                keystore.import(name, &parse_secret(secret.trim())?)?
+            };
+            keystore.lock();
+            keystore.save(&path)?;
+            writeln!(out, "{}", Address::from(&key))?;
+        }
+        Some("list") => {
+            args.expect(1, &["wallet"])?;
+            for info in Keystore::load(&path)?.list() {
+                writeln!(
+                    out,
+                    "{} {} {}",
+                    info.name,
+                    info.public_key.as_ref().to_hex(),
+                    Address::from(&info.public_key)
+                )?;
+            }
+        }
+        _ => {
+            return Err(Error::Usage(
+                "wallet takes create, generate NAME, import NAME or list, see bloc help".to_owned(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+// the keystore of --wallet, or the one of the data directory
+fn wallet_file(datadir: &Path, args: &Args) -> PathBuf {
+    match args.option("wallet") {
+        Some(path) => PathBuf::from(path),
+        None => datadir.join(WALLET_FILE),
+    }
+}
+
+fn print_json(out: &mut dyn Write, value: &Value) -> Result<(), Error> {
+    writeln!(out, "{}", serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+fn parse_key(hex: &str) -> Result<PubKey, Error> {
+    PubKey::from_str(hex).map_err(|e| Error::Usage(format!("invalid public key: {}", e)))
+}
+
+fn parse_address(address: &str) -> Result<Address, Error> {
+    Address::from_str(address).map_err(|e| Error::Usage(e.to_string()))
+}
+
+fn parse_secret(hex: &str) -> Result<SecKey, Error> {
+    SecKey::from_str(hex).map_err(|e| Error::Usage(format!("invalid secret key: {}", e)))
+}
+
+fn parse_amount(amount: &str) -> Result<Amount, Error> {
+    amount
+        .parse()
+        .map_err(|e| Error::Usage(format!("invalid amount {}: {}", amount, e)))
+}
+
+#[cfg(test)]
+mod test_cli {
+    use super::*;
+    use bloc_core::constant::*;
+    use bloc_core::secp256k1::ToHex;
+
+    // cheap enough for debug builds
+    const FAST: KdfParams = KdfParams {
+        log_n: 4,
+        r: 8,
+        p: 1,
+    };
+
+    fn bloc(datadir: &Path, args: &[&str]) -> Result<String, Error> {
+        bloc_prompted(datadir, args, &[])
+    }
+
+    // `bloc`, answering its prompts with `answers` in order
+    fn bloc_prompted(datadir: &Path, args: &[&str], answers: &[&str]) -> Result<String, Error> {
+        let mut line = vec!["--datadir".to_owned(), datadir.display().to_string()];
+        line.extend(args.iter().map(|arg| arg.to_string()));
+        let mut answers = answers.iter();
+        let mut prompt = |_: &str| {
+            answers
+                .next()
+                .map(|answer| answer.to_string())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "no answer left"))
+        };
+        let mut out = Vec::new();
+        run_with(&line, &mut prompt, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    // a wallet of the data directory holding KEY1 as key1
+    fn with_wallet(datadir: &Path) {
+        fs::create_dir_all(datadir).unwrap();
+        let mut keystore = Keystore::create("hunter2", FAST).unwrap();
+        keystore.import("key1", &KEY1.0).unwrap();
+        keystore.save(datadir.join(WALLET_FILE)).unwrap();
+    }
+
+    fn funded(dir: &Path) -> PathBuf {
+        let path = dir.join("genesis.toml");
+        fs::write(&path, funded_genesis(1000).to_toml().unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_local() {
+        let dir = tempfile::tempdir().unwrap();
+        let datadir = dir.path().join("chain");
+        assert!(matches!(
+            bloc(&datadir, &["balance", &KEY1.3.to_string()]),
+            Err(Error::Usage(_))
+        ));
+        let genesis = funded(dir.path());
+        bloc(&datadir, &["init", "--genesis", genesis.to_str().unwrap()]).unwrap();
+        assert!(matches!(bloc(&datadir, &["init"]), Err(Error::Usage(_))));
+        assert_eq!(
+            bloc(&datadir, &["balance", &KEY1.3.to_string()]).unwrap(),
+            "1000\n"
+        );
+        assert_eq!(
+            bloc(&datadir, &["address", &KEY1.1.to_hex()]).unwrap(),
+            format!("{}\n", KEY1.3)
+        );
+
+        let mined = bloc(
+            &datadir,
+            &["mine", "--address", &KEY2.3.to_string(), "--count", "2"],
+        )
+        .unwrap();
+        let hashes: Vec<&str> = mined.lines().collect();
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(
+            bloc(&datadir, &["balance", &KEY2.3.to_string()]).unwrap(),
+            "200\n"
+        );
+
+        let block: Value = serde_json::from_str(&bloc(&datadir, &["block", "2"]).unwrap()).unwrap();
+        assert_eq!(block["hash"], json!(hashes[1]));
+        let block: Value =
+            serde_json::from_str(&bloc(&datadir, &["block", hashes[0]]).unwrap()).unwrap();
+        assert_eq!(block["height"], json!(1));
+
+        let chain = BlockChain::open(&datadir).unwrap();
+        let txid = chain.chain[1].txids().unwrap()[0];
+        drop(chain);
+        let trans: Value =
+            serde_json::from_str(&bloc(&datadir, &["tx", &txid.to_hex()]).unwrap()).unwrap();
+        assert_eq!(trans["blockhash"], json!(hashes[0]));
+        assert_eq!(trans["confirmations"], json!(2));
+
+        let export = dir.path().join("export.json");
+        bloc(&datadir, &["export", "--out", export.to_str().unwrap()]).unwrap();
+        let exported = BlockChain::from_json(&fs::read_to_string(&export).unwrap()).unwrap();
+        assert_eq!(exported.chain, BlockChain::open(&datadir).unwrap().chain);
+
+        // a transfer needs a node to relay it
+        with_wallet(&datadir);
+        let send = [
+            "send",
+            "--name",
+            "key1",
+            "--to",
+            &KEY2.3.to_string(),
+            "--amount",
+            "1",
+        ];
+        assert!(matches!(
+            bloc_prompted(&datadir, &send, &["hunter2"]),
+            Err(Error::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_wallet() {
+        let dir = tempfile::tempdir().unwrap();
+        let datadir = dir.path().join("chain");
+        assert!(bloc(&datadir, &["wallet", "list"]).is_err());
+        assert!(matches!(
+            bloc_prompted(&datadir, &["wallet", "create"], &["hunter2", "hunter3"]),
+            Err(Error::Usage(_))
+        ));
+        assert!(matches!(
+            bloc_prompted(&datadir, &["wallet", "create"], &[""]),
+            Err(Error::Usage(_))
+        ));
+        assert!(!datadir.join(WALLET_FILE).exists());
+
+        with_wallet(&datadir);
+        assert!(matches!(
+            bloc_prompted(&datadir, &["wallet", "create"], &["hunter2", "hunter2"]),
+            Err(Error::Usage(_))
+        ));
+        assert_eq!(
+            bloc_prompted(&datadir, &["wallet", "generate", "key1"], &["hunter2"]),
+            Err(Error::Ledger(bloc_core::Error::DuplicateKey(
+                "key1".to_owned()
+            )))
+        );
+        assert_eq!(
+            bloc_prompted(&datadir, &["wallet", "generate", "key2"], &["hunter3"]),
+            Err(Error::Ledger(bloc_core::Error::WrongPassword))
+        );
+        let address =
+            bloc_prompted(&datadir, &["wallet", "generate", "key2"], &["hunter2"]).unwrap();
+        let address = Address::from_str(address.trim()).unwrap();
+
+        let other = dir.path().join("other.json");
+        Keystore::create("correct horse", FAST)
+            .unwrap()
+            .save(&other)
+            .unwrap();
+        let import = [
+            "wallet",
+            "import",
+            "key3",
+            "--wallet",
+            other.to_str().unwrap(),
+        ];
+        assert!(matches!(
+            bloc_prompted(&datadir, &import, &["correct horse", "zz"]),
+            Err(Error::Usage(_))
+        ));
+        assert_eq!(
+            bloc_prompted(&datadir, &import, &["correct horse", &KEY2.0.to_hex()]).unwrap(),
+            format!("{}\n", KEY2.3)
+        );
+
+        let list = bloc(&datadir, &["wallet", "list"]).unwrap();
+        let list: Vec<&str> = list.lines().collect();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0], format!("key1 {} {}", KEY1.1.to_hex(), KEY1.3));
+        assert!(list[1].starts_with("key2 ") && list[1].ends_with(&address.to_string()));
+        assert_eq!(
+            bloc(
+                &datadir,
+                &["wallet", "list", "--wallet", other.to_str().unwrap()]
+            )
+            .unwrap(),
+            format!("key3 {} {}\n", KEY2.1.to_hex(), KEY2.3)
+        );
+    }
+
+    #[test]
+    fn test_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let datadir = dir.path();
+        assert!(bloc(datadir, &["help"]).unwrap().starts_with("usage"));
+        for args in [
+            &[][..],
+            &["frobnicate"],
+            &["init", "extra"],
+            &["init", "--bogus", "1"],
+            &["mine"],
+            &["mine", "--address"],
+            &["mine", "--address", "zz"],
+            &["mine", "--address", &KEY1.3.to_string(), "--count", "many"],
+            &["balance"],
+            &["balance", &KEY1.1.to_hex()],
+            &["address", "zz"],
+            &["tx", "not a txid"],
+            &[
+                "send",
+                "--key",
+                &KEY1.0.to_hex(),
+                "--to",
+                &KEY2.3.to_string(),
+            ],
+            &["wallet"],
+            &["wallet", "frobnicate"],
+            &["wallet", "generate"],
+            &["wallet", "list", "extra"],
+        ] {
+            assert!(
+                matches!(bloc(datadir, args), Err(Error::Usage(_))),
+                "{:?} accepted",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn test_remote() {
+        let dir = tempfile::tempdir().unwrap();
+        let datadir = dir.path().join("chain");
+        let genesis = funded(dir.path());
+        bloc(&datadir, &["init", "--genesis", genesis.to_str().unwrap()]).unwrap();
+
+        // what `bloc run` sets up
+        let chain = BlockChain::open(&datadir).unwrap();
+        let node = Arc::new(Node::start(chain, NodeConfig::default()).unwrap());
+        let server = RpcServer::start(node.clone(), SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        fs::write(datadir.join(LOCK_FILE), server.local_addr().to_string()).unwrap();
+        assert!(matches!(bloc(&datadir, &["run"]), Err(Error::Usage(_))));
+        assert!(matches!(bloc(&datadir, &["export"]), Err(Error::Usage(_))));
+
+        with_wallet(&datadir);
+        let send = [
+            "send",
+            "--name",
+            "key1",
+            "--to",
+            &KEY2.3.to_string(),
+            "--amount",
+            "10",
+            "--tip",
+            "1",
+        ];
+        assert_eq!(
+            bloc_prompted(&datadir, &send, &["hunter3"]),
+            Err(Error::Ledger(bloc_core::Error::WrongPassword))
+        );
+        let txid = bloc_prompted(&datadir, &send, &["hunter2"]).unwrap();
+        let txid = txid.trim();
+        assert!(node
+            .chain()
+            .mempool
+            .contains(&Sha256::from_str(txid).unwrap()));
+        // the nonce of the second transfer counts the pending one
+        bloc_prompted(&datadir, &send, &["hunter2"]).unwrap();
+        assert_eq!(node.chain().mempool.len(), 2);
+
+        let mined = bloc(&datadir, &["mine", "--address", &KEY2.3.to_string()]).unwrap();
+        let trans: Value = serde_json::from_str(&bloc(&datadir, &["tx", txid]).unwrap()).unwrap();
+        assert_eq!(trans["blockhash"], json!(mined.trim()));
+        assert_eq!(
+            bloc(&datadir, &["balance", &KEY2.3.to_string()]).unwrap(),
+            "122\n"
+        );
+        let block: Value = serde_json::from_str(&bloc(&datadir, &["block", "1"]).unwrap()).unwrap();
+        assert_eq!(block["hash"], json!(mined.trim()));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::peer::PeerId;
+use std::fmt;
+use std::net::SocketAddr;
+
+/// reasons a network operation fails
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// the ledger rejected a block or transaction
+    Ledger(bloc_core::Error),
+    /// reading or writing a socket failed
+    Io(String),
+    /// a message failed to (de)serialize
+    Serialize(String),
+    /// the frame does not start with the network magic
+    BadMagic,
+    /// the payload does not match the checksum of its frame
+    BadChecksum,
+    /// the frame announces a payload over `MAX_MESSAGE_SIZE`
+    MessageTooLarge(u32),
+    /// the peer speaks a protocol version older than the supported ones
+    UnsupportedProtocol(u32),
+    /// the peer follows another chain
+    ChainMismatch,
+    /// the node connected to itself
+    SelfConnection,
+    /// the peer sent a message out of place
+    Protocol(String),
+    /// no room left for another peer
+    TooManyPeers,
+    /// a connection to the node already exists
+    AlreadyConnected,
+    /// no connected peer has this id
+    UnknownPeer(PeerId),
+    /// the rpc server answered with an error code and message
+    Rpc(i64, String),
+    /// the rpc server may only listen on a loopback address
+    RpcNotLocal(SocketAddr),
+    /// the command line is invalid, or not possible on the data directory
+    Usage(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Ledger(e) => write!(f, "{}", e),
+            Error::Io(desc) => write!(f, "io error: {}", desc),
+            Error::Serialize(desc) => write!(f, "serialization failed: {}", desc),
+            Error::BadMagic => write!(f, "message does not start with the network magic"),
+            Error::BadChecksum => write!(f, "message checksum mismatch"),
+            Error::MessageTooLarge(size) => write!(f, "message of {} bytes is too large", size),
+            Error::UnsupportedProtocol(version) => {
+                write!(f, "unsupported protocol version {}", version)
+            }
+            Error::ChainMismatch => write!(f, "peer follows another chain"),
+            Error::SelfConnection => write!(f, "connected to self"),
+            Error::Protocol(desc) => write!(f, "protocol violation: {}", desc),
+            Error::TooManyPeers => write!(f, "too many peers"),
+            Error::AlreadyConnected => write!(f, "already connected to the node"),
+            Error::UnknownPeer(id) => write!(f, "unknown peer {}", id),
+            Error::Rpc(code, message) => write!(f, "rpc error {}: {}", code, message),
+            Error::RpcNotLocal(addr) => write!(f, "rpc address {} is not a loopback one", addr),
+            Error::Usage(desc) => write!(f, "{}", desc),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<bloc_core::Error> for Error {
+    fn from(e: bloc_core::Error) -> Self {
+        Error::Ledger(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Serialize(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serialize(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e.to_string())
+    }
+}
@@ -0,0 +1,12 @@
+//! native node of bloc: peers exchanging blocks and transactions of a
+//! `bloc_core::blockchain::BlockChain` over TCP, and a JSON-RPC server to
+//! query and drive it
+pub mod cli;
+pub mod error;
+pub mod message;
+pub mod node;
+pub mod peer;
+pub mod rpc;
+pub mod sync;
+
+pub use error::Error;
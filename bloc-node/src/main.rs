@@ -0,0 +1,8 @@
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = bloc_node::cli::run(&args, &mut std::io::stdout()) {
+        eprintln!("bloc: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::Error;
+use bitcoin_hashes::sha256::Hash as Sha256;
+use bloc_core::block::{Block, BlockHeader};
+use bloc_core::template::MAX_BLOCK_SIZE;
+use bloc_core::transaction::Transaction;
+use bloc_core::utxo::UtxoTransaction;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+
+/// version of the messages below, sent in the handshake
+pub const PROTOCOL_VERSION: u32 = 1;
+/// oldest version a peer may speak
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+/// first bytes of every frame
+pub const MAGIC: [u8; 4] = *b"bloc";
+/// largest payload of a frame, a block and some slack
+pub const MAX_MESSAGE_SIZE: u32 = 2 * MAX_BLOCK_SIZE as u32;
+/// most headers sent in answer to a `GetHeaders`
+pub const MAX_HEADERS: usize = 2000;
+
+/// what a node tells about itself when connecting
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Version {
+    pub version: u32,
+    pub chain_id: Sha256,
+    pub genesis: Sha256,
+    /// height of the active chain
+    pub height: u64,
+    /// port the node accepts connections on, if it does
+    pub listen_port: Option<u16>,
+    /// random per node, to detect connecting to self
+    pub nonce: u64,
+}
+
+/// an object announced to or requested from a peer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Inventory {
+    Block(Sha256),
+    Transaction(Sha256),
+    UtxoTransaction(Sha256),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Message {
+    Version(Version),
+    /// the version of the peer is accepted
+    Verack,
+    Ping(u64),
+    Pong(u64),
+    /// objects the sender has
+    Inv(Vec<Inventory>),
+    /// objects the sender wants
+    GetData(Vec<Inventory>),
+    /// requested objects the sender does not have
+    NotFound(Vec<Inventory>),
+    Block(Block),
+    Transaction(Transaction),
+    UtxoTransaction(UtxoTransaction),
+    /// headers of the active chain after the first hash of `locator` the
+    /// receiver knows, up to `stop`
+    GetHeaders {
+        locator: Vec<Sha256>,
+        stop: Option<Sha256>,
+    },
+    Headers(Vec<BlockHeader>),
+    /// ask for the addresses of the peers of the receiver
+    GetAddr,
+    Addr(Vec<SocketAddr>),
+}
+
+impl Message {
+    /// name of the message in logs
+    pub fn command(&self) -> &'static str {
+        match self {
+            Message::Version(_) => "version",
+            Message::Verack => "verack",
+            Message::Ping(_) => "ping",
+            Message::Pong(_) => "pong",
+            Message::Inv(_) => "inv",
+            Message::GetData(_) => "getdata",
+            Message::NotFound(_) => "notfound",
+            Message::Block(_) => "block",
+            Message::Transaction(_) => "tx",
+            Message::UtxoTransaction(_) => "utxotx",
+            Message::GetHeaders { .. } => "getheaders",
+            Message::Headers(_) => "headers",
+            Message::GetAddr => "getaddr",
+            Message::Addr(_) => "addr",
+        }
+    }
+}
+
+/// write `message` framed as the magic, the payload length and crc32, then
+/// the bincode payload
+pub fn write_message<W: Write>(writer: &mut W, message: &Message) -> Result<(), Error> {
+    let payload = bincode::serialize(message)?;
+    if payload.len() > MAX_MESSAGE_SIZE as usize {
+        return Err(Error::MessageTooLarge(payload.len() as u32));
+    }
+    let mut frame = Vec::with_capacity(12 + payload.len());
+    frame.extend_from_slice(&MAGIC);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// read the next frame written by `write_message`
+pub fn read_message<R: Read>(reader: &mut R) -> Result<Message, Error> {
+    let mut head = [0u8; 12];
+    reader.read_exact(&mut head)?;
+    if head[..4] != MAGIC {
+        return Err(Error::BadMagic);
+    }
+    let len = u32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+    if len > MAX_MESSAGE_SIZE {
+        return Err(Error::MessageTooLarge(len));
+    }
+    let checksum = u32::from_le_bytes([head[8], head[9], head[10], head[11]]);
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    if crc32fast::hash(&payload) != checksum {
+        return Err(Error::BadChecksum);
+    }
+    Ok(bincode::deserialize(&payload)?)
+}
+
+#[cfg(test)]
+mod test_message {
+    use super::*;
+    use bloc_core::blockchain::BlockChain;
+
+    #[test]
+    fn test_roundtrip() {
+        let chain = BlockChain::new();
+        let messages = [
+            Message::Ping(7),
+            Message::Block(chain.chain[0].clone()),
+            Message::GetHeaders {
+                locator: chain.locator(),
+                stop: None,
+            },
+            Message::Inv(vec![Inventory::Block(chain.genesis_hash())]),
+        ];
+        let mut buf = Vec::new();
+        for message in messages.iter() {
+            write_message(&mut buf, message).unwrap();
+        }
+        let mut reader = &buf[..];
+        for message in messages.iter() {
+            assert_eq!(&read_message(&mut reader).unwrap(), message);
+        }
+        assert!(matches!(read_message(&mut reader), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &Message::Pong(42)).unwrap();
+
+        let mut corrupted = buf.clone();
+        corrupted[0] = b'c';
+        assert_eq!(read_message(&mut &corrupted[..]), Err(Error::BadMagic));
+
+        let mut corrupted = buf.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(read_message(&mut &corrupted[..]), Err(Error::BadChecksum));
+
+        let mut corrupted = buf;
+        corrupted[4..8].copy_from_slice(&(MAX_MESSAGE_SIZE + 1).to_le_bytes());
+        assert_eq!(
+            read_message(&mut &corrupted[..]),
+            Err(Error::MessageTooLarge(MAX_MESSAGE_SIZE + 1))
+        );
+    }
+}
@@ -0,0 +1,758 @@
+use crate::message::{read_message, Inventory, Message, Version, MAX_HEADERS, PROTOCOL_VERSION};
+use crate::peer::{handshake, Peer, PeerId, PeerInfo, HANDSHAKE_TIMEOUT};
+use crate::sync::BlockSync;
+use crate::Error;
+use bloc_core::block::Block;
+use bloc_core::blockchain::BlockChain;
+use bloc_core::miner::{CancelToken, MineOutcome, Miner};
+use bloc_core::transaction::Transaction;
+use bloc_core::tree::ChainEvent;
+use bloc_core::utxo::UtxoTransaction;
+use bloc_core::Address;
+use rand_core::{OsRng, RngCore};
+use std::collections::{HashMap, HashSet};
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// most addresses kept from the `Addr` messages of peers
+const MAX_ADDRS: usize = 1000;
+/// how often the maintenance thread wakes up
+const TICK: Duration = Duration::from_millis(50);
+
+/// settings of a `Node`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeConfig {
+    /// address peers connect to, port 0 picks a free one
+    pub listen: SocketAddr,
+    pub max_peers: usize,
+    /// peers are pinged this often, and dropped after three silent intervals
+    pub ping_interval: Duration,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 0)),
+            max_peers: 8,
+            ping_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// a chain gossiping its blocks and transactions with its peers: what a
+/// peer announces and the node misses is requested, what the node accepts
+/// is announced to the other peers
+pub struct Node {
+    shared: Arc<Shared>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+// state of the node shared by the threads of its listener and peers
+struct Shared {
+    chain: Mutex<BlockChain>,
+    // locked after the chain when both are
+    sync: Mutex<BlockSync>,
+    peers: Mutex<HashMap<PeerId, Arc<Peer>>>,
+    addrs: Mutex<HashSet<SocketAddr>>,
+    config: NodeConfig,
+    local_addr: SocketAddr,
+    nonce: u64,
+    next_id: AtomicU64,
+    running: AtomicBool,
+    // cancels the local mining once a block of a peer extends the chain
+    mining: Mutex<Option<CancelToken>>,
+}
+
+impl Node {
+    /// accept peers on `config.listen` for `chain`
+    pub fn start(chain: BlockChain, config: NodeConfig) -> Result<Self, Error> {
+        let listener = TcpListener::bind(config.listen)?;
+        let local_addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            chain: Mutex::new(chain),
+            sync: Mutex::new(BlockSync::new()),
+            peers: Mutex::new(HashMap::new()),
+            addrs: Mutex::new(HashSet::new()),
+            config,
+            local_addr,
+            nonce: OsRng.next_u64(),
+            next_id: AtomicU64::new(0),
+            running: AtomicBool::new(true),
+            mining: Mutex::new(None),
+        });
+        let accepting = shared.clone();
+        let maintained = shared.clone();
+        let threads = vec![
+            thread::spawn(move || accepting.accept(listener)),
+            thread::spawn(move || maintained.maintain()),
+        ];
+        log::info!("node listening on {}", local_addr);
+        Ok(Self { shared, threads })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.shared.local_addr
+    }
+
+    /// lock the chain of the node, peers wait for it to be released
+    pub fn chain(&self) -> MutexGuard<'_, BlockChain> {
+        self.shared.chain()
+    }
+
+    /// open a connection to the node at `addr`
+    pub fn connect(&self, addr: SocketAddr) -> Result<PeerId, Error> {
+        let stream = TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT)?;
+        self.shared.add_peer(stream, false)
+    }
+
+    pub fn disconnect(&self, id: PeerId) -> Result<(), Error> {
+        let peer = self
+            .shared
+            .peers()
+            .remove(&id)
+            .ok_or(Error::UnknownPeer(id))?;
+        peer.close();
+        Ok(())
+    }
+
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self
+            .shared
+            .peer_list()
+            .iter()
+            .map(|peer| peer.info())
+            .collect();
+        peers.sort_by_key(|peer| peer.id);
+        peers
+    }
+
+    /// addresses of the nodes learned from the peers
+    pub fn known_addrs(&self) -> Vec<SocketAddr> {
+        self.shared
+            .addrs
+            .lock()
+            .expect("addresses poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// queue `trans` and announce it to the peers
+    pub fn submit_transaction(&self, trans: Transaction) -> Result<(), Error> {
+        let txid = trans.txid()?;
+        self.chain().add_transaction(trans)?;
+        self.shared
+            .broadcast(&Message::Inv(vec![Inventory::Transaction(txid)]), None);
+        Ok(())
+    }
+
+    pub fn submit_utxo_transaction(&self, tx: UtxoTransaction) -> Result<(), Error> {
+        let txid = tx.txid()?;
+        self.chain().add_utxo_transaction(tx)?;
+        self.shared
+            .broadcast(&Message::Inv(vec![Inventory::UtxoTransaction(txid)]), None);
+        Ok(())
+    }
+
+    /// submit `block` to the chain, announcing it if it moved the tip
+    pub fn submit_block(&self, block: Block) -> Result<ChainEvent, Error> {
+        let hash = block.hash;
+        let event = self.chain().submit_block(block)?;
+        if let ChainEvent::Connected { .. } | ChainEvent::Reorganized(_) = event {
+            self.shared
+                .broadcast(&Message::Inv(vec![Inventory::Block(hash)]), None);
+        }
+        Ok(event)
+    }
+
+    /// mine a block of the pending transactions paying `reward_address`
+    /// without holding the chain, `None` when a block of a peer arrived
+    /// first
+    pub fn mine(&self, reward_address: &Address) -> Result<Option<Block>, Error> {
+        let template = self.chain().block_template(reward_address)?;
+        let cancel = CancelToken::new();
+        *self.shared.mining.lock().expect("mining poisoned") = Some(cancel.clone());
+        let outcome = Miner::default().mine(template.block, &cancel, |stats| {
+            log::debug!("mining at {:.0}H/s", stats.hash_rate())
+        });
+        *self.shared.mining.lock().expect("mining poisoned") = None;
+        match outcome? {
+            MineOutcome::Mined { block, .. } => match self.submit_block(block.clone())? {
+                ChainEvent::Connected { .. } | ChainEvent::Reorganized(_) => Ok(Some(block)),
+                _ => Ok(None),
+            },
+            MineOutcome::Cancelled(_) => Ok(None),
+        }
+    }
+
+    /// stop accepting peers and close every connection
+    pub fn shutdown(&mut self) {
+        if !self.shared.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        // wake the listener up so it sees the node stopped
+        let _ = TcpStream::connect(self.shared.local_addr);
+        for peer in self.shared.peers().drain().map(|(_, peer)| peer) {
+            peer.close();
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        log::info!("node on {} stopped", self.shared.local_addr);
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl Shared {
+    fn chain(&self) -> MutexGuard<'_, BlockChain> {
+        self.chain.lock().expect("chain poisoned")
+    }
+
+    fn sync(&self) -> MutexGuard<'_, BlockSync> {
+        self.sync.lock().expect("sync poisoned")
+    }
+
+    fn peers(&self) -> MutexGuard<'_, HashMap<PeerId, Arc<Peer>>> {
+        self.peers.lock().expect("peers poisoned")
+    }
+
+    fn peer_list(&self) -> Vec<Arc<Peer>> {
+        self.peers().values().cloned().collect()
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    fn version(&self) -> Version {
+        let chain = self.chain();
+        Version {
+            version: PROTOCOL_VERSION,
+            chain_id: chain.chain_id,
+            genesis: chain.genesis_hash(),
+            height: chain.len() as u64 - 1,
+            listen_port: Some(self.local_addr.port()),
+            nonce: self.nonce,
+        }
+    }
+
+    fn accept(self: Arc<Self>, listener: TcpListener) {
+        for stream in listener.incoming() {
+            if !self.is_running() {
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    let shared = self.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = shared.add_peer(stream, true) {
+                            log::debug!("inbound connection refused: {}", e);
+                        }
+                    });
+                }
+                Err(e) => log::warn!("failed to accept a connection: {}", e),
+            }
+        }
+    }
+
+    // ping the peers every interval, dropping those silent for three, and
+    // hand the blocks of slow peers to others
+    fn maintain(self: Arc<Self>) {
+        let interval = self.config.ping_interval;
+        let mut last_ping = Instant::now();
+        while self.is_running() {
+            thread::sleep(TICK);
+            self.request_blocks();
+            if last_ping.elapsed() < interval {
+                continue;
+            }
+            last_ping = Instant::now();
+            for peer in self.peer_list() {
+                if peer.idle() > interval * 3 {
+                    log::info!("peer {} timed out", peer.id);
+                    self.remove_peer(peer.id);
+                } else if let Err(e) = peer.send(&Message::Ping(OsRng.next_u64())) {
+                    log::debug!("failed to ping peer {}: {}", peer.id, e);
+                }
+            }
+        }
+    }
+
+    // complete the handshake on `stream` and start reading its messages
+    fn add_peer(self: &Arc<Self>, mut stream: TcpStream, inbound: bool) -> Result<PeerId, Error> {
+        if self.peers().len() >= self.config.max_peers {
+            return Err(Error::TooManyPeers);
+        }
+        let ours = self.version();
+        let theirs = handshake(&mut stream, &ours)?;
+        let reader = stream.try_clone()?;
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let peer = Arc::new(Peer::new(id, stream, theirs, inbound)?);
+        {
+            let mut peers = self.peers();
+            if peers.len() >= self.config.max_peers {
+                peer.close();
+                return Err(Error::TooManyPeers);
+            }
+            if peers
+                .values()
+                .any(|other| other.version.nonce == peer.version.nonce)
+            {
+                peer.close();
+                return Err(Error::AlreadyConnected);
+            }
+            peers.insert(id, peer.clone());
+        }
+        if let Some(addr) = peer.listen_addr() {
+            self.addrs.lock().expect("addresses poisoned").insert(addr);
+        }
+        log::info!(
+            "peer {} connected from {} at height {}",
+            id,
+            peer.addr,
+            peer.version.height
+        );
+        if peer.version.height > ours.height {
+            if let Some(message) = self.start_sync(id) {
+                peer.send(&message)?;
+            }
+        }
+        if !inbound {
+            peer.send(&Message::GetAddr)?;
+        }
+        let shared = self.clone();
+        thread::spawn(move || shared.run_peer(peer, reader));
+        Ok(id)
+    }
+
+    fn run_peer(self: Arc<Self>, peer: Arc<Peer>, reader: TcpStream) {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let message = match read_message(&mut reader) {
+                Ok(message) => message,
+                Err(e) => {
+                    log::debug!("peer {} disconnected: {}", peer.id, e);
+                    break;
+                }
+            };
+            peer.seen();
+            log::trace!("peer {}: received {}", peer.id, message.command());
+            if let Err(e) = self.handle(&peer, message) {
+                log::warn!("dropping peer {}: {}", peer.id, e);
+                break;
+            }
+        }
+        self.remove_peer(peer.id);
+    }
+
+    fn remove_peer(&self, id: PeerId) {
+        if let Some(peer) = self.peers().remove(&id) {
+            peer.close();
+        }
+        self.sync().peer_gone(id);
+    }
+
+    // the `GetHeaders` starting a sync from `peer`, unless one is running
+    fn start_sync(&self, peer: PeerId) -> Option<Message> {
+        let chain = self.chain();
+        let message = self.sync().start(&chain, peer);
+        message
+    }
+
+    // ask the peers for the next blocks the sync misses
+    fn request_blocks(&self) {
+        let peers: HashMap<PeerId, Arc<Peer>> = self.peers().clone();
+        let ids: Vec<PeerId> = peers.keys().cloned().collect();
+        let requests = self.sync().requests(&ids, Instant::now());
+        for (id, message) in requests {
+            if let Err(e) = peers[&id].send(&message) {
+                log::debug!("failed to ask peer {} for blocks: {}", id, e);
+            }
+        }
+    }
+
+    fn broadcast(&self, message: &Message, except: Option<PeerId>) {
+        for peer in self.peer_list() {
+            if Some(peer.id) == except {
+                continue;
+            }
+            if let Err(e) = peer.send(message) {
+                log::debug!(
+                    "failed to send {} to peer {}: {}",
+                    message.command(),
+                    peer.id,
+                    e
+                );
+            }
+        }
+    }
+
+    // answer a message of `peer`, an error drops the peer; the chain is
+    // released before anything is sent so two nodes never wait on each other
+    fn handle(&self, peer: &Peer, message: Message) -> Result<(), Error> {
+        match message {
+            Message::Version(_) | Message::Verack => Err(Error::Protocol(format!(
+                "{} after the handshake",
+                message.command()
+            ))),
+            Message::Ping(nonce) => peer.send(&Message::Pong(nonce)),
+            Message::Pong(_) => Ok(()),
+            Message::Inv(items) => {
+                let wanted: Vec<Inventory> = {
+                    let chain = self.chain();
+                    items
+                        .into_iter()
+                        .filter(|item| !has(&chain, item))
+                        .collect()
+                };
+                if wanted.is_empty() {
+                    return Ok(());
+                }
+                peer.send(&Message::GetData(wanted))
+            }
+            Message::GetData(items) => {
+                let (mut found, mut missing) = (Vec::new(), Vec::new());
+                {
+                    let chain = self.chain();
+                    for item in items {
+                        match get(&chain, &item) {
+                            Some(message) => found.push(message),
+                            None => missing.push(item),
+                        }
+                    }
+                }
+                for message in found.iter() {
+                    peer.send(message)?;
+                }
+                if !missing.is_empty() {
+                    peer.send(&Message::NotFound(missing))?;
+                }
+                Ok(())
+            }
+            Message::NotFound(items) => {
+                log::debug!("peer {} misses {} objects", peer.id, items.len());
+                self.sync().not_found(peer.id, &items);
+                self.request_blocks();
+                Ok(())
+            }
+            Message::Block(block) => {
+                let synced = self.sync().wants(&block.hash);
+                if synced {
+                    self.sync().on_block(block);
+                    self.connect_synced(peer);
+                    self.request_blocks();
+                } else {
+                    self.receive_block(peer, block);
+                }
+                Ok(())
+            }
+            Message::Transaction(trans) => {
+                let txid = trans.txid()?;
+                match self.chain().add_transaction(trans) {
+                    Ok(()) => self.broadcast(
+                        &Message::Inv(vec![Inventory::Transaction(txid)]),
+                        Some(peer.id),
+                    ),
+                    Err(e) => log::debug!("peer {}: transaction {} rejected: {}", peer.id, txid, e),
+                }
+                Ok(())
+            }
+            Message::UtxoTransaction(tx) => {
+                let txid = tx.txid()?;
+                match self.chain().add_utxo_transaction(tx) {
+                    Ok(()) => self.broadcast(
+                        &Message::Inv(vec![Inventory::UtxoTransaction(txid)]),
+                        Some(peer.id),
+                    ),
+                    Err(e) => log::debug!("peer {}: transaction {} rejected: {}", peer.id, txid, e),
+                }
+                Ok(())
+            }
+            Message::GetHeaders { locator, stop } => {
+                let headers = self
+                    .chain()
+                    .headers_after(&locator, stop.as_ref(), MAX_HEADERS);
+                peer.send(&Message::Headers(headers))
+            }
+            Message::Headers(headers) => {
+                if headers.len() > MAX_HEADERS {
+                    return Err(Error::Protocol(format!("{} headers", headers.len())));
+                }
+                let next = {
+                    let chain = self.chain();
+                    let next = self.sync().on_headers(&chain, peer.id, headers)?;
+                    next
+                };
+                if let Some(message) = next {
+                    peer.send(&message)?;
+                }
+                self.request_blocks();
+                Ok(())
+            }
+            Message::GetAddr => {
+                let addrs = self
+                    .addrs
+                    .lock()
+                    .expect("addresses poisoned")
+                    .iter()
+                    .filter(|addr| Some(**addr) != peer.listen_addr())
+                    .cloned()
+                    .collect();
+                peer.send(&Message::Addr(addrs))
+            }
+            Message::Addr(addrs) => {
+                let mut known = self.addrs.lock().expect("addresses poisoned");
+                for addr in addrs {
+                    if known.len() >= MAX_ADDRS {
+                        break;
+                    }
+                    if addr != self.local_addr {
+                        known.insert(addr);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // a block the chain rejects is only logged, its peer may be on a branch
+    // this node does not know yet
+    fn receive_block(&self, peer: &Peer, block: Block) {
+        let hash = block.hash;
+        let result = self.chain().submit_block(block);
+        match result {
+            Ok(ChainEvent::Connected { height }) => {
+                log::info!(
+                    "block {} from peer {} connected at {}",
+                    hash,
+                    peer.id,
+                    height
+                );
+                self.block_arrived(hash, peer.id);
+            }
+            Ok(ChainEvent::Reorganized(reorg)) => {
+                log::info!(
+                    "block {} from peer {} reorganized at {}",
+                    hash,
+                    peer.id,
+                    reorg.fork_height
+                );
+                self.block_arrived(hash, peer.id);
+            }
+            Ok(_) => {}
+            Err(bloc_core::Error::UnknownParent(_)) => {
+                // the peer is ahead, sync the headers leading to the block
+                if let Some(message) = self.start_sync(peer.id) {
+                    if let Err(e) = peer.send(&message) {
+                        log::debug!("failed to ask peer {} for headers: {}", peer.id, e);
+                    }
+                }
+            }
+            Err(e) => log::warn!("block {} from peer {} rejected: {}", hash, peer.id, e),
+        }
+    }
+
+    // connect the downloaded blocks whose parent is in the chain, only the
+    // new tip is announced
+    fn connect_synced(&self, peer: &Peer) {
+        let mut tip = None;
+        {
+            let mut chain = self.chain();
+            let ready = self.sync().ready();
+            for block in ready {
+                let hash = block.hash;
+                match chain.submit_block(block) {
+                    Ok(ChainEvent::Connected { .. }) | Ok(ChainEvent::Reorganized(_)) => {
+                        tip = Some(hash)
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::warn!(
+                            "synced block {} from peer {} rejected: {}",
+                            hash,
+                            peer.id,
+                            e
+                        );
+                        self.sync().block_failed(&hash);
+                        break;
+                    }
+                }
+            }
+            if let Some(hash) = tip {
+                log::info!("synced to block {} at {}", hash, chain.len() - 1);
+            }
+        }
+        if let Some(hash) = tip {
+            self.block_arrived(hash, peer.id);
+        }
+    }
+
+    fn block_arrived(&self, hash: bitcoin_hashes::sha256::Hash, from: PeerId) {
+        if let Some(cancel) = self.mining.lock().expect("mining poisoned").as_ref() {
+            cancel.cancel();
+        }
+        self.broadcast(&Message::Inv(vec![Inventory::Block(hash)]), Some(from));
+    }
+}
+
+// whether the chain already has the object of `item`
+fn has(chain: &BlockChain, item: &Inventory) -> bool {
+    match item {
+        Inventory::Block(hash) => chain.tree.contains(hash),
+        Inventory::Transaction(txid) => chain.mempool.contains(txid),
+        Inventory::UtxoTransaction(txid) => chain
+            .utxo_transactions
+            .iter()
+            .any(|tx| tx.txid().ok().as_ref() == Some(txid)),
+    }
+}
+
+// the message carrying the object of `item`, if the chain has it
+fn get(chain: &BlockChain, item: &Inventory) -> Option<Message> {
+    match item {
+        Inventory::Block(hash) => chain
+            .tree
+            .get(hash)
+            .map(|node| Message::Block(node.block.clone())),
+        Inventory::Transaction(txid) => chain
+            .mempool
+            .get(txid)
+            .map(|entry| Message::Transaction(entry.trans.clone())),
+        Inventory::UtxoTransaction(txid) => chain
+            .utxo_transactions
+            .iter()
+            .find(|tx| tx.txid().ok().as_ref() == Some(txid))
+            .map(|tx| Message::UtxoTransaction(tx.clone())),
+    }
+}
+
+#[cfg(test)]
+mod test_node {
+    use super::*;
+    use bloc_core::constant::*;
+    use bloc_core::genesis::GenesisConfig;
+    use bloc_core::Amount;
+
+    fn start(config: &GenesisConfig) -> Node {
+        let chain = BlockChain::from_genesis(config).unwrap();
+        Node::start(chain, NodeConfig::default()).unwrap()
+    }
+
+    // poll `done` for up to ten seconds
+    fn wait_until<F: Fn() -> bool>(done: F) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(10) {
+            if done() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn test_handshake() {
+        let a = start(&GenesisConfig::default());
+        let b = start(&GenesisConfig::default());
+        a.connect(b.local_addr()).unwrap();
+        assert!(wait_until(|| b.peers().len() == 1));
+        assert_eq!(a.peers()[0].listen_addr, Some(b.local_addr()));
+        assert!(!a.peers()[0].inbound && b.peers()[0].inbound);
+        assert_eq!(a.connect(b.local_addr()), Err(Error::AlreadyConnected));
+        assert_eq!(a.connect(a.local_addr()), Err(Error::SelfConnection));
+
+        let other = start(&funded_genesis(1000));
+        assert_eq!(a.connect(other.local_addr()), Err(Error::ChainMismatch));
+        assert!(wait_until(|| other.peers().is_empty()));
+
+        let id = a.peers()[0].id;
+        a.disconnect(id).unwrap();
+        assert!(wait_until(|| b.peers().is_empty()));
+        assert_eq!(a.disconnect(id), Err(Error::UnknownPeer(id)));
+    }
+
+    #[test]
+    fn test_gossip() {
+        let config = funded_genesis(1000);
+        let nodes: Vec<Node> = (0..3).map(|_| start(&config)).collect();
+        // a line: the first and last nodes only hear of each other through
+        // the middle one
+        nodes[0].connect(nodes[1].local_addr()).unwrap();
+        nodes[1].connect(nodes[2].local_addr()).unwrap();
+
+        let mut trans = Transaction::new(KEY1.1, KEY2.3, Amount::from_coins(10).unwrap(), None, 0);
+        trans.sign(&KEY1.2, &nodes[0].chain().chain_id).unwrap();
+        let txid = trans.txid().unwrap();
+        nodes[0].submit_transaction(trans).unwrap();
+        assert!(wait_until(|| nodes[2].chain().mempool.contains(&txid)));
+
+        let block = nodes[2].mine(&KEY2.3).unwrap().unwrap();
+        for node in nodes.iter() {
+            assert!(wait_until(
+                || node.chain().get_last_block().hash == block.hash
+            ));
+            assert!(node.chain().mempool.is_empty());
+        }
+        assert_eq!(
+            nodes[0].chain().get_balance(&KEY2.3),
+            Ok(Amount::from_coins(110).unwrap())
+        );
+
+        // a newcomer learns the other nodes from the one it connects to
+        let newcomer = start(&config);
+        newcomer.connect(nodes[1].local_addr()).unwrap();
+        assert!(wait_until(|| newcomer.known_addrs().len() == 3));
+        assert!(newcomer.known_addrs().contains(&nodes[0].local_addr()));
+    }
+
+    #[test]
+    fn test_catch_up() {
+        let a = start(&GenesisConfig::default());
+        let b = start(&GenesisConfig::default());
+        for _ in 0..3 {
+            b.mine(&KEY1.3).unwrap().unwrap();
+        }
+        a.connect(b.local_addr()).unwrap();
+        assert!(wait_until(|| a.chain().len() == 4));
+        assert_eq!(a.chain().chain, b.chain().chain);
+
+        // a block mined while connected is announced and fetched
+        b.mine(&KEY1.3).unwrap().unwrap();
+        assert!(wait_until(|| a.chain().len() == 5));
+
+        // a newcomer downloads a longer chain from both nodes at once
+        for _ in 0..40 {
+            b.mine(&KEY1.3).unwrap().unwrap();
+        }
+        assert!(wait_until(|| a.chain().len() == 45));
+        let c = start(&GenesisConfig::default());
+        c.connect(a.local_addr()).unwrap();
+        c.connect(b.local_addr()).unwrap();
+        assert!(wait_until(|| c.chain().len() == 45));
+        assert_eq!(c.chain().chain, b.chain().chain);
+        assert!(wait_until(|| c.shared.sync().is_idle()));
+    }
+
+    #[test]
+    fn test_protocol_violation() {
+        let a = start(&GenesisConfig::default());
+        let mut stream = TcpStream::connect(a.local_addr()).unwrap();
+        let version = Version {
+            nonce: 1,
+            ..a.shared.version()
+        };
+        handshake(&mut stream, &version).unwrap();
+        assert!(wait_until(|| a.peers().len() == 1));
+        crate::message::write_message(&mut stream, &Message::Verack).unwrap();
+        assert!(wait_until(|| a.peers().is_empty()));
+    }
+}
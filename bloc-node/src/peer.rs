@@ -0,0 +1,134 @@
+use crate::message::{read_message, write_message, Message, Version, MIN_PROTOCOL_VERSION};
+use crate::Error;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// id of a connection, unique within a node
+pub type PeerId = u64;
+
+/// time a peer has to complete the handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// a connected node that completed the handshake
+#[derive(Debug)]
+pub struct Peer {
+    pub id: PeerId,
+    pub addr: SocketAddr,
+    /// whether the peer connected to us
+    pub inbound: bool,
+    /// what the peer told about itself
+    pub version: Version,
+    stream: Mutex<TcpStream>,
+    last_seen: Mutex<Instant>,
+}
+
+/// what is known of a connected peer
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerInfo {
+    pub id: PeerId,
+    pub addr: SocketAddr,
+    pub inbound: bool,
+    pub version: u32,
+    /// height of its chain when it connected
+    pub height: u64,
+    /// address it accepts connections on
+    pub listen_addr: Option<SocketAddr>,
+}
+
+impl Peer {
+    /// `stream` is the write half, messages are read on a clone of it
+    pub fn new(
+        id: PeerId,
+        stream: TcpStream,
+        version: Version,
+        inbound: bool,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            id,
+            addr: stream.peer_addr()?,
+            inbound,
+            version,
+            stream: Mutex::new(stream),
+            last_seen: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub fn send(&self, message: &Message) -> Result<(), Error> {
+        log::trace!("peer {}: sending {}", self.id, message.command());
+        let mut stream = self.stream.lock().expect("peer stream poisoned");
+        write_message(&mut *stream, message)
+    }
+
+    /// close the connection, the thread reading it stops
+    pub fn close(&self) {
+        let stream = self.stream.lock().expect("peer stream poisoned");
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    pub fn seen(&self) {
+        *self.last_seen.lock().expect("peer clock poisoned") = Instant::now();
+    }
+
+    /// time since the last message of the peer
+    pub fn idle(&self) -> Duration {
+        self.last_seen
+            .lock()
+            .expect("peer clock poisoned")
+            .elapsed()
+    }
+
+    pub fn listen_addr(&self) -> Option<SocketAddr> {
+        self.version
+            .listen_port
+            .map(|port| SocketAddr::new(self.addr.ip(), port))
+    }
+
+    pub fn info(&self) -> PeerInfo {
+        PeerInfo {
+            id: self.id,
+            addr: self.addr,
+            inbound: self.inbound,
+            version: self.version.version,
+            height: self.version.height,
+            listen_addr: self.listen_addr(),
+        }
+    }
+}
+
+/// exchange versions on a fresh connection, both sides send theirs first
+/// then acknowledge the other one; the version of the peer once accepted
+pub fn handshake(stream: &mut TcpStream, ours: &Version) -> Result<Version, Error> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    write_message(stream, &Message::Version(ours.clone()))?;
+    let theirs = match read_message(stream)? {
+        Message::Version(version) => version,
+        message => {
+            return Err(Error::Protocol(format!(
+                "{} before the version",
+                message.command()
+            )))
+        }
+    };
+    if theirs.version < MIN_PROTOCOL_VERSION {
+        return Err(Error::UnsupportedProtocol(theirs.version));
+    }
+    if theirs.nonce == ours.nonce {
+        return Err(Error::SelfConnection);
+    }
+    if theirs.genesis != ours.genesis || theirs.chain_id != ours.chain_id {
+        return Err(Error::ChainMismatch);
+    }
+    write_message(stream, &Message::Verack)?;
+    match read_message(stream)? {
+        Message::Verack => {}
+        message => {
+            return Err(Error::Protocol(format!(
+                "{} before the verack",
+                message.command()
+            )))
+        }
+    }
+    stream.set_read_timeout(None)?;
+    Ok(theirs)
+}
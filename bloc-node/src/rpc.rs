@@ -0,0 +1,656 @@
+use crate::node::Node;
+use crate::Error;
+use bitcoin_hashes::hex::ToHex as _;
+use bitcoin_hashes::sha256::Hash as Sha256;
+use bloc_core::block::Block;
+use bloc_core::blockchain::BlockChain;
+use bloc_core::pow::Target;
+use bloc_core::secp256k1::{decode_hex, PubKey};
+use bloc_core::transaction::Transaction;
+use bloc_core::utxo::{ChainModel, UtxoTransaction};
+use bloc_core::Address;
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// the body is not valid json
+pub const PARSE_ERROR: i64 = -32700;
+/// the json is not a request object
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// the block or transaction asked for is unknown
+pub const NOT_FOUND: i64 = -5;
+/// the chain rejected the submitted transaction
+pub const REJECTED: i64 = -26;
+
+/// most blocks one `mine` request mines, each holding up an rpc thread
+pub const MAX_MINE_COUNT: u64 = 100;
+
+/// largest request body accepted
+const MAX_BODY: usize = 1 << 20;
+/// largest request line and headers accepted
+const MAX_HEAD: u64 = 8 * 1024;
+/// time a client has to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// the error member of a response
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl From<RpcError> for Error {
+    fn from(e: RpcError) -> Self {
+        Error::Rpc(e.code, e.message)
+    }
+}
+
+impl RpcError {
+    fn new<M: ToString>(code: i64, message: M) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({"code": self.code, "message": self.message})
+    }
+}
+
+/// JSON-RPC 2.0 over HTTP for the chain of a node, one `POST` per request
+/// or batch; it has no authentication so it only listens on loopback
+/// addresses
+///
+/// | method | params | result |
+/// |---|---|---|
+/// | `getblockcount` | | height of the tip |
+/// | `getblock` | hash or height | the block and its height |
+/// | `gettransaction` | txid | the transaction, and its block once mined |
+/// | `getbalance` | address | amount |
+/// | `getnonce` | public key hex | nonce of its next transaction |
+/// | `sendrawtransaction` | bincode hex | txid |
+/// | `getmempool` | | txids of the pending transactions |
+/// | `getdifficulty` | | work of the next block relative to the easiest |
+/// | `mine` | count up to `MAX_MINE_COUNT`, address | hashes of the mined blocks |
+pub struct RpcServer {
+    local_addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RpcServer {
+    /// answer requests on `addr` for `node`
+    pub fn start(node: Arc<Node>, addr: SocketAddr) -> Result<Self, Error> {
+        if !addr.ip().is_loopback() {
+            return Err(Error::RpcNotLocal(addr));
+        }
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let serving = running.clone();
+        let thread = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !serving.load(Ordering::SeqCst) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => {
+                        let node = node.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = serve(&node, stream) {
+                                log::debug!("rpc connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => log::warn!("failed to accept an rpc connection: {}", e),
+                }
+            }
+        });
+        log::info!("rpc listening on {}", local_addr);
+        Ok(Self {
+            local_addr,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// stop accepting requests, those being answered complete
+    pub fn shutdown(&mut self) {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        // wake the listener up so it sees the server stopped
+        let _ = TcpStream::connect(self.local_addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// call `method` of the server at `addr`, the error of the server comes
+/// back as `Error::Rpc`
+pub fn call(addr: SocketAddr, method: &str, params: Value) -> Result<Value, Error> {
+    let body = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}).to_string();
+    let mut stream = TcpStream::connect(addr)?;
+    write!(
+        stream,
+        "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        addr,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    let mut reader = BufReader::new(stream);
+    let (status, _) = read_head(&mut reader)?;
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body)?;
+    if status != "200" {
+        return Err(Error::Protocol(format!("http status {}", status)));
+    }
+    let mut response: Value = serde_json::from_slice(&body)?;
+    if let Some(error) = response.get("error") {
+        return Err(Error::Rpc(
+            error["code"].as_i64().unwrap_or(INTERNAL_ERROR),
+            error["message"].as_str().unwrap_or_default().to_owned(),
+        ));
+    }
+    Ok(response["result"].take())
+}
+
+// answer the single request of `stream`
+fn serve(node: &Node, stream: TcpStream) -> Result<(), Error> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (method, length) = read_head(&mut reader)?;
+    let mut stream = stream;
+    if method != "POST" {
+        return write_response(&mut stream, "405 Method Not Allowed", "");
+    }
+    let length = match length {
+        Some(length) if length <= MAX_BODY => length,
+        Some(_) => return write_response(&mut stream, "413 Payload Too Large", ""),
+        None => return write_response(&mut stream, "411 Length Required", ""),
+    };
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+    match respond(node, &body) {
+        Some(response) => write_response(&mut stream, "200 OK", &response.to_string()),
+        None => write_response(&mut stream, "204 No Content", ""),
+    }
+}
+
+// the method of a request line or the status of a response line, and the
+// content length of the headers
+fn read_head<R: BufRead>(reader: &mut R) -> Result<(String, Option<usize>), Error> {
+    let mut head = reader.take(MAX_HEAD);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let first = words.next().unwrap_or_default();
+    let word = if first.starts_with("HTTP/") {
+        words.next().unwrap_or_default()
+    } else {
+        first
+    }
+    .to_owned();
+    let mut length = None;
+    loop {
+        line.clear();
+        if head.read_line(&mut line)? == 0 {
+            return Err(Error::Protocol("truncated http head".to_owned()));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok((word, length));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse().map_err(|_| {
+                    Error::Protocol(format!("bad content length {:?}", value.trim()))
+                })?);
+            }
+        }
+    }
+}
+
+fn write_response(stream: &mut TcpStream, status: &str, body: &str) -> Result<(), Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+// the response to a request or batch, `None` when it only held notifications
+fn respond(node: &Node, body: &[u8]) -> Option<Value> {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return Some(error_response(Value::Null, RpcError::new(PARSE_ERROR, e))),
+    };
+    match request {
+        Value::Array(batch) if batch.is_empty() => Some(error_response(
+            Value::Null,
+            RpcError::new(INVALID_REQUEST, "empty batch"),
+        )),
+        Value::Array(batch) => {
+            let responses: Vec<Value> = batch
+                .into_iter()
+                .filter_map(|request| respond_one(node, request))
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        request => respond_one(node, request),
+    }
+}
+
+fn respond_one(node: &Node, request: Value) -> Option<Value> {
+    let request = match request {
+        Value::Object(request) => request,
+        _ => {
+            return Some(error_response(
+                Value::Null,
+                RpcError::new(INVALID_REQUEST, "request is not an object"),
+            ))
+        }
+    };
+    // a request without id is a notification, never answered
+    let id = request.get("id").cloned();
+    let method = match (request.get("jsonrpc"), request.get("method")) {
+        (Some(Value::String(version)), Some(Value::String(method))) if version == "2.0" => method,
+        _ => {
+            return Some(error_response(
+                id.unwrap_or(Value::Null),
+                RpcError::new(INVALID_REQUEST, "not a json-rpc 2.0 request"),
+            ))
+        }
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    log::debug!("rpc {}", method);
+    let result = dispatch(node, method, &params);
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(e) => error_response(id, e),
+    })
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": error.to_json()})
+}
+
+fn dispatch(node: &Node, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "getblockcount" => Ok(json!(node.chain().len() as u64 - 1)),
+        "getblock" => get_block(&node.chain(), param(params, 0, "block")?),
+        "gettransaction" => get_transaction(&node.chain(), &parse_hash(param(params, 0, "txid")?)?),
+        "getbalance" => {
+            let address = parse_address(param(params, 0, "address")?)?;
+            let balance = node
+                .chain()
+                .get_balance(&address)
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e))?;
+            Ok(json!(balance.to_string()))
+        }
+        "getnonce" => {
+            let key = parse_key(param(params, 0, "key")?)?;
+            Ok(json!(node.chain().next_nonce(&key)))
+        }
+        "sendrawtransaction" => {
+            let hex = param(params, 0, "hex")?
+                .as_str()
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "hex must be a string"))?;
+            let raw = decode_hex(hex).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+            let model = node.chain().model();
+            let txid = match model {
+                ChainModel::Account => {
+                    let trans: Transaction = decode(&raw)?;
+                    let txid = trans.txid().map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                    node.submit_transaction(trans)
+                        .map_err(|e| RpcError::new(REJECTED, e))?;
+                    txid
+                }
+                ChainModel::Utxo => {
+                    let tx: UtxoTransaction = decode(&raw)?;
+                    let txid = tx.txid().map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                    node.submit_utxo_transaction(tx)
+                        .map_err(|e| RpcError::new(REJECTED, e))?;
+                    txid
+                }
+            };
+            Ok(json!(txid.to_hex()))
+        }
+        "getmempool" => {
+            let chain = node.chain();
+            let txids: Result<Vec<String>, _> = match chain.model() {
+                ChainModel::Account => chain
+                    .mempool
+                    .transactions()
+                    .iter()
+                    .map(|trans| trans.txid().map(|txid| txid.to_hex()))
+                    .collect(),
+                ChainModel::Utxo => chain
+                    .utxo_transactions
+                    .iter()
+                    .map(|tx| tx.txid().map(|txid| txid.to_hex()))
+                    .collect(),
+            };
+            txids
+                .map(|txids| json!(txids))
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e))
+        }
+        "getdifficulty" => {
+            let bits = node.chain().state.bits;
+            let target = Target::from_bits(bits).map_err(|e| RpcError::new(INTERNAL_ERROR, e))?;
+            Ok(json!(
+                target.work() as f64 / Target::pow_limit().work() as f64
+            ))
+        }
+        "mine" => {
+            let count = param(params, 0, "count")?
+                .as_u64()
+                .filter(|count| (1..=MAX_MINE_COUNT).contains(count))
+                .ok_or_else(|| {
+                    RpcError::new(
+                        INVALID_PARAMS,
+                        format!("count must be between 1 and {}", MAX_MINE_COUNT),
+                    )
+                })?;
+            let address = parse_address(param(params, 1, "address")?)?;
+            let mut hashes = Vec::new();
+            while (hashes.len() as u64) < count {
+                // `None` when a block of a peer came first, mine on top of it
+                if let Some(block) = node
+                    .mine(&address)
+                    .map_err(|e| RpcError::new(INTERNAL_ERROR, e))?
+                {
+                    hashes.push(json!(block.hash.to_hex()));
+                }
+            }
+            Ok(Value::Array(hashes))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method {}", method),
+        )),
+    }
+}
+
+// the parameter at `index` of positional params, or `name` of named ones
+fn param<'a>(params: &'a Value, index: usize, name: &str) -> Result<&'a Value, RpcError> {
+    let value = match params {
+        Value::Array(values) => values.get(index),
+        Value::Object(values) => values.get(name),
+        _ => None,
+    };
+    value.ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("missing {}", name)))
+}
+
+fn parse_hash(value: &Value) -> Result<Sha256, RpcError> {
+    value
+        .as_str()
+        .and_then(|hex| Sha256::from_str(hex).ok())
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected a 64 digit hex hash"))
+}
+
+fn parse_key(value: &Value) -> Result<PubKey, RpcError> {
+    let hex = value
+        .as_str()
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, "public key must be a hex string"))?;
+    PubKey::from_hex(hex).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+fn parse_address(value: &Value) -> Result<Address, RpcError> {
+    let address = value
+        .as_str()
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, "address must be a string"))?;
+    Address::from_str(address).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(raw: &[u8]) -> Result<T, RpcError> {
+    bincode::deserialize(raw).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+fn block_json(block: &Block, height: u64, chain: &BlockChain) -> Result<Value, RpcError> {
+    let mut value = serde_json::to_value(block).map_err(|e| RpcError::new(INTERNAL_ERROR, e))?;
+    let active = chain.chain.get(height as usize).map(|active| active.hash) == Some(block.hash);
+    if let Value::Object(map) = &mut value {
+        map.insert("height".to_owned(), json!(height));
+        // blocks off the active chain have no confirmations
+        let confirmations = if active {
+            chain.len() as u64 - height
+        } else {
+            0
+        };
+        map.insert("confirmations".to_owned(), json!(confirmations));
+    }
+    Ok(value)
+}
+
+// the block at a height of the active chain, or of a hash on any branch
+pub(crate) fn get_block(chain: &BlockChain, block: &Value) -> Result<Value, RpcError> {
+    if let Some(height) = block.as_u64() {
+        return match chain.chain.get(height as usize) {
+            Some(block) => block_json(block, height, chain),
+            None => Err(RpcError::new(NOT_FOUND, format!("no block at {}", height))),
+        };
+    }
+    let hash = parse_hash(block)?;
+    match chain.tree.get(&hash) {
+        Some(node) => block_json(&node.block, node.height, chain),
+        None => Err(RpcError::new(NOT_FOUND, format!("unknown block {}", hash))),
+    }
+}
+
+// a pending transaction, or one of the active chain with its block
+pub(crate) fn get_transaction(chain: &BlockChain, txid: &Sha256) -> Result<Value, RpcError> {
+    let to_json = |value: Result<Value, serde_json::Error>| {
+        value.map_err(|e| RpcError::new(INTERNAL_ERROR, e))
+    };
+    if let Some(entry) = chain.mempool.get(txid) {
+        return Ok(json!({
+            "transaction": to_json(serde_json::to_value(&entry.trans))?,
+            "confirmations": 0,
+        }));
+    }
+    if let Some(tx) = chain
+        .utxo_transactions
+        .iter()
+        .find(|tx| tx.txid().ok().as_ref() == Some(txid))
+    {
+        return Ok(json!({
+            "transaction": to_json(serde_json::to_value(tx))?,
+            "confirmations": 0,
+        }));
+    }
+    for (height, block) in chain.chain.iter().enumerate().rev() {
+        let transaction = block
+            .data
+            .iter()
+            .find(|trans| trans.txid().ok().as_ref() == Some(txid))
+            .map(serde_json::to_value)
+            .or_else(|| {
+                block
+                    .utxo_data
+                    .iter()
+                    .find(|tx| tx.txid().ok().as_ref() == Some(txid))
+                    .map(serde_json::to_value)
+            });
+        if let Some(transaction) = transaction {
+            let mut found = Map::new();
+            found.insert("transaction".to_owned(), to_json(transaction)?);
+            found.insert("blockhash".to_owned(), json!(block.hash.to_hex()));
+            found.insert("height".to_owned(), json!(height));
+            found.insert("confirmations".to_owned(), json!(chain.len() - height));
+            return Ok(Value::Object(found));
+        }
+    }
+    Err(RpcError::new(
+        NOT_FOUND,
+        format!("unknown transaction {}", txid),
+    ))
+}
+
+#[cfg(test)]
+mod test_rpc {
+    use super::*;
+    use crate::node::NodeConfig;
+    use bloc_core::constant::*;
+    use bloc_core::secp256k1::ToHex;
+    use bloc_core::Amount;
+
+    fn start() -> (Arc<Node>, RpcServer) {
+        let chain = BlockChain::from_genesis(&funded_genesis(1000)).unwrap();
+        let node = Arc::new(Node::start(chain, NodeConfig::default()).unwrap());
+        let server = RpcServer::start(node.clone(), SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        (node, server)
+    }
+
+    #[test]
+    fn test_methods() {
+        let (node, server) = start();
+        let addr = server.local_addr();
+        assert_eq!(call(addr, "getblockcount", json!([])), Ok(json!(0)));
+        let difficulty = call(addr, "getdifficulty", json!([])).unwrap();
+        assert!(difficulty.as_f64().unwrap() >= 1.0);
+
+        let mined = call(addr, "mine", json!([2, KEY2.3.to_string()])).unwrap();
+        assert_eq!(mined.as_array().unwrap().len(), 2);
+        assert_eq!(call(addr, "getblockcount", json!([])), Ok(json!(2)));
+        let block = call(addr, "getblock", json!([2])).unwrap();
+        assert_eq!(block["hash"], mined[1]);
+        assert_eq!(block["confirmations"], json!(1));
+        let block = call(addr, "getblock", json!({"block": mined[0]})).unwrap();
+        assert_eq!(block["height"], json!(1));
+
+        assert_eq!(
+            call(addr, "getnonce", json!([KEY1.1.to_hex()])),
+            Ok(json!(0))
+        );
+        let mut trans = Transaction::new(KEY1.1, KEY2.3, Amount::from_coins(10).unwrap(), None, 0);
+        trans.sign(&KEY1.2, &node.chain().chain_id).unwrap();
+        let hex = bincode::serialize(&trans).unwrap().to_hex();
+        let txid = call(addr, "sendrawtransaction", json!([hex])).unwrap();
+        assert_eq!(txid, json!(trans.txid().unwrap().to_hex()));
+        assert_eq!(call(addr, "getmempool", json!([])), Ok(json!([txid])));
+        assert_eq!(
+            call(addr, "getnonce", json!([KEY1.1.to_hex()])),
+            Ok(json!(1))
+        );
+        let pending = call(addr, "gettransaction", json!([txid])).unwrap();
+        assert_eq!(pending["confirmations"], json!(0));
+        assert_eq!(
+            call(addr, "sendrawtransaction", json!([hex]))
+                .map_err(|e| matches!(e, Error::Rpc(REJECTED, _))),
+            Err(true)
+        );
+
+        let mined = call(
+            addr,
+            "mine",
+            json!({"count": 1, "address": KEY2.3.to_string()}),
+        )
+        .unwrap();
+        let confirmed = call(addr, "gettransaction", json!([txid])).unwrap();
+        assert_eq!(confirmed["blockhash"], mined[0]);
+        assert_eq!(confirmed["height"], json!(3));
+        assert_eq!(call(addr, "getmempool", json!([])), Ok(json!([])));
+        assert_eq!(
+            call(addr, "getbalance", json!([KEY2.3.to_string()])),
+            Ok(json!(node
+                .chain()
+                .get_balance(&KEY2.3)
+                .unwrap()
+                .to_string()))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let (node, server) = start();
+        let addr = server.local_addr();
+        let genesis = node.chain().genesis_hash();
+        let code = |method: &str, params: Value| match call(addr, method, params) {
+            Err(Error::Rpc(code, _)) => code,
+            other => panic!("{} succeeded: {:?}", method, other),
+        };
+        assert_eq!(code("getnothing", json!([])), METHOD_NOT_FOUND);
+        assert_eq!(code("getblock", json!([])), INVALID_PARAMS);
+        assert_eq!(code("getblock", json!(["00"])), INVALID_PARAMS);
+        assert_eq!(code("getblock", json!([7])), NOT_FOUND);
+        assert_eq!(code("gettransaction", json!([genesis.to_hex()])), NOT_FOUND);
+        assert_eq!(code("getbalance", json!(["zz"])), INVALID_PARAMS);
+        assert_eq!(code("getbalance", json!([KEY1.1.to_hex()])), INVALID_PARAMS);
+        assert_eq!(code("sendrawtransaction", json!(["0g"])), INVALID_PARAMS);
+        assert_eq!(code("sendrawtransaction", json!(["0102"])), INVALID_PARAMS);
+        assert_eq!(
+            code("getnonce", json!([format!("02{}", "00".repeat(32))])),
+            INVALID_PARAMS
+        );
+        for count in [json!(-1), json!(0), json!(MAX_MINE_COUNT + 1)] {
+            assert_eq!(
+                code("mine", json!([count, KEY1.3.to_string()])),
+                INVALID_PARAMS
+            );
+        }
+
+        assert_eq!(
+            respond(&node, b"{"),
+            Some(
+                json!({"jsonrpc": "2.0", "id": null, "error": {"code": PARSE_ERROR, "message": "EOF while parsing an object at line 1 column 1"}})
+            )
+        );
+        assert_eq!(
+            respond(&node, b"[]").unwrap()["error"]["code"],
+            json!(INVALID_REQUEST)
+        );
+        assert_eq!(
+            respond(&node, br#"{"id": 3, "method": "getblockcount"}"#).unwrap()["error"]["code"],
+            json!(INVALID_REQUEST)
+        );
+        // a batch answers its requests but not its notifications
+        let batch = br#"[
+            {"jsonrpc": "2.0", "id": 1, "method": "getblockcount"},
+            {"jsonrpc": "2.0", "method": "getblockcount"},
+            {"jsonrpc": "2.0", "id": "b", "method": "getmempool", "params": []}
+        ]"#;
+        assert_eq!(
+            respond(&node, batch),
+            Some(json!([
+                {"jsonrpc": "2.0", "id": 1, "result": 0},
+                {"jsonrpc": "2.0", "id": "b", "result": []},
+            ]))
+        );
+        assert_eq!(
+            respond(&node, br#"{"jsonrpc": "2.0", "method": "getblockcount"}"#),
+            None
+        );
+
+        assert_eq!(
+            RpcServer::start(node, SocketAddr::from(([0, 0, 0, 0], 0))).err(),
+            Some(Error::RpcNotLocal(SocketAddr::from(([0, 0, 0, 0], 0))))
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use crate::message::{Inventory, Message, MAX_HEADERS};
+use crate::peer::PeerId;
+use crate::Error;
+use bitcoin_hashes::sha256::Hash as Sha256;
+use bloc_core::block::{Block, BlockHeader};
+use bloc_core::blockchain::BlockChain;
+use bloc_core::validation::HeaderState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// most blocks requested from one peer at a time
+pub const BLOCK_WINDOW: usize = 16;
+/// time a peer has to deliver a requested block before another is asked
+pub const BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// headers-first download of the blocks the chain misses: the headers are
+/// fetched from one peer and checked for linkage and proof of work, then
+/// their blocks are requested in windows from every peer at once and handed
+/// back in chain order; what was fetched survives the loss of a peer, the
+/// sync picks up from the last header with the next one
+#[derive(Debug, Default)]
+pub struct BlockSync {
+    /// hashes of the headers whose block is not connected yet, in chain
+    /// order
+    pending: VecDeque<Sha256>,
+    /// the same hashes, to look them up without a scan
+    pending_hashes: HashSet<Sha256>,
+    /// the headers after the last pending one, or after the chain tip
+    header_state: Option<HeaderState>,
+    /// peer asked for headers
+    header_peer: Option<PeerId>,
+    /// blocks requested, by whom and when
+    in_flight: HashMap<Sha256, (PeerId, Instant)>,
+    /// blocks arrived ahead of their parent
+    received: HashMap<Sha256, Block>,
+}
+
+impl BlockSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// whether every header known was downloaded and no peer is asked for
+    /// more
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty() && self.header_peer.is_none()
+    }
+
+    /// headers waiting for their block
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// ask `peer` for the headers after what is known, unless another peer
+    /// is already asked
+    pub fn start(&mut self, chain: &BlockChain, peer: PeerId) -> Option<Message> {
+        if self.header_peer.is_some() {
+            return None;
+        }
+        self.header_peer = Some(peer);
+        Some(self.get_headers(chain))
+    }
+
+    fn get_headers(&self, chain: &BlockChain) -> Message {
+        let mut locator = chain.locator();
+        if let Some(state) = self.header_state.as_ref() {
+            if !self.pending.is_empty() {
+                locator.insert(0, state.tip);
+            }
+        }
+        Message::GetHeaders {
+            locator,
+            stop: None,
+        }
+    }
+
+    /// check the `headers` sent by `peer` and queue their blocks, the
+    /// message asking for the next batch if there may be one; an error
+    /// means the peer sent headers that are not a valid chain
+    pub fn on_headers(
+        &mut self,
+        chain: &BlockChain,
+        peer: PeerId,
+        headers: Vec<BlockHeader>,
+    ) -> Result<Option<Message>, Error> {
+        if self.header_peer == Some(peer) {
+            self.header_peer = None;
+        }
+        let full = headers.len() == MAX_HEADERS;
+        let mut headers = headers
+            .into_iter()
+            .skip_while(|header| chain.tree.contains(&header.hash()))
+            .peekable();
+        let first = match headers.peek() {
+            Some(first) => first,
+            None => return Ok(None),
+        };
+        let params = chain.params();
+        let mut state = match self.header_state.take() {
+            Some(state) if state.tip == first.prev_hash => state,
+            state => {
+                let fork = chain.tree.get(&first.prev_hash).filter(|node| {
+                    chain
+                        .chain
+                        .get(node.height as usize)
+                        .map(|block| block.hash)
+                        == Some(first.prev_hash)
+                });
+                let on_pending = self.pending_hashes.contains(&first.prev_hash);
+                match fork {
+                    // another branch of the pending headers, kept out
+                    None if on_pending => {
+                        self.header_state = state;
+                        log::debug!("peer {} sent headers of another branch", peer);
+                        return Ok(None);
+                    }
+                    Some(node) => {
+                        // the headers fork from the active chain, what was
+                        // pending from another branch is dropped
+                        self.pending.clear();
+                        self.pending_hashes.clear();
+                        HeaderState::from_blocks(&params, &chain.chain[..=node.height as usize])?
+                    }
+                    None => {
+                        self.header_state = state;
+                        return Err(Error::Protocol(format!(
+                            "headers build on unknown block {}",
+                            first.prev_hash
+                        )));
+                    }
+                }
+            }
+        };
+        let mut result = Ok(());
+        for header in headers {
+            if let Err(e) = state.connect_header(&params, &header) {
+                result = Err(Error::Ledger(e));
+                break;
+            }
+            let hash = header.hash();
+            self.pending_hashes.insert(hash);
+            self.pending.push_back(hash);
+        }
+        self.header_state = Some(state);
+        result?;
+        if full {
+            self.header_peer = Some(peer);
+            return Ok(Some(self.get_headers(chain)));
+        }
+        Ok(None)
+    }
+
+    /// the `GetData` to send for the next blocks to download, each of
+    /// `peers` asked for up to `BLOCK_WINDOW` of them at once; requests
+    /// older than `BLOCK_TIMEOUT` are handed to another peer
+    pub fn requests(&mut self, peers: &[PeerId], now: Instant) -> Vec<(PeerId, Message)> {
+        self.in_flight
+            .retain(|_, (_, asked)| now.duration_since(*asked) < BLOCK_TIMEOUT);
+        let mut load: HashMap<PeerId, usize> = peers.iter().map(|peer| (*peer, 0)).collect();
+        for (peer, _) in self.in_flight.values() {
+            if let Some(count) = load.get_mut(peer) {
+                *count += 1;
+            }
+        }
+        let mut wanted: HashMap<PeerId, Vec<Inventory>> = HashMap::new();
+        // only a window per peer ahead of the chain, the blocks are
+        // connected in order
+        let lookahead = BLOCK_WINDOW * peers.len().max(1);
+        for hash in self.pending.iter().take(lookahead) {
+            let hash = *hash;
+            if self.in_flight.contains_key(&hash) || self.received.contains_key(&hash) {
+                continue;
+            }
+            let peer = match peers
+                .iter()
+                .filter(|peer| load[*peer] < BLOCK_WINDOW)
+                .min_by_key(|peer| load[*peer])
+            {
+                Some(peer) => *peer,
+                None => break,
+            };
+            *load.get_mut(&peer).expect("peer has a load") += 1;
+            self.in_flight.insert(hash, (peer, now));
+            wanted.entry(peer).or_default().push(Inventory::Block(hash));
+        }
+        let mut requests: Vec<(PeerId, Message)> = wanted
+            .into_iter()
+            .map(|(peer, items)| (peer, Message::GetData(items)))
+            .collect();
+        requests.sort_by_key(|(peer, _)| *peer);
+        requests
+    }
+
+    /// whether the block `hash` is one the sync is downloading
+    pub fn wants(&self, hash: &Sha256) -> bool {
+        self.in_flight.contains_key(hash) || self.pending_hashes.contains(hash)
+    }
+
+    /// keep `block` until its parent is connected
+    pub fn on_block(&mut self, block: Block) {
+        self.in_flight.remove(&block.hash);
+        self.received.insert(block.hash, block);
+    }
+
+    /// the blocks received in chain order from the first pending one on,
+    /// to connect to the chain
+    pub fn ready(&mut self) -> Vec<Block> {
+        let mut ready = Vec::new();
+        while let Some(hash) = self.pending.front() {
+            match self.received.remove(hash) {
+                Some(block) => {
+                    self.pending_hashes.remove(&block.hash);
+                    ready.push(block);
+                    self.pending.pop_front();
+                }
+                None => break,
+            }
+        }
+        ready
+    }
+
+    /// the block `hash` failed to connect, drop the headers from it on
+    pub fn block_failed(&mut self, hash: &Sha256) {
+        self.reset();
+        log::warn!("sync dropped the headers from block {}", hash);
+    }
+
+    /// the blocks `peer` does not have are asked to another one
+    pub fn not_found(&mut self, peer: PeerId, items: &[Inventory]) {
+        for item in items {
+            if let Inventory::Block(hash) = item {
+                if self.in_flight.get(hash).map(|(from, _)| *from) == Some(peer) {
+                    self.in_flight.remove(hash);
+                }
+            }
+        }
+    }
+
+    /// forget what was asked to `peer`, it disconnected
+    pub fn peer_gone(&mut self, peer: PeerId) {
+        self.in_flight.retain(|_, (from, _)| *from != peer);
+        if self.header_peer == Some(peer) {
+            self.header_peer = None;
+        }
+    }
+
+    // start over from the chain, the headers ahead of it are unusable
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.pending_hashes.clear();
+        self.header_state = None;
+        self.in_flight.clear();
+        self.received.clear();
+    }
+}
+
+#[cfg(test)]
+mod test_sync {
+    use super::*;
+    use bloc_core::constant::*;
+
+    fn source(blocks: usize) -> BlockChain {
+        let mut chain = BlockChain::new();
+        for _ in 0..blocks {
+            chain.mine_transaction(&KEY1.3).unwrap();
+        }
+        chain
+    }
+
+    // what an in-process peer with `chain` answers to `message`
+    fn answer(chain: &BlockChain, message: &Message) -> Vec<Message> {
+        match message {
+            Message::GetHeaders { locator, stop } => {
+                vec![Message::Headers(chain.headers_after(
+                    locator,
+                    stop.as_ref(),
+                    MAX_HEADERS,
+                ))]
+            }
+            Message::GetData(items) => items
+                .iter()
+                .map(|item| match item {
+                    Inventory::Block(hash) => match chain.tree.get(hash) {
+                        Some(node) => Message::Block(node.block.clone()),
+                        None => Message::NotFound(vec![*item]),
+                    },
+                    _ => Message::NotFound(vec![*item]),
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn headers(message: Vec<Message>) -> Vec<BlockHeader> {
+        match message.into_iter().next() {
+            Some(Message::Headers(headers)) => headers,
+            other => panic!("not headers: {:?}", other),
+        }
+    }
+
+    // deliver the answers of `peers` to `requests` and connect what is ready
+    fn deliver(
+        sync: &mut BlockSync,
+        chain: &mut BlockChain,
+        peers: &[(PeerId, &BlockChain)],
+        requests: Vec<(PeerId, Message)>,
+    ) {
+        for (to, request) in requests {
+            let (_, peer) = peers.iter().find(|(id, _)| *id == to).unwrap();
+            for message in answer(peer, &request) {
+                match message {
+                    Message::Block(block) => {
+                        assert!(sync.wants(&block.hash));
+                        sync.on_block(block);
+                    }
+                    Message::NotFound(items) => sync.not_found(to, &items),
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+        }
+        for block in sync.ready() {
+            chain.submit_block(block).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sync() {
+        let remote = source(40);
+        let mut chain = BlockChain::new();
+        let mut sync = BlockSync::new();
+        let request = sync.start(&chain, 1).unwrap();
+        assert_eq!(sync.start(&chain, 2), None);
+        let next = sync
+            .on_headers(&chain, 1, headers(answer(&remote, &request)))
+            .unwrap();
+        assert_eq!(next, None);
+        assert_eq!(sync.pending(), 40);
+
+        let peers = [(1, &remote), (2, &remote)];
+        let requests = sync.requests(&[1, 2], Instant::now());
+        // both peers download a window in parallel
+        assert_eq!(requests.len(), 2);
+        for (_, request) in requests.iter() {
+            assert!(matches!(request, Message::GetData(items) if items.len() == BLOCK_WINDOW));
+        }
+        deliver(&mut sync, &mut chain, &peers, requests);
+        assert_eq!(chain.len(), 2 * BLOCK_WINDOW + 1);
+        while !sync.is_idle() {
+            let requests = sync.requests(&[1, 2], Instant::now());
+            deliver(&mut sync, &mut chain, &peers, requests);
+        }
+        assert_eq!(chain.chain, remote.chain);
+        assert_eq!(chain.is_valid(), Ok(()));
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let remote = source(5);
+        let chain = BlockChain::new();
+        let mut sync = BlockSync::new();
+        let request = sync.start(&chain, 1).unwrap();
+        let mut headers = headers(answer(&remote, &request));
+        headers[3].nonce += 1;
+        assert!(matches!(
+            sync.on_headers(&chain, 1, headers.clone()),
+            Err(Error::Ledger(_))
+        ));
+        // the headers before the bad one are kept
+        assert_eq!(sync.pending(), 3);
+
+        let mut sync = BlockSync::new();
+        headers.remove(0);
+        assert!(matches!(
+            sync.on_headers(&chain, 1, headers),
+            Err(Error::Protocol(_))
+        ));
+        assert!(sync.is_idle());
+    }
+
+    #[test]
+    fn test_resume() {
+        let remote = source(20);
+        let mut chain = BlockChain::new();
+        let mut sync = BlockSync::new();
+        let request = sync.start(&chain, 1).unwrap();
+        sync.on_headers(&chain, 1, headers(answer(&remote, &request)))
+            .unwrap();
+
+        // the first peer leaves without answering, its blocks go to the
+        // second one
+        let now = Instant::now();
+        let mut requests = sync.requests(&[1, 2], now);
+        assert_eq!(requests.remove(0).0, 1);
+        sync.peer_gone(1);
+        deliver(&mut sync, &mut chain, &[(2, &remote)], requests);
+        assert_eq!(chain.len(), 1);
+        let requests = sync.requests(&[2], now);
+        assert!(matches!(
+            &requests[..],
+            [(2, Message::GetData(items))] if items[0] == Inventory::Block(remote.chain[1].hash)
+        ));
+        deliver(&mut sync, &mut chain, &[(2, &remote)], requests);
+        assert_eq!(chain.len(), BLOCK_WINDOW + 1);
+
+        // a peer too slow to answer is replaced once the request expires
+        assert_eq!(sync.requests(&[2], now).len(), 1);
+        assert!(sync.requests(&[2, 3], now).is_empty());
+        let later = now + BLOCK_TIMEOUT;
+        let requests = sync.requests(&[3], later);
+        deliver(&mut sync, &mut chain, &[(3, &remote)], requests);
+        assert_eq!(chain.chain, remote.chain);
+
+        // a new sync after a restart picks up from the chain
+        let remote = {
+            let mut remote = remote;
+            remote.mine_transaction(&KEY1.3).unwrap();
+            remote
+        };
+        let mut sync = BlockSync::new();
+        let request = sync.start(&chain, 4).unwrap();
+        let headers = headers(answer(&remote, &request));
+        assert_eq!(headers.len(), 1);
+        sync.on_headers(&chain, 4, headers).unwrap();
+        let requests = sync.requests(&[4], Instant::now());
+        deliver(&mut sync, &mut chain, &[(4, &remote)], requests);
+        assert_eq!(chain.len(), 22);
+    }
+}
@@ -1,13 +1,23 @@
-use crate::web::event_bus::{EventBus, Request};
-use crate::{block::*, blockchain::*, constant::*, secp256k1::*, transaction::*};
+use crate::event_bus::{EventBus, Request};
+use crate::miner_worker::{MinerRequest, MinerResponse, MinerWorker};
+use bloc_core::{
+    block::*,
+    blockchain::*,
+    constant::*,
+    secp256k1::*,
+    transaction::*,
+    wallet::{KdfParams, Keystore},
+    Address, Amount, Error,
+};
 use yew::html::Scope;
 use yew::prelude::*;
 //use yew_agent::{Agent, AgentLink, Dispatched, Dispatcher};
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
 use yew_agent::{Bridge, Bridged};
 use yew_router::prelude::*;
 
-use crate::web::{pages::home::*, pages::not_found::*};
+use crate::{pages::home::*, pages::not_found::*};
 
 #[function_component(AppWrap)]
 pub fn app_wrap() -> Html {
@@ -65,37 +75,24 @@ impl UserMeta {
         }
     }
 
-    pub fn push(&mut self, trans: &Transaction) {
-        let trans = trans.clone();
+    pub fn chain_mut(&mut self) -> &mut BlockChain {
         match self {
-            Self::Mint(mint) => mint.transactions.push(trans),
-            Self::Owner(owner) => owner.transactions.push(trans),
-            Self::User(user) => user.transactions.push(trans),
+            Self::Mint(mint) => &mut mint.chain,
+            Self::Owner(owner) => &mut owner.chain,
+            Self::User(user) => &mut user.chain,
         }
     }
 
-    // push block to user
-    pub fn push_bloc(&mut self, bloc: Block) {
-        match self {
-            Self::Mint(mint) => mint.chain.chain.push(bloc),
-            Self::Owner(owner) => owner.chain.chain.push(bloc),
-            Self::User(user) => user.chain.chain.push(bloc),
-        }
+    // queue a transaction in the mempool of the user
+    pub fn push(&mut self, trans: &Transaction) -> Result<(), Error> {
+        self.chain_mut().add_transaction(trans.clone())
     }
 
-    pub fn rm_trans_bloc(&mut self, bloc: &Block) {
-        match self {
-            Self::Mint(mint) => {
-                mint.transactions.retain(|trans| !bloc.data.contains(trans));
-            }
-            Self::Owner(owner) => {
-                owner
-                    .transactions
-                    .retain(|trans| !bloc.data.contains(trans));
-            }
-            Self::User(user) => {
-                user.transactions.retain(|trans| !bloc.data.contains(trans));
-            }
+    // push block to user
+    pub fn push_bloc(&mut self, bloc: Block) {
+        match self.chain_mut().submit_block(bloc) {
+            Ok(event) => log::debug!("block submitted: {:?}", event),
+            Err(e) => log::error!("block rejected: {}", e),
         }
     }
 
@@ -133,26 +130,42 @@ impl UserMeta {
         }
     }
 
-    pub fn get_secret_key(&self) -> String {
+    pub fn get_address(&self) -> String {
         match self {
             UserMeta::Mint(_) => "".into(),
-            UserMeta::Owner(owner) => owner.secret_key.to_hex(),
-            UserMeta::User(user) => user.secret_key.to_hex(),
+            user => Address::from(user.pub_key()).to_string(),
         }
     }
 
+    /// sign `trans` with the key of the user, unlocking its keystore with
+    /// `password`
+    pub fn sign(&self, password: &str, trans: &mut Transaction) -> Result<(), Error> {
+        let (name, keystore) = match self {
+            UserMeta::Mint(mint) => return Err(Error::UnknownKey(mint.name.clone())),
+            UserMeta::Owner(owner) => (&owner.name, &owner.keystore),
+            UserMeta::User(user) => (&user.name, &user.keystore),
+        };
+        let mut keystore = Keystore::from_json(keystore)?;
+        keystore.unlock(password)?;
+        keystore.sign_transaction(name, trans, &self.chain().chain_id)
+    }
+
     pub fn get_balance(&self) -> String {
         match self {
             UserMeta::Mint(_) => "".into(),
             UserMeta::Owner(owner) => {
-                let pubkey = &owner.public_key;
-                let balance = owner.chain.get_balance(pubkey);
-                format!("{:.3}", balance)
+                let address = Address::from(&owner.public_key);
+                match owner.chain.get_balance(&address) {
+                    Ok(balance) => format!("{:.3}", balance),
+                    Err(e) => e.to_string(),
+                }
             }
             UserMeta::User(user) => {
-                let pubkey = &user.public_key;
-                let balance = user.chain.get_balance(pubkey);
-                format!("{:.3}", balance)
+                let address = Address::from(&user.public_key);
+                match user.chain.get_balance(&address) {
+                    Ok(balance) => format!("{:.3}", balance),
+                    Err(e) => e.to_string(),
+                }
             }
         }
     }
@@ -161,20 +174,33 @@ impl UserMeta {
 pub struct App {
     pub users: Rc<Vec<UserMeta>>,
     _producer: Box<dyn Bridge<EventBus>>,
+    miner: Box<dyn Bridge<MinerWorker>>,
+    /// the blocks being mined in the worker by id
+    jobs: HashMap<u64, MiningJob>,
+    next_job: u64,
+    /// new users waiting for the MINT to mine a block rewarding them
+    funding: VecDeque<Address>,
+}
+
+/// a block a user races to mine in the worker
+pub struct MiningJob {
+    pub miner: PubKey,
+    /// headers hashed so far
+    pub attempts: u64,
 }
 
 pub enum Msg {
     UserMintCreate,
-    UserCreate(String),
+    UserCreate(String, String),
     UserCreated,
     UserCreateFailed(String),
-    TransferInitialUser(Transaction),
+    FundUser(Address),
     Transfer(Transaction),
-    MineTransaction(Transaction),
-    MinedTransaction((Block, PubKey)),
+    Mine,
+    Miner(MinerResponse),
     Transfered,
     TransferFailed,
-    InvalidTransaction,
+    InvalidTransaction(Error),
     InvalidUserOrTransaction,
 }
 
@@ -183,9 +209,7 @@ pub struct Mint {
     pub(crate) name: String,
     pub(crate) avatar: String,
     pub(crate) public_key: PubKey,
-    pub(crate) secret_key: SecKey,
-    pub(crate) balance: f64,
-    pub(crate) transactions: Vec<Transaction>,
+    pub(crate) balance: Amount,
     pub(crate) chain: BlockChain,
 }
 
@@ -194,9 +218,9 @@ pub struct Owner {
     pub name: String,
     pub avatar: String,
     pub public_key: PubKey,
-    pub secret_key: SecKey,
-    pub balance: f64,
-    pub transactions: Vec<Transaction>,
+    /// the keystore sealing the key of the owner, as json
+    pub keystore: String,
+    pub balance: Amount,
     pub chain: BlockChain,
 }
 
@@ -205,9 +229,9 @@ pub struct User {
     pub name: String,
     pub avatar: String,
     pub public_key: PubKey,
-    pub balance: f64,
-    pub(crate) secret_key: SecKey,
-    pub transactions: Vec<Transaction>,
+    pub balance: Amount,
+    /// the keystore sealing the key of the user, as json
+    pub(crate) keystore: String,
     pub chain: BlockChain,
 }
 
@@ -217,7 +241,7 @@ impl Component for App {
 
     fn create(ctx: &Context<Self>) -> Self {
         let callback = move |e: Request| match e {
-            Request::UserCreate(user) => Msg::UserCreate(user),
+            Request::UserCreate(user, password) => Msg::UserCreate(user, password),
             Request::Transfer(trans) => Msg::Transfer(trans),
             Request::Transfered => Msg::Transfered,
         };
@@ -226,31 +250,20 @@ impl Component for App {
             users: Rc::new(Vec::new()),
             //chain: BlockChain::new(),
             _producer: EventBus::bridge(ctx.link().callback(callback)),
+            miner: MinerWorker::bridge(ctx.link().callback(Msg::Miner)),
+            jobs: HashMap::new(),
+            next_job: 0,
+            funding: VecDeque::new(),
         }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            Msg::TransferInitialUser(trans) => {
-                log::info!("transfer initial money to user: {}", trans.to.to_hex());
-                Rc::make_mut(&mut self.users)
-                    .iter_mut()
-                    .for_each(|user| match user {
-                        UserMeta::Owner(owner) => {
-                            owner.transactions.push(trans.clone());
-                        }
-                        UserMeta::User(usr) => {
-                            usr.transactions.push(trans.clone());
-                        }
-                        _ => {}
-                    });
-                let block_transactions = vec![trans];
-                let block = Block::new(chrono::Utc::now(), block_transactions);
-                self.sync(&block, None);
-                ctx.link().send_message(Msg::Transfered);
-                //self.add_block(block);
-                //log::debug!("mine the transaction");
-                //ctx.link().send_message(Msg::MineTransaction(trans));
+            Msg::FundUser(address) => {
+                // the MINT mines a block rewarding the new user
+                log::info!("fund the new user: {}", address);
+                self.funding.push_back(address);
+                ctx.link().send_message(Msg::Mine);
             }
             Msg::Transfer(trans) => {
                 assert_ne!(trans.signature, MINT_KEY.3, "transaction must be signed");
@@ -268,74 +281,109 @@ impl Component for App {
                     }
                     Some(chain) => {
                         log::debug!("first validating the transaction");
-                        if !trans.is_valid(chain) {
+                        if let Err(e) = trans.is_valid(chain) {
                             // not valid
-                            ctx.link().send_message(Msg::InvalidTransaction);
+                            ctx.link().send_message(Msg::InvalidTransaction(e));
                             return true;
                         }
                     }
                 }
-                // FIXME use atomic to ensure that mining is cancelled after it's mined
                 Rc::make_mut(&mut self.users)
                     .iter_mut()
                     .for_each(|user| match user {
-                        /*
-                         *UserMeta::Mint(mint) => {
-                         *    mint.transactions.push(trans.clone());
-                         *}
-                         */
-                        UserMeta::Owner(owner) => {
-                            owner.transactions.push(trans.clone());
+                        UserMeta::Mint(_) => {}
+                        user => {
+                            if let Err(e) = user.push(&trans) {
+                                log::error!("transaction not queued: {}", e);
+                            }
                         }
-                        UserMeta::User(usr) => {
-                            usr.transactions.push(trans.clone());
-                        }
-                        _ => {}
                     });
                 log::debug!("mine the transaction");
-                ctx.link().send_message(Msg::MineTransaction(trans));
+                ctx.link().send_message(Msg::Mine);
             }
-            Msg::InvalidTransaction => {
-                log::info!("invalid transaction");
+            Msg::InvalidTransaction(e) => {
+                log::info!("invalid transaction: {}", e);
             }
             Msg::InvalidUserOrTransaction => {
                 log::info!("invalid transaction or user not found");
             }
-            Msg::MineTransaction(trans) => {
-                self.random_mine(trans, ctx.link());
+            Msg::Mine => {
+                self.cancel_mining();
+                self.start_mining();
+                return false;
             }
-            Msg::MinedTransaction((bloc, pub_key)) => {
-                self.sync(&bloc, Some(&pub_key));
+            Msg::Miner(MinerResponse::Progress(id, attempts)) => {
+                if let Some(job) = self.jobs.get_mut(&id) {
+                    log::trace!("job {} hashed {} headers", id, attempts);
+                    job.attempts = attempts;
+                }
+                return false;
+            }
+            Msg::Miner(MinerResponse::Mined(id, bloc)) => {
+                let job = match self.jobs.remove(&id) {
+                    Some(job) => job,
+                    // cancelled while its last chunk was hashed
+                    None => return false,
+                };
+                log::info!(
+                    "{} mined {} after {} hashes",
+                    job.miner.to_hex(),
+                    bloc.hash,
+                    job.attempts
+                );
+                // the others lost the race, they start over on the new tip
+                self.cancel_mining();
+                let reward = bloc.data.last().map(|coinbase| coinbase.to);
+                if reward.is_some() && self.funding.front() == reward.as_ref() {
+                    self.funding.pop_front();
+                }
+                self.sync(&bloc, None);
+                self.start_mining();
                 ctx.link().send_message(Msg::Transfered);
             }
+            Msg::Miner(MinerResponse::Cancelled(id)) => {
+                log::debug!("mining job {} cancelled", id);
+                return false;
+            }
+            Msg::Miner(MinerResponse::Failed(id, e)) => {
+                self.jobs.remove(&id);
+                log::error!("mining job {} failed: {}", id, e);
+                return false;
+            }
             Msg::Transfered => {
                 // here update transaction records
                 log::debug!("the transaction completed");
                 return true;
             }
-            Msg::UserMintCreate => {
-                if self.users.is_empty() {
-                    ctx.link().send_message(Msg::UserCreate("MINT".into()));
-                }
+            Msg::UserMintCreate if self.users.is_empty() => {
+                ctx.link()
+                    .send_message(Msg::UserCreate("MINT".into(), String::new()));
             }
-            Msg::UserCreate(user_name) => {
+            Msg::UserCreate(user_name, password) => {
                 if self.users.is_empty() {
                     log::info!("MINT USER CREATED");
+                    let chain = BlockChain::new();
                     let user = UserMeta::Mint(Mint {
                         name: "MINT".into(),
                         avatar: "assets/rust.png".into(),
-                        balance: f64::NAN,
-                        public_key: MINT_KEY.1,
-                        secret_key: MINT_KEY.0,
-                        transactions: Vec::new(),
-                        chain: BlockChain::new(),
+                        balance: Amount::ZERO,
+                        public_key: chain.mint_address,
+                        chain,
                     });
                     Rc::make_mut(&mut self.users).push(user);
                     return true;
                 }
                 log::trace!("received user name from agent: {}", user_name);
-                let (secret_key, public_key) = Secp256K1::new().gen_keypair();
-                let pubkey = public_key.clone();
+                let (public_key, keystore) = match seal_key(&user_name, &password) {
+                    Ok(sealed) => sealed,
+                    Err(e) => {
+                        log::error!("no key sealed for {}: {}", user_name, e);
+                        ctx.link()
+                            .send_message(Msg::UserCreateFailed(e.to_string()));
+                        return false;
+                    }
+                };
+                let pubkey = public_key;
                 // FIXME change to == when MINT created
                 let user = if self.users.len() == 1 {
                     log::info!("no user found, create Owner: {}", user_name);
@@ -343,10 +391,9 @@ impl Component for App {
                     UserMeta::Owner(Owner {
                         name: user_name,
                         avatar: "assets/rust.png".into(),
-                        balance: 0.0,
+                        balance: Amount::ZERO,
                         public_key,
-                        secret_key,
-                        transactions: Vec::new(),
+                        keystore,
                         chain,
                     })
                 } else {
@@ -355,10 +402,9 @@ impl Component for App {
                     UserMeta::User(User {
                         name: user_name,
                         avatar: "assets/rust-user.png".into(),
-                        balance: 0.0,
+                        balance: Amount::ZERO,
                         public_key,
-                        secret_key,
-                        transactions: Vec::new(),
+                        keystore,
                         chain,
                     })
                 };
@@ -368,8 +414,8 @@ impl Component for App {
                 assert!(mint.is_some(), "MINT not found");
                 let mint = mint.unwrap();
                 assert_ne!(&mint.public_key, &pubkey, "MINT equals");
-                let trans = Transaction::new(mint.public_key, pubkey, 10000.0, None);
-                ctx.link().send_message(Msg::TransferInitialUser(trans));
+                ctx.link()
+                    .send_message(Msg::FundUser(Address::from(&pubkey)));
                 return true;
             }
             Msg::UserCreated => {
@@ -456,62 +502,61 @@ impl App {
         Rc::make_mut(&mut self.users).iter_mut().for_each(|e| {
             if Some(e.pub_key()) != pubkey {
                 e.push_bloc(bloc.clone());
-                e.rm_trans_bloc(bloc);
             }
         });
     }
 
     // obtain the longest blockchain from all users
+    // the chain with the most work among the users
     pub fn longest_chain(&self) -> BlockChain {
         let mut ind = 0;
-        let mut len = 0;
+        let mut work = 0;
         for i in 0..self.users.len() {
-            let usr = &self.users[i];
-            if usr.chain_len() > len {
+            let usr_work = self.users[i].chain().chain_work().unwrap_or(0);
+            if usr_work > work {
                 ind = i;
-                len = usr.chain_len();
+                work = usr_work;
             }
         }
         self.users[ind].chain().clone()
     }
 
-    // randomly select a user to mine a transaction
-    pub fn random_mine(&mut self, trans: Transaction, link: &Scope<Self>) {
-        let now = chrono::Utc::now().timestamp_nanos();
-        let mut ind = now as usize % self.users.len();
-        if let UserMeta::Mint(_) = self.users[ind] {
-            ind += 1;
-            ind = ind % self.users.len();
-        }
-        match Rc::make_mut(&mut self.users)[ind] {
-            UserMeta::Owner(ref mut owner) => {
-                log::info!(
-                    "{} mining the transaction: {}-{}",
-                    owner.name,
-                    trans.from.to_hex(),
-                    trans.to.to_hex(),
-                );
-                let pub_key = &owner.public_key;
-                owner.chain.add_transaction(trans);
-                let bloc = owner.chain.mine_transaction(pub_key);
-                link.send_message(Msg::MinedTransaction((bloc, pub_key.clone())));
-            }
-            UserMeta::User(ref mut user) => {
-                log::info!(
-                    "{} mining the transaction: {}-{}",
-                    user.name,
-                    trans.from.to_hex(),
-                    trans.to.to_hex(),
-                );
-                let pub_key = &user.public_key;
-                user.chain.add_transaction(trans);
-                let bloc = user.chain.mine_transaction(pub_key);
-                link.send_message(Msg::MinedTransaction((bloc, pub_key.clone())));
+    // the MINT mines a block for the next user to fund while every other
+    // user mines the pending transactions, in the worker; whoever finds a
+    // block first wins the race
+    pub fn start_mining(&mut self) {
+        let mut templates = Vec::new();
+        for user in self.users.iter() {
+            let reward = match user {
+                UserMeta::Mint(_) => match self.funding.front() {
+                    Some(address) => *address,
+                    None => continue,
+                },
+                user if user.chain().mempool.is_empty() => continue,
+                user => Address::from(user.pub_key()),
+            };
+            match user.chain().block_template(&reward) {
+                Ok(template) => templates.push((*user.pub_key(), template.block)),
+                Err(e) => log::error!("no block to mine for {}: {}", user.get_name(), e),
             }
-            _ => {}
+        }
+        for (miner, bloc) in templates {
+            let id = self.next_job;
+            self.next_job += 1;
+            log::info!("{} mining job {}", miner.to_hex(), id);
+            self.jobs.insert(id, MiningJob { miner, attempts: 0 });
+            self.miner.send(MinerRequest::Mine(id, bloc));
         }
     }
 
+    // stop every block being mined
+    pub fn cancel_mining(&mut self) {
+        for id in self.jobs.keys() {
+            self.miner.send(MinerRequest::Cancel(*id));
+        }
+        self.jobs.clear();
+    }
+
     pub fn find(&self, pubkey: &PubKey) -> Option<&BlockChain> {
         for user in self.users.iter() {
             match user {
@@ -523,15 +568,11 @@ impl App {
                  *    return None;
                  *}
                  */
-                UserMeta::Owner(owner) => {
-                    if &owner.public_key == pubkey {
-                        return Some(&owner.chain);
-                    }
+                UserMeta::Owner(owner) if &owner.public_key == pubkey => {
+                    return Some(&owner.chain);
                 }
-                UserMeta::User(usr) => {
-                    if &usr.public_key == pubkey {
-                        return Some(&usr.chain);
-                    }
+                UserMeta::User(usr) if &usr.public_key == pubkey => {
+                    return Some(&usr.chain);
                 }
                 _ => {}
             }
@@ -540,21 +581,22 @@ impl App {
     }
 
     pub fn find_mint(&self) -> Option<&Mint> {
-        let users = self.users.as_ref();
-        let mut i = 0;
-        for ind in 0..users.len() {
-            if let UserMeta::Mint(_) = users[ind] {
-                i = ind;
-                break;
-            }
-        }
-        match self.users.as_ref()[i] {
-            UserMeta::Mint(ref mint) => Some(mint),
+        self.users.iter().find_map(|user| match user {
+            UserMeta::Mint(mint) => Some(mint),
             _ => None,
-        }
+        })
     }
 }
 
+// a new key named `name` in a keystore sealed with `password`, returned as
+// json next to its public key
+fn seal_key(name: &str, password: &str) -> Result<(PubKey, String), Error> {
+    let mut keystore = Keystore::create(password, KdfParams::default())?;
+    let public_key = keystore.generate(name)?;
+    keystore.lock();
+    Ok((public_key, keystore.to_json()?))
+}
+
 pub fn run_app() {
     wasm_logger::init(wasm_logger::Config::new(log::Level::Trace));
     yew::start_app::<App>();
@@ -0,0 +1,8 @@
+use bloc_web::miner_worker::MinerWorker;
+use yew_agent::Threaded;
+
+// entry point of `worker.js`, the web worker mining blocks off the page
+fn main() {
+    wasm_logger::init(wasm_logger::Config::new(log::Level::Debug));
+    MinerWorker::register();
+}
@@ -1,10 +1,11 @@
-use crate::transaction::Transaction;
+use bloc_core::transaction::Transaction;
 use std::collections::HashMap;
 use yew_agent::{Agent, AgentLink, HandlerId};
 
 #[derive(Clone)]
 pub enum Request {
-    UserCreate(String),
+    /// the name of the new user and the password sealing its key
+    UserCreate(String, String),
     Transfer(Transaction),
     Transfered,
 }
@@ -0,0 +1,7 @@
+// the `html!` macro of yew 0.19 expands components into unit statements
+#![allow(clippy::let_unit_value, clippy::unnecessary_operation)]
+
+pub mod app;
+pub mod event_bus;
+pub mod miner_worker;
+pub mod pages;
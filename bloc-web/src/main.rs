@@ -0,0 +1,3 @@
+fn main() {
+    bloc_web::app::run_app();
+}
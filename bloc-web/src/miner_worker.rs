@@ -0,0 +1,147 @@
+use bloc_core::block::Block;
+use bloc_core::miner;
+use gloo_timers::future::TimeoutFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use yew_agent::{Agent, AgentLink, HandlerId, Public};
+
+/// nonces hashed before the worker yields to its incoming messages
+const CHUNK: u64 = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum MinerRequest {
+    /// search the proof of work of a linked block, under the id of the job
+    Mine(u64, Block),
+    /// stop the job, another block won the race
+    Cancel(u64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum MinerResponse {
+    /// headers hashed by the job so far
+    Progress(u64, u64),
+    Mined(u64, Block),
+    Cancelled(u64),
+    Failed(u64, String),
+}
+
+struct Job {
+    id: u64,
+    who: HandlerId,
+    block: Block,
+    next_nonce: u64,
+    attempts: u64,
+}
+
+pub enum Msg {
+    Step,
+}
+
+/// mines the blocks of the simulated users off the page thread, a chunk of
+/// one job at a time so several jobs race and a cancel is heard in between
+pub struct MinerWorker {
+    link: AgentLink<Self>,
+    jobs: VecDeque<Job>,
+    stepping: bool,
+}
+
+impl Agent for MinerWorker {
+    type Reach = Public<Self>;
+    type Message = Msg;
+    type Input = MinerRequest;
+    type Output = MinerResponse;
+
+    fn create(link: AgentLink<Self>) -> Self {
+        Self {
+            link,
+            jobs: VecDeque::new(),
+            stepping: false,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) {
+        match msg {
+            Msg::Step => {
+                self.stepping = false;
+                if let Some(job) = self.jobs.pop_front() {
+                    self.step(job);
+                }
+                self.schedule();
+            }
+        }
+    }
+
+    fn handle_input(&mut self, msg: Self::Input, who: HandlerId) {
+        match msg {
+            MinerRequest::Mine(id, block) => {
+                log::debug!("worker mining job {}", id);
+                self.jobs.push_back(Job {
+                    id,
+                    who,
+                    block,
+                    next_nonce: 0,
+                    attempts: 0,
+                });
+                self.schedule();
+            }
+            MinerRequest::Cancel(id) => {
+                if let Some(index) = self.jobs.iter().position(|job| job.id == id) {
+                    self.jobs.remove(index);
+                    self.link.respond(who, MinerResponse::Cancelled(id));
+                }
+            }
+        }
+    }
+
+    fn name_of_resource() -> &'static str {
+        "worker.js"
+    }
+
+    fn resource_path_is_relative() -> bool {
+        true
+    }
+}
+
+impl MinerWorker {
+    // hash one chunk of `job`, putting it back in line unless it is over
+    fn step(&mut self, mut job: Job) {
+        let (id, who) = (job.id, job.who);
+        match miner::mine_nonces(&mut job.block, job.next_nonce, CHUNK) {
+            Ok(true) => {
+                job.attempts += job.block.nonce - job.next_nonce + 1;
+                self.link
+                    .respond(who, MinerResponse::Progress(id, job.attempts));
+                self.link.respond(who, MinerResponse::Mined(id, job.block));
+            }
+            Ok(false) => {
+                job.attempts += CHUNK;
+                match job.next_nonce.checked_add(CHUNK) {
+                    Some(next_nonce) => job.next_nonce = next_nonce,
+                    None => {
+                        miner::refresh_timestamp(&mut job.block);
+                        job.next_nonce = 0;
+                    }
+                }
+                self.link
+                    .respond(who, MinerResponse::Progress(id, job.attempts));
+                self.jobs.push_back(job);
+            }
+            Err(e) => self
+                .link
+                .respond(who, MinerResponse::Failed(id, e.to_string())),
+        }
+    }
+
+    // queue the next chunk behind the messages already waiting, a zero
+    // timeout lets the browser deliver them first
+    fn schedule(&mut self) {
+        if self.stepping || self.jobs.is_empty() {
+            return;
+        }
+        self.stepping = true;
+        self.link.send_future(async {
+            TimeoutFuture::new(0).await;
+            Msg::Step
+        });
+    }
+}
@@ -1,6 +1,6 @@
-use crate::web::app::*;
-use crate::web::event_bus::{EventBus, Request};
-use crate::{constant::*, secp256k1::*, transaction::*};
+use crate::app::*;
+use crate::event_bus::{EventBus, Request};
+use bloc_core::{constant::*, secp256k1::*, transaction::*, Address, Amount};
 use std::rc::Rc;
 use web_sys::{HtmlElement, HtmlInputElement, HtmlTextAreaElement};
 use yew::{html::*, prelude::*};
@@ -15,18 +15,20 @@ pub struct HomePageProps {
 pub struct Refs {
     sign_passed: bool,
     node_ref_user: NodeRef,
+    node_ref_user_password: NodeRef,
     node_ref_from: NodeRef,
     node_ref_to: NodeRef,
     node_ref_amount: NodeRef,
     node_ref_tips: NodeRef,
-    node_ref_secret: NodeRef,
+    node_ref_password: NodeRef,
     node_ref_signature: NodeRef,
     node_ref_help_user: NodeRef,
+    node_ref_help_user_password: NodeRef,
     node_ref_help_from: NodeRef,
     node_ref_help_to: NodeRef,
     node_ref_help_amount: NodeRef,
     node_ref_help_tips: NodeRef,
-    node_ref_help_secret: NodeRef,
+    node_ref_help_password: NodeRef,
     node_ref_label_signature: NodeRef,
     node_ref_no_user: NodeRef,
 }
@@ -34,18 +36,19 @@ pub struct Refs {
 pub struct HomePage {
     event_bus: Dispatcher<EventBus>,
     refs: Refs,
-    transaction: Option<(Transaction, SecKey)>,
+    transaction: Option<Transaction>,
 }
 
 pub enum Msg {
-    UserCreate(String),
+    UserCreate(String, String),
     UserDataCheck,
     InvalidOrNullUserName,
+    InvalidOrNullUserPassword,
     InvalidOrNullFromPubkey,
-    InvalidOrNullToPubkey,
+    InvalidOrNullToAddress,
     InvalidOrNullAmount,
     InvalidOrNullTips,
-    InvalidOrNullSecret,
+    InvalidOrNullPassword,
     TransferDataCheck,
     TransferSign,
     Transfer(Transaction),
@@ -65,18 +68,20 @@ impl Component for HomePage {
             refs: Refs {
                 sign_passed: false,
                 node_ref_user: NodeRef::default(),
+                node_ref_user_password: NodeRef::default(),
                 node_ref_from: NodeRef::default(),
                 node_ref_to: NodeRef::default(),
                 node_ref_amount: NodeRef::default(),
                 node_ref_tips: NodeRef::default(),
-                node_ref_secret: NodeRef::default(),
+                node_ref_password: NodeRef::default(),
                 node_ref_signature: NodeRef::default(),
                 node_ref_help_user: NodeRef::default(),
+                node_ref_help_user_password: NodeRef::default(),
                 node_ref_help_from: NodeRef::default(),
                 node_ref_help_to: NodeRef::default(),
                 node_ref_help_amount: NodeRef::default(),
                 node_ref_help_tips: NodeRef::default(),
-                node_ref_help_secret: NodeRef::default(),
+                node_ref_help_password: NodeRef::default(),
                 node_ref_label_signature: NodeRef::default(),
                 node_ref_no_user: NodeRef::default(),
             },
@@ -93,8 +98,20 @@ impl Component for HomePage {
                         ctx.link().send_message(Msg::InvalidOrNullUserName);
                         return false;
                     }
+                    let password = self
+                        .refs
+                        .node_ref_user_password
+                        .cast::<HtmlInputElement>()
+                        .map(|input| input.value())
+                        .unwrap_or_default();
+                    if password.is_empty() {
+                        ctx.link().send_message(Msg::InvalidOrNullUserPassword);
+                        return false;
+                    }
                     log::info!("got user name: {}", user_name);
-                    ctx.link().send_message(Msg::UserCreate(user_name));
+                    self.refs.clear_user_content();
+                    ctx.link()
+                        .send_message(Msg::UserCreate(user_name, password));
                     return false;
                 } else {
                     log::trace!("none user name");
@@ -108,9 +125,16 @@ impl Component for HomePage {
                     ele.set_class_name("help is-danger")
                 }
             }
-            Msg::UserCreate(user_name) => {
+            Msg::InvalidOrNullUserPassword => {
+                log::trace!("msg InvalidOrNullUserPassword received");
+                if let Some(ele) = self.refs.node_ref_help_user_password.cast::<HtmlElement>() {
+                    ele.set_class_name("help is-danger")
+                }
+            }
+            Msg::UserCreate(user_name, password) => {
                 log::trace!("msg UserCreate received");
-                self.event_bus.send(Request::UserCreate(user_name));
+                self.event_bus
+                    .send(Request::UserCreate(user_name, password));
                 return false;
             }
             Msg::TransferDataCheck => {
@@ -124,13 +148,13 @@ impl Component for HomePage {
                         return true;
                     }
 
-                    // check and get desitination's pubkey
+                    // check and get desitination's address
                     if let Some(input_transfer_to) =
                         self.refs.node_ref_to.cast::<HtmlInputElement>()
                     {
-                        let pubkey_to = input_transfer_to.value().trim().to_owned();
-                        if pubkey_to.is_empty() {
-                            ctx.link().send_message(Msg::InvalidOrNullToPubkey);
+                        let address_to = input_transfer_to.value().trim().to_owned();
+                        if address_to.is_empty() {
+                            ctx.link().send_message(Msg::InvalidOrNullToAddress);
                             return true;
                         }
 
@@ -138,54 +162,51 @@ impl Component for HomePage {
                         if let Some(input_transfer_amount) =
                             self.refs.node_ref_amount.cast::<HtmlInputElement>()
                         {
-                            let amount = input_transfer_amount.value_as_number();
-                            if amount == 0.0 || amount.is_nan() {
-                                ctx.link().send_message(Msg::InvalidOrNullAmount);
-                                return true;
-                            }
+                            let amount = match input_transfer_amount.value().parse::<Amount>() {
+                                Ok(amount) if !amount.is_zero() => amount,
+                                _ => {
+                                    ctx.link().send_message(Msg::InvalidOrNullAmount);
+                                    return true;
+                                }
+                            };
 
                             // check and get tips
                             if let Some(input_transfer_tips) =
                                 self.refs.node_ref_tips.cast::<HtmlInputElement>()
                             {
-                                let tips = input_transfer_tips.value_as_number();
-                                if tips.is_nan() {
-                                    ctx.link().send_message(Msg::InvalidOrNullTips);
-                                    return true;
-                                }
+                                let tips = match input_transfer_tips.value().parse::<Amount>() {
+                                    Ok(tips) => tips,
+                                    Err(_) => {
+                                        ctx.link().send_message(Msg::InvalidOrNullTips);
+                                        return true;
+                                    }
+                                };
                                 let from = PubKey::from_hex(&pubkey_from);
                                 if let Ok(from) = from {
-                                    if let Ok(to) = PubKey::from_hex(&pubkey_to) {
-                                        if let Some(input_transfer_seckey) =
-                                            self.refs.node_ref_secret.cast::<HtmlInputElement>()
-                                        {
-                                            let hex_sec_key =
-                                                input_transfer_seckey.value().trim().to_owned();
-                                            let sec_key = SecKey::from_hex(&hex_sec_key);
-                                            if sec_key.is_err() {
-                                                ctx.link().send_message(Msg::InvalidOrNullSecret);
+                                    if let Ok(to) = address_to.parse::<Address>() {
+                                        log::info!(
+                                            "from: {:?}, to: {:?}, amount: {}, tips: {}",
+                                            from.to_hex(),
+                                            to.to_string(),
+                                            amount,
+                                            tips
+                                        );
+                                        let nonce = match Self::sender(ctx, &from) {
+                                            Some(user) => user.chain().next_nonce(&from),
+                                            None => {
+                                                ctx.link()
+                                                    .send_message(Msg::InvalidOrNullFromPubkey);
                                                 return true;
                                             }
-                                            let sec_key = sec_key.unwrap();
-                                            log::info!(
-                                                "from: {:?}, to: {:?}, amount: {:?}, tips: {:?}",
-                                                from.to_hex(),
-                                                to.to_hex(),
-                                                amount,
-                                                tips
-                                            );
-                                            let transfer =
-                                                Transaction::new(from, to, amount, Some(tips));
-                                            self.transaction = Some((transfer, sec_key));
-                                            ctx.link().send_message(Msg::TransferSign);
-
-                                            return true;
-                                        } else {
-                                            ctx.link().send_message(Msg::InvalidOrNullSecret);
-                                            return true;
-                                        }
+                                        };
+                                        let transfer =
+                                            Transaction::new(from, to, amount, Some(tips), nonce);
+                                        self.transaction = Some(transfer);
+                                        ctx.link().send_message(Msg::TransferSign);
+
+                                        return true;
                                     } else {
-                                        ctx.link().send_message(Msg::InvalidOrNullToPubkey);
+                                        ctx.link().send_message(Msg::InvalidOrNullToAddress);
                                         return true;
                                     }
                                 } else {
@@ -201,7 +222,7 @@ impl Component for HomePage {
                             return true;
                         }
                     } else {
-                        ctx.link().send_message(Msg::InvalidOrNullToPubkey);
+                        ctx.link().send_message(Msg::InvalidOrNullToAddress);
                         return true;
                     }
                 } else {
@@ -216,8 +237,8 @@ impl Component for HomePage {
                     ele.set_class_name("help is-danger")
                 }
             }
-            Msg::InvalidOrNullToPubkey => {
-                log::trace!("msg InvalidOrNullToPubkey received");
+            Msg::InvalidOrNullToAddress => {
+                log::trace!("msg InvalidOrNullToAddress received");
                 if let Some(ele) = self.refs.node_ref_help_to.cast::<HtmlElement>() {
                     ele.set_class_name("help is-danger")
                 }
@@ -234,17 +255,39 @@ impl Component for HomePage {
                     ele.set_class_name("help is-danger")
                 }
             }
+            Msg::InvalidOrNullPassword => {
+                log::trace!("msg InvalidOrNullPassword received");
+                if let Some(ele) = self.refs.node_ref_help_password.cast::<HtmlElement>() {
+                    ele.set_class_name("help is-danger")
+                }
+            }
             Msg::TransferSign => {
                 if self.transaction.is_some() {
                     if self.refs.sign_passed {
                         log::debug!("already has a signature");
                         return false;
                     }
-                    // sign the Transaction
-                    let (mut trans, sec_key) = self.transaction.take().unwrap();
-                    let key_pair = KeyPair::from(&sec_key);
-                    trans.sign(&key_pair);
-                    self.transaction = Some((trans, sec_key));
+                    // sign the Transaction with the keystore of the sender
+                    let mut trans = self.transaction.take().unwrap();
+                    let password = self
+                        .refs
+                        .node_ref_password
+                        .cast::<HtmlInputElement>()
+                        .map(|input| input.value())
+                        .unwrap_or_default();
+                    let signed = match Self::sender(ctx, &trans.from) {
+                        Some(user) => user.sign(&password, &mut trans),
+                        None => {
+                            ctx.link().send_message(Msg::InvalidOrNullFromPubkey);
+                            return true;
+                        }
+                    };
+                    if let Err(e) = signed {
+                        log::info!("failed to sign the transaction: {}", e);
+                        ctx.link().send_message(Msg::InvalidOrNullPassword);
+                        return true;
+                    }
+                    self.transaction = Some(trans);
                     self.refs.sign_passed = true;
                     self.show_signature_controler();
                     log::trace!("msg Transfer Signed");
@@ -255,7 +298,7 @@ impl Component for HomePage {
             }
             Msg::TransferConfirm => {
                 assert!(self.transaction.is_some(), "transaction must not be null");
-                let trans = self.transaction.take().unwrap().0;
+                let trans = self.transaction.take().unwrap();
                 ctx.link().send_message(Msg::Transfer(trans));
             }
             Msg::Transfer(trans) => {
@@ -277,7 +320,6 @@ impl Component for HomePage {
                 self.refs.clear_input_content();
                 return true;
             }
-            _ => {}
         }
         false
     }
@@ -298,6 +340,7 @@ impl Component for HomePage {
         let this4 = self.refs.clone();
         let this4_link = ctx.link().clone();
         let this5 = self.refs.clone();
+        let this6 = self.refs.clone();
         let sign_passed = self.refs.sign_passed;
 
         html! {
@@ -305,7 +348,7 @@ impl Component for HomePage {
                 <aside class="aside-buttons buttons aside-border">
                     <div class="space-between" style="height:25px;">
                         <span class="title is-6">{ "Initials" }</span>
-                        <span class="balance">{ "10000.0" }</span>
+                        <span class="balance">{ format!( "{:.1}", *REWARD ) }</span>
                     </div>
                     <div class="space-between" style="height:25px;">
                         <span class="title is-6">{ "Reward" }</span>
@@ -320,6 +363,10 @@ impl Component for HomePage {
                                 <input class="input" type="text" onfocus={move |_| this0.clear_help_message("user" )} ref={self.refs.node_ref_user.clone()} id="user-name" name="user-name" value="" placeholder="your name here" />
                                 <p class="help no-display" ref={self.refs.node_ref_help_user.clone()} > {"invalid or deplicated user name"} </p>
                             </div>
+                            <div class="control mt-1">
+                                <input class="input" type="password" onfocus={move |_| this6.clear_help_message("user" )} ref={self.refs.node_ref_user_password.clone()} id="user-password" name="user-password" value="" placeholder="password sealing your key" />
+                                <p class="help no-display" ref={self.refs.node_ref_help_user_password.clone()} > {"a password is needed"} </p>
+                            </div>
                             <div class="control mt-1">
                                 <button type="submit" onclick={ move |_| link.clone().send_message(Msg::UserDataCheck) } class="button is-info is-small">{ "Create" }</button>
                             </div>
@@ -337,8 +384,8 @@ impl Component for HomePage {
                             </div>
                             <label for="to-user">{ "To" }</label>
                             <div class="control">
-                                <input class="input"  type="text" oninput={move |_| this1_link.send_message(Msg::TransferClean) } onfocus={move |_| this1.clear_help_message("transfer") } ref={self.refs.node_ref_to.clone()} id="public-key-to" name="public-key-to" placeholder="public key(hex)" />
-                                <p class="help no-display" ref={self.refs.node_ref_help_to.clone()} > {"66 long hex string"} </p>
+                                <input class="input"  type="text" oninput={move |_| this1_link.send_message(Msg::TransferClean) } onfocus={move |_| this1.clear_help_message("transfer") } ref={self.refs.node_ref_to.clone()} id="address-to" name="address-to" placeholder="address" />
+                                <p class="help no-display" ref={self.refs.node_ref_help_to.clone()} > {"mistyped or invalid address"} </p>
                             </div>
                             <label for="amount-user">{ "Amount" }</label>
                             <div class="control">
@@ -350,10 +397,10 @@ impl Component for HomePage {
                                 <input class="input"  type="number" oninput={move |_| this3_link.send_message(Msg::TransferClean) } onfocus={move |_| this3.clear_help_message("transfer" ) } ref={self.refs.node_ref_tips.clone()} id="tips" name="tips" min="0" step="any" />
                                 <p class="help no-display" ref={self.refs.node_ref_help_tips.clone()} > {"invalid tips"} </p>
                             </div>
-                            <label for="from-user">{ "SecretKey" }</label>
+                            <label for="password">{ "Password" }</label>
                             <div class="control">
-                                <input class="input"  type="text" oninput={move |_| this4_link.send_message(Msg::TransferClean) } onfocus={move |_| this4.clear_help_message("transfer" )} ref={self.refs.node_ref_secret.clone()} id="secret-key" name="secret-key" placeholder="secret key(hex)" />
-                                <p class="help no-display" ref={self.refs.node_ref_help_secret.clone()} > {"64 long hex string"} </p>
+                                <input class="input"  type="password" oninput={move |_| this4_link.send_message(Msg::TransferClean) } onfocus={move |_| this4.clear_help_message("transfer" )} ref={self.refs.node_ref_password.clone()} id="password" name="password" placeholder="password of the sender" />
+                                <p class="help no-display" ref={self.refs.node_ref_help_password.clone()} > {"wrong password"} </p>
                             </div>
                             <label for="signature" class="no-display" ref={self.refs.node_ref_label_signature.clone() } >{ "Signature" }</label>
                             <div class="control">
@@ -416,6 +463,11 @@ impl Component for HomePage {
 }
 
 impl HomePage {
+    // the user owning `from`, who signs the transfer on their chain
+    fn sender<'a>(ctx: &'a Context<Self>, from: &PubKey) -> Option<&'a UserMeta> {
+        ctx.props().users.iter().find(|usr| usr.pub_key() == from)
+    }
+
     pub fn view_users(&self, ctx: &Context<Self>) -> Html {
         let mut widgets = Vec::new();
         for usr in ctx.props().users.iter() {
@@ -467,16 +519,16 @@ impl HomePage {
                     <hr class="doted"/>
 
                     <div class="column no-padding" >
-                        <p class="auto-line-break"> { "Public Key: " }
-                            <code>{ user.get_public_key() }</code>
+                        <p class="auto-line-break"> { "Address: " }
+                            <code>{ user.get_address() }</code>
                         </p>
-                        </div>
+                    </div>
 
                     <div class="column no-padding" >
-                        <p class="auto-line-break">{ "Secret Key: " }
-                            <code>{ user.get_secret_key() }</code>
+                        <p class="auto-line-break"> { "Public Key: " }
+                            <code>{ user.get_public_key() }</code>
                         </p>
-                    </div>
+                        </div>
 
                 <div class="column no-padding">
                 {
@@ -556,8 +608,9 @@ impl HomePage {
                                     <td>{ format!("{}", trans.tips) }</td>
                                 </tr>
                             });
-                        } else if &trans.to == pubkey {
-                            let (name, is_mint, is_owner) = self.get_user_name(&trans.from, ctx);
+                        } else if trans.to.is_owned_by(pubkey) {
+                            let (name, is_mint, is_owner) =
+                                self.get_user_name(&Address::from(&trans.from), ctx);
                             let href = format!("#user-{}", name.replace(" ", "-"));
                             let td = if is_mint {
                                 html! {
@@ -613,8 +666,9 @@ impl HomePage {
                                     <td>{ format!("{}", trans.tips) }</td>
                                 </tr>
                             });
-                        } else if &trans.to == pubkey {
-                            let (name, is_mint, is_owner) = self.get_user_name(&trans.from, ctx);
+                        } else if trans.to.is_owned_by(pubkey) {
+                            let (name, is_mint, is_owner) =
+                                self.get_user_name(&Address::from(&trans.from), ctx);
                             let href = format!("#user-{}", name.replace(" ", "-"));
                             let href1 = format!("#user-{}", user.name.replace(" ", "-"));
                             let td = if is_mint {
@@ -647,7 +701,7 @@ impl HomePage {
         html! {{  for widgets.into_iter().by_ref() } }
     }
 
-    fn get_user_name(&self, pubkey: &PubKey, ctx: &Context<Self>) -> (String, bool, bool) {
+    fn get_user_name(&self, address: &Address, ctx: &Context<Self>) -> (String, bool, bool) {
         let mut name = "".into();
         let mut is_mint = false;
         let mut is_owner = false;
@@ -655,21 +709,21 @@ impl HomePage {
         for usr in users.iter() {
             match usr {
                 UserMeta::Mint(mint) => {
-                    if &mint.public_key == pubkey {
+                    if address.is_owned_by(&mint.public_key) {
                         name = usr.get_name();
                         is_mint = true;
                         break;
                     }
                 }
                 UserMeta::Owner(owner) => {
-                    if &owner.public_key == pubkey {
+                    if address.is_owned_by(&owner.public_key) {
                         name = usr.get_name();
                         is_owner = true;
                         break;
                     }
                 }
                 UserMeta::User(user) => {
-                    if &user.public_key == pubkey {
+                    if address.is_owned_by(&user.public_key) {
                         name = usr.get_name();
                         break;
                     }
@@ -682,7 +736,7 @@ impl HomePage {
     fn get_signature(&self) -> String {
         match self.transaction {
             None => "".into(),
-            Some(ref trans) => trans.0.signature.to_hex(),
+            Some(ref trans) => trans.signature.to_hex(),
         }
     }
 
@@ -714,6 +768,9 @@ impl Refs {
             if let Some(ele) = self.node_ref_help_user.cast::<HtmlElement>() {
                 ele.set_class_name("help no-display");
             }
+            if let Some(ele) = self.node_ref_help_user_password.cast::<HtmlElement>() {
+                ele.set_class_name("help no-display");
+            }
         } else if typ == "transfer" {
             if let Some(ele) = self.node_ref_help_from.cast::<HtmlElement>() {
                 ele.set_class_name("help no-display");
@@ -727,6 +784,9 @@ impl Refs {
             if let Some(ele) = self.node_ref_help_tips.cast::<HtmlElement>() {
                 ele.set_class_name("help no-display");
             }
+            if let Some(ele) = self.node_ref_help_password.cast::<HtmlElement>() {
+                ele.set_class_name("help no-display");
+            }
         }
     }
 
@@ -743,7 +803,16 @@ impl Refs {
         if let Some(ele) = self.node_ref_tips.cast::<HtmlInputElement>() {
             ele.set_value_as_number(0.0);
         }
-        if let Some(ele) = self.node_ref_secret.cast::<HtmlInputElement>() {
+        if let Some(ele) = self.node_ref_password.cast::<HtmlInputElement>() {
+            ele.set_value("");
+        }
+    }
+
+    fn clear_user_content(&self) {
+        if let Some(ele) = self.node_ref_user.cast::<HtmlInputElement>() {
+            ele.set_value("");
+        }
+        if let Some(ele) = self.node_ref_user_password.cast::<HtmlInputElement>() {
             ele.set_value("");
         }
     }
@@ -1,3 +0,0 @@
-pub mod app;
-pub mod event_bus;
-pub mod pages;